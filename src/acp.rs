@@ -1,11 +1,29 @@
 use serde_json::Value;
+use std::collections::HashMap;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Direction {
     EditorToAgent,
     AgentToEditor,
 }
 
+impl Direction {
+    /// Value for the `acp.direction` attribute.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Direction::EditorToAgent => "editor_to_agent",
+            Direction::AgentToEditor => "agent_to_editor",
+        }
+    }
+
+    pub fn reverse(self) -> Self {
+        match self {
+            Direction::EditorToAgent => Direction::AgentToEditor,
+            Direction::AgentToEditor => Direction::EditorToAgent,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum MessageType {
     Request {
@@ -74,6 +92,255 @@ pub fn extract_prompt_text(params: &Value) -> Option<String> {
     }
 }
 
+/// Size of a `session/prompt` without its content. Chars are Unicode scalar values.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PromptStats {
+    pub blocks: usize,
+    pub text_chars: usize,
+    /// `resource` and `resource_link` blocks.
+    pub resource_blocks: usize,
+    /// Text of embedded `resource` blocks; links carry no content.
+    pub resource_chars: usize,
+    pub images: usize,
+    pub audio: usize,
+}
+
+pub fn prompt_stats(params: &Value) -> PromptStats {
+    let mut stats = PromptStats::default();
+    let blocks = params.get("prompt").and_then(|p| p.as_array());
+    for block in blocks.into_iter().flatten() {
+        stats.blocks += 1;
+        match block.get("type").and_then(|t| t.as_str()) {
+            Some("text") => {
+                if let Some(text) = block.get("text").and_then(|t| t.as_str()) {
+                    stats.text_chars += text.chars().count();
+                }
+            }
+            Some("resource") => {
+                stats.resource_blocks += 1;
+                let text = block
+                    .get("resource")
+                    .and_then(|r| r.get("text"))
+                    .and_then(|t| t.as_str());
+                if let Some(text) = text {
+                    stats.resource_chars += text.chars().count();
+                }
+            }
+            Some("resource_link") => stats.resource_blocks += 1,
+            Some("image") => stats.images += 1,
+            Some("audio") => stats.audio += 1,
+            _ => {}
+        }
+    }
+    stats
+}
+
+/// An `image` or `audio` content block, described without its base64 payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MediaBlock<'a> {
+    /// `"image"` or `"audio"`.
+    pub kind: &'a str,
+    pub mime_type: Option<&'a str>,
+    /// Decoded size of `data`.
+    pub byte_size: usize,
+}
+
+pub fn media_block(block: &Value) -> Option<MediaBlock<'_>> {
+    let kind = block.get("type")?.as_str()?;
+    if kind != "image" && kind != "audio" {
+        return None;
+    }
+    let data = block.get("data").and_then(|d| d.as_str()).unwrap_or("");
+    Some(MediaBlock {
+        kind,
+        mime_type: block.get("mimeType").and_then(|m| m.as_str()),
+        byte_size: base64_decoded_len(data),
+    })
+}
+
+fn base64_decoded_len(data: &str) -> usize {
+    let padding = data.bytes().rev().take_while(|&b| b == b'=').count();
+    (data.len() / 4 * 3 + data.len() % 4 * 3 / 4).saturating_sub(padding)
+}
+
+pub fn extract_prompt_media(params: &Value) -> Vec<MediaBlock<'_>> {
+    let blocks = params.get("prompt").and_then(|p| p.as_array());
+    blocks
+        .into_iter()
+        .flatten()
+        .filter_map(media_block)
+        .collect()
+}
+
+/// Media inside a tool call's `content` blocks (`{"type":"content","content":{...}}`).
+pub fn extract_tool_call_media(params: &Value) -> Vec<MediaBlock<'_>> {
+    let content = params
+        .get("update")
+        .and_then(|u| u.get("content"))
+        .and_then(|c| c.as_array());
+    content
+        .into_iter()
+        .flatten()
+        .filter_map(|c| media_block(c.get("content")?))
+        .collect()
+}
+
+/// URIs of `resource` and `resource_link` blocks in a prompt, in order.
+pub fn extract_prompt_resource_uris(params: &Value) -> Vec<&str> {
+    let blocks = params.get("prompt").and_then(|p| p.as_array());
+    blocks
+        .into_iter()
+        .flatten()
+        .filter_map(|block| match block.get("type")?.as_str()? {
+            "resource" => block.get("resource")?.get("uri")?.as_str(),
+            "resource_link" => block.get("uri")?.as_str(),
+            _ => None,
+        })
+        .collect()
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+}
+
+/// Token usage from a `session/prompt` result. Agents disagree on where and how
+/// to report it, so look in `usage` and `_meta.usage` under the common spellings.
+pub fn extract_usage(result: &Value) -> Option<Usage> {
+    let candidates = [
+        result.get("usage"),
+        result.get("_meta").and_then(|m| m.get("usage")),
+    ];
+    candidates.into_iter().flatten().find_map(|usage| {
+        let tokens = |keys: &[&str]| keys.iter().find_map(|k| usage.get(*k)?.as_u64());
+        let parsed = Usage {
+            input_tokens: tokens(&["inputTokens", "input_tokens", "promptTokens"]),
+            output_tokens: tokens(&["outputTokens", "output_tokens", "completionTokens"]),
+        };
+        (parsed != Usage::default()).then_some(parsed)
+    })
+}
+
+/// Model that actually served a turn, from a `session/prompt` result or a session
+/// update. Looks at `_meta.model` (a string or an object with `id`/`modelId`),
+/// `_meta.modelId`, and top-level `modelId`/`model`.
+pub fn extract_response_model(value: &Value) -> Option<&str> {
+    let meta = value.get("_meta");
+    let candidates = [
+        meta.and_then(|m| m.get("model")),
+        meta.and_then(|m| m.get("model")).and_then(|m| m.get("id")),
+        meta.and_then(|m| m.get("model"))
+            .and_then(|m| m.get("modelId")),
+        meta.and_then(|m| m.get("modelId")),
+        value.get("modelId"),
+        value.get("model"),
+    ];
+    candidates
+        .into_iter()
+        .flatten()
+        .find_map(|v| v.as_str().filter(|s| !s.is_empty()))
+}
+
+/// `_meta` objects carried by a message, most specific first: the session update's
+/// own `_meta` before the params', and `error.data._meta` after the result's.
+pub fn message_meta(msg: &MessageType) -> Vec<&Value> {
+    let candidates = match msg {
+        MessageType::Request { params, .. } => vec![params.get("_meta")],
+        MessageType::Notification { params, .. } => vec![
+            params.get("update").and_then(|u| u.get("_meta")),
+            params.get("_meta"),
+        ],
+        MessageType::Response { result, error, .. } => vec![
+            result.as_ref().and_then(|r| r.get("_meta")),
+            error
+                .as_ref()
+                .and_then(|e| e.get("data"))
+                .and_then(|d| d.get("_meta")),
+        ],
+    };
+    candidates.into_iter().flatten().collect()
+}
+
+/// Value at a dotted path such as `cost.usd`; numeric segments index arrays.
+pub fn lookup_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |v, key| match v {
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        _ => v.get(key),
+    })
+}
+
+/// What the proxy knew when a message arrived, for checking [`PROTOCOL_RULES`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ProtocolContext<'a> {
+    /// Method of a request or notification.
+    pub method: Option<&'a str>,
+    /// `sessionUpdate` of a `session/update` notification.
+    pub update_type: Option<&'a str>,
+    /// An `initialize` response has been seen.
+    pub initialized: bool,
+    /// The connection was seen from its first line, so a missing handshake is real
+    /// rather than the proxy (or a partial transcript) starting mid-session.
+    pub from_start: bool,
+    /// The session has had at least one prompt.
+    pub prompted: bool,
+    /// The session's latest prompt is still awaiting its response.
+    pub turn_open: bool,
+    /// The referenced toolCallId already reported completed or failed this turn.
+    pub tool_finished: bool,
+    pub has_result: bool,
+    pub has_error: bool,
+}
+
+/// A protocol rule: `name` becomes the `acp.protocol.rule` value when `violated`.
+pub struct ProtocolRule {
+    pub name: &'static str,
+    pub violated: fn(&ProtocolContext) -> bool,
+}
+
+/// Protocol rules checked against every message. Observability only — a violation
+/// never stops a message from being forwarded.
+pub const PROTOCOL_RULES: &[ProtocolRule] = &[
+    ProtocolRule {
+        name: "prompt_before_initialize",
+        violated: |cx| cx.method == Some("session/prompt") && cx.from_start && !cx.initialized,
+    },
+    ProtocolRule {
+        name: "update_after_turn",
+        violated: |cx| {
+            cx.prompted
+                && !cx.turn_open
+                && matches!(
+                    cx.update_type,
+                    Some(
+                        "agent_message_chunk"
+                            | "agent_thought_chunk"
+                            | "tool_call"
+                            | "tool_call_update"
+                            | "plan"
+                    )
+                )
+        },
+    },
+    ProtocolRule {
+        name: "result_and_error",
+        violated: |cx| cx.has_result && cx.has_error,
+    },
+    ProtocolRule {
+        name: "update_for_finished_tool",
+        violated: |cx| cx.update_type == Some("tool_call_update") && cx.tool_finished,
+    },
+];
+
+/// Names of the rules the message violates.
+pub fn protocol_violations(cx: &ProtocolContext) -> Vec<&'static str> {
+    PROTOCOL_RULES
+        .iter()
+        .filter(|rule| (rule.violated)(cx))
+        .map(|rule| rule.name)
+        .collect()
+}
+
 pub fn extract_update_type(params: &Value) -> Option<&str> {
     params.get("update")?.get("sessionUpdate")?.as_str()
 }
@@ -90,6 +357,44 @@ pub fn extract_tool_call_title(params: &Value) -> Option<&str> {
     params.get("update")?.get("title")?.as_str()
 }
 
+/// Server and bare tool name of an MCP-backed tool call.
+#[derive(Debug, PartialEq, Eq)]
+pub struct McpTool<'a> {
+    pub server: &'a str,
+    pub tool: &'a str,
+}
+
+/// Recognize MCP-backed tool calls from the update's title or `_meta`:
+/// - `mcp__<server>__<tool>` (Claude Code)
+/// - `<tool> (<server> MCP Server)` (Gemini CLI)
+/// - `_meta.mcpServer`, with `_meta.mcpTool` or the title as the tool name
+pub fn detect_mcp_tool(params: &Value) -> Option<McpTool<'_>> {
+    let update = params.get("update")?;
+    let title = update.get("title").and_then(|t| t.as_str());
+    if let Some(title) = title {
+        if let Some((server, tool)) = title
+            .strip_prefix("mcp__")
+            .and_then(|rest| rest.split_once("__"))
+        {
+            if !server.is_empty() && !tool.is_empty() {
+                return Some(McpTool { server, tool });
+            }
+        }
+        if let Some((tool, server)) = title
+            .strip_suffix(" MCP Server)")
+            .and_then(|rest| rest.rsplit_once(" ("))
+        {
+            if !server.is_empty() && !tool.is_empty() {
+                return Some(McpTool { server, tool });
+            }
+        }
+    }
+    let meta = update.get("_meta")?;
+    let server = meta.get("mcpServer")?.as_str()?;
+    let tool = meta.get("mcpTool").and_then(|t| t.as_str()).or(title)?;
+    Some(McpTool { server, tool })
+}
+
 pub fn extract_tool_call_kind(params: &Value) -> Option<&str> {
     params.get("update")?.get("kind")?.as_str()
 }
@@ -98,6 +403,182 @@ pub fn extract_tool_call_status(params: &Value) -> Option<&str> {
     params.get("update")?.get("status")?.as_str()
 }
 
+/// Summary of a `plan` session update.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PlanSummary<'a> {
+    pub total: usize,
+    pub pending: usize,
+    pub in_progress: usize,
+    pub completed: usize,
+    /// Entry `content` strings, in plan order.
+    pub titles: Vec<&'a str>,
+}
+
+pub fn summarize_plan(params: &Value) -> Option<PlanSummary<'_>> {
+    let entries = params.get("update")?.get("entries")?.as_array()?;
+    let mut summary = PlanSummary {
+        total: entries.len(),
+        ..Default::default()
+    };
+    for entry in entries {
+        match entry.get("status").and_then(|s| s.as_str()) {
+            Some("pending") => summary.pending += 1,
+            Some("in_progress") => summary.in_progress += 1,
+            Some("completed") => summary.completed += 1,
+            _ => {}
+        }
+        if let Some(content) = entry.get("content").and_then(|c| c.as_str()) {
+            summary.titles.push(content);
+        }
+    }
+    Some(summary)
+}
+
+pub fn extract_available_command_names(params: &Value) -> Option<Vec<&str>> {
+    let commands = params.get("update")?.get("availableCommands")?.as_array()?;
+    Some(
+        commands
+            .iter()
+            .filter_map(|c| c.get("name")?.as_str())
+            .collect(),
+    )
+}
+
+pub fn extract_tool_call_locations(params: &Value) -> Option<&Vec<Value>> {
+    params.get("update")?.get("locations")?.as_array()
+}
+
+/// Distinct `type`s of a tool call's `content` blocks (`content`, `diff`, `terminal`), in order.
+pub fn extract_tool_call_content_types(params: &Value) -> Vec<&str> {
+    let mut types = Vec::new();
+    let content = params
+        .get("update")
+        .and_then(|u| u.get("content"))
+        .and_then(|c| c.as_array());
+    for block in content.into_iter().flatten() {
+        if let Some(t) = block.get("type").and_then(|t| t.as_str()) {
+            if !types.contains(&t) {
+                types.push(t);
+            }
+        }
+    }
+    types
+}
+
+/// Text of the update's `content` blocks wrapping a text content block, in order.
+pub fn extract_tool_call_content_text(params: &Value) -> Vec<&str> {
+    params
+        .get("update")
+        .and_then(|u| u.get("content"))
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+        .filter(|block| block.get("type").and_then(|t| t.as_str()) == Some("content"))
+        .filter_map(|block| block.get("content"))
+        .filter(|inner| inner.get("type").and_then(|t| t.as_str()) == Some("text"))
+        .filter_map(|inner| inner.get("text").and_then(|t| t.as_str()))
+        .collect()
+}
+
+/// A `diff` tool call content block. `old_text` is `None` for new files.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FileDiff<'a> {
+    pub path: &'a str,
+    pub old_text: Option<&'a str>,
+    pub new_text: &'a str,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DiffStat {
+    pub lines_added: usize,
+    pub lines_removed: usize,
+}
+
+impl FileDiff<'_> {
+    /// Lines only in the old text and lines only in the new text, compared as
+    /// multisets — cheap, order-insensitive, and exact for pure additions/removals.
+    fn changed_lines(&self) -> (Vec<&str>, Vec<&str>) {
+        let mut delta: HashMap<&str, isize> = HashMap::new();
+        let old_lines: Vec<&str> = self
+            .old_text
+            .map(|t| t.lines().collect())
+            .unwrap_or_default();
+        for line in &old_lines {
+            *delta.entry(line).or_default() -= 1;
+        }
+        for line in self.new_text.lines() {
+            *delta.entry(line).or_default() += 1;
+        }
+        let mut removed = Vec::new();
+        for line in old_lines {
+            let d = delta.get_mut(line).unwrap();
+            if *d < 0 {
+                removed.push(line);
+                *d += 1;
+            }
+        }
+        let mut added = Vec::new();
+        for line in self.new_text.lines() {
+            let d = delta.get_mut(line).unwrap();
+            if *d > 0 {
+                added.push(line);
+                *d -= 1;
+            }
+        }
+        (removed, added)
+    }
+
+    pub fn stat(&self) -> DiffStat {
+        let (removed, added) = self.changed_lines();
+        DiffStat {
+            lines_added: added.len(),
+            lines_removed: removed.len(),
+        }
+    }
+
+    /// Render as a minimal unified-style diff (no hunks or context lines).
+    pub fn render(&self) -> String {
+        let (removed, added) = self.changed_lines();
+        let old_path = if self.old_text.is_some() {
+            self.path
+        } else {
+            "/dev/null"
+        };
+        let mut out = format!("--- {old_path}\n+++ {}\n", self.path);
+        for line in removed {
+            out.push('-');
+            out.push_str(line);
+            out.push('\n');
+        }
+        for line in added {
+            out.push('+');
+            out.push_str(line);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// `diff` blocks in a tool call's `content`.
+pub fn extract_diffs(params: &Value) -> Vec<FileDiff<'_>> {
+    let content = params
+        .get("update")
+        .and_then(|u| u.get("content"))
+        .and_then(|c| c.as_array());
+    content
+        .into_iter()
+        .flatten()
+        .filter(|block| block.get("type").and_then(|t| t.as_str()) == Some("diff"))
+        .filter_map(|block| {
+            Some(FileDiff {
+                path: block.get("path")?.as_str()?,
+                old_text: block.get("oldText").and_then(|t| t.as_str()),
+                new_text: block.get("newText")?.as_str()?,
+            })
+        })
+        .collect()
+}
+
 pub fn extract_agent_info(result: &Value) -> Option<(&str, Option<&str>)> {
     let info = result.get("agentInfo")?;
     let name = info.get("name")?.as_str()?;
@@ -105,6 +586,24 @@ pub fn extract_agent_info(result: &Value) -> Option<(&str, Option<&str>)> {
     Some((name, version))
 }
 
+/// ACP protocol versions whose message shapes the extractors here are written for.
+pub const KNOWN_PROTOCOL_VERSIONS: &[i64] = &[1];
+
+/// Trace content that depends on those shapes, listed when an agent negotiates an
+/// unknown version.
+pub const VERSION_DEPENDENT_FEATURES: &[&str] = &[
+    "tool call spans",
+    "prompt and output content",
+    "token usage",
+    "stop reasons",
+    "session modes and models",
+];
+
+/// `protocolVersion` from `initialize` params (requested) or its result (negotiated).
+pub fn extract_protocol_version(value: &Value) -> Option<i64> {
+    value.get("protocolVersion")?.as_i64()
+}
+
 pub fn extract_client_info(params: &Value) -> Option<(&str, Option<&str>)> {
     let info = params.get("clientInfo")?;
     let name = info.get("name")?.as_str()?;
@@ -112,6 +611,180 @@ pub fn extract_client_info(params: &Value) -> Option<(&str, Option<&str>)> {
     Some((name, version))
 }
 
+/// What the client advertised in `initialize`; anything missing counts as unsupported.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ClientCapabilities {
+    pub fs_read_text_file: bool,
+    pub fs_write_text_file: bool,
+    pub terminal: bool,
+}
+
+pub fn extract_client_capabilities(params: &Value) -> ClientCapabilities {
+    let caps = params.get("clientCapabilities");
+    let flag = |value: Option<&Value>| value.and_then(|v| v.as_bool()).unwrap_or(false);
+    let fs = caps.and_then(|c| c.get("fs"));
+    ClientCapabilities {
+        fs_read_text_file: flag(fs.and_then(|f| f.get("readTextFile"))),
+        fs_write_text_file: flag(fs.and_then(|f| f.get("writeTextFile"))),
+        terminal: flag(caps.and_then(|c| c.get("terminal"))),
+    }
+}
+
+/// What the agent advertised in its `initialize` result; missing means unsupported.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AgentCapabilities {
+    pub load_session: bool,
+    pub prompt_image: bool,
+    pub prompt_audio: bool,
+    pub prompt_embedded_context: bool,
+}
+
+pub fn extract_agent_capabilities(result: &Value) -> AgentCapabilities {
+    let caps = result.get("agentCapabilities");
+    let flag = |value: Option<&Value>| value.and_then(|v| v.as_bool()).unwrap_or(false);
+    let prompt = caps.and_then(|c| c.get("promptCapabilities"));
+    AgentCapabilities {
+        load_session: flag(caps.and_then(|c| c.get("loadSession"))),
+        prompt_image: flag(prompt.and_then(|p| p.get("image"))),
+        prompt_audio: flag(prompt.and_then(|p| p.get("audio"))),
+        prompt_embedded_context: flag(prompt.and_then(|p| p.get("embeddedContext"))),
+    }
+}
+
+/// Ids of the `authMethods` advertised in the initialize result.
+pub fn extract_auth_method_ids(result: &Value) -> Option<Vec<&str>> {
+    let methods = result.get("authMethods")?.as_array()?;
+    Some(
+        methods
+            .iter()
+            .filter_map(|m| m.get("id")?.as_str())
+            .collect(),
+    )
+}
+
+/// Session state returned by `session/new` (and `session/load`).
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SessionSetup<'a> {
+    pub session_id: Option<&'a str>,
+    pub current_mode: Option<&'a str>,
+    /// `None` when the agent didn't return a `modes` object at all.
+    pub available_modes: Option<usize>,
+    pub current_model: Option<&'a str>,
+    pub available_models: Option<usize>,
+}
+
+pub fn extract_session_setup(result: &Value) -> SessionSetup<'_> {
+    let modes = result.get("modes");
+    let models = result.get("models");
+    let count = |obj: Option<&Value>, key: &str| Some(obj?.get(key)?.as_array()?.len());
+    SessionSetup {
+        session_id: result.get("sessionId").and_then(|s| s.as_str()),
+        current_mode: modes
+            .and_then(|m| m.get("currentModeId"))
+            .and_then(|m| m.as_str()),
+        available_modes: count(modes, "availableModes"),
+        current_model: models
+            .and_then(|m| m.get("currentModelId"))
+            .and_then(|m| m.as_str()),
+        available_models: count(models, "availableModels"),
+    }
+}
+
+pub fn extract_auth_method_id(params: &Value) -> Option<&str> {
+    params.get("methodId")?.as_str()
+}
+
+pub fn extract_mode_id(params: &Value) -> Option<&str> {
+    params.get("modeId")?.as_str()
+}
+
+pub fn extract_model_id(params: &Value) -> Option<&str> {
+    params.get("modelId")?.as_str()
+}
+
+pub fn extract_current_mode_id(params: &Value) -> Option<&str> {
+    params.get("update")?.get("currentModeId")?.as_str()
+}
+
+pub fn extract_permission_tool_call_id(params: &Value) -> Option<&str> {
+    params.get("toolCall")?.get("toolCallId")?.as_str()
+}
+
+pub fn extract_permission_tool_title(params: &Value) -> Option<&str> {
+    params.get("toolCall")?.get("title")?.as_str()
+}
+
+/// Offered permission options as `(optionId, kind)` pairs.
+pub fn extract_permission_options(params: &Value) -> Vec<(&str, &str)> {
+    params
+        .get("options")
+        .and_then(|o| o.as_array())
+        .map(|options| {
+            options
+                .iter()
+                .filter_map(|opt| {
+                    let id = opt.get("optionId")?.as_str()?;
+                    let kind = opt.get("kind").and_then(|k| k.as_str()).unwrap_or("");
+                    Some((id, kind))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Permission outcome from a `session/request_permission` result:
+/// `("selected", Some(optionId))` or `("cancelled", None)`.
+pub fn extract_permission_outcome(result: &Value) -> Option<(&str, Option<&str>)> {
+    let outcome = result.get("outcome")?;
+    let kind = outcome.get("outcome")?.as_str()?;
+    let option_id = outcome.get("optionId").and_then(|v| v.as_str());
+    Some((kind, option_id))
+}
+
+pub fn extract_terminal_id(value: &Value) -> Option<&str> {
+    value.get("terminalId")?.as_str()
+}
+
+pub fn extract_terminal_output(result: &Value) -> Option<&str> {
+    result.get("output")?.as_str()
+}
+
+/// Whether a `terminal/output` result says its output was cut short.
+pub fn extract_terminal_truncated(result: &Value) -> bool {
+    result
+        .get("truncated")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+/// Exit status from `terminal/wait_for_exit` (top level) or `terminal/output`
+/// (nested under `exitStatus`): `(exitCode, signal)`.
+pub fn extract_terminal_exit_status(result: &Value) -> (Option<i64>, Option<&str>) {
+    let status = result.get("exitStatus").unwrap_or(result);
+    (
+        status.get("exitCode").and_then(|v| v.as_i64()),
+        status.get("signal").and_then(|v| v.as_str()),
+    )
+}
+
+/// Readable `error.type` for a JSON-RPC error code; unknown codes are `_OTHER`, with
+/// the number left to `rpc.jsonrpc.error_code`.
+pub fn error_type_for_code(code: i64) -> &'static str {
+    match code {
+        -32700 => "parse_error",
+        -32600 => "invalid_request",
+        -32601 => "method_not_found",
+        -32602 => "invalid_params",
+        -32603 => "internal_error",
+        -32000 => "auth_required",
+        _ => "_OTHER",
+    }
+}
+
+pub fn extract_error_message(error: &Value) -> Option<&str> {
+    error.get("message")?.as_str()
+}
+
 pub fn extract_stop_reason(result: &Value) -> Option<&str> {
     result.get("stopReason")?.as_str()
 }
@@ -135,15 +808,88 @@ pub fn map_stop_reason_to_finish_reason(stop_reason: &str) -> &str {
     }
 }
 
+/// Stop reasons meaning the agent gave up before finishing the turn.
+pub fn is_truncation_stop_reason(stop_reason: &str) -> bool {
+    matches!(stop_reason, "max_tokens" | "max_turn_requests")
+}
+
+/// How an ACP method is traced. Client-side methods (the agent asking the
+/// editor to do something) are listed explicitly; everything else is `Other`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MethodClass {
+    /// `fs/*` — file access performed by the editor.
+    FileSystem,
+    /// `terminal/*` — command execution performed by the editor.
+    Terminal,
+    /// `session/request_permission` — user approval of a tool call.
+    Permission,
+    /// Agent-side protocol methods, extensions, and unknown methods.
+    Other,
+}
+
+pub fn classify_method(method: &str) -> MethodClass {
+    match method {
+        "fs/read_text_file" | "fs/write_text_file" => MethodClass::FileSystem,
+        "terminal/create"
+        | "terminal/output"
+        | "terminal/wait_for_exit"
+        | "terminal/kill"
+        | "terminal/release" => MethodClass::Terminal,
+        "session/request_permission" => MethodClass::Permission,
+        _ => MethodClass::Other,
+    }
+}
+
+/// The param identifying what a client-side method operates on, as
+/// `(attribute key, value)`: the file path for `fs/*`, the terminal id for `terminal/*`.
+pub fn extract_method_target<'a>(
+    method: &str,
+    params: &'a Value,
+) -> Option<(&'static str, &'a str)> {
+    let (field, key) = match classify_method(method) {
+        MethodClass::FileSystem => ("path", "acp.fs.path"),
+        MethodClass::Terminal => ("terminalId", "acp.terminal.id"),
+        _ => return None,
+    };
+    Some((key, params.get(field)?.as_str()?))
+}
+
+/// `line` (1-based start) and `limit` (max lines) of an `fs/read_text_file`;
+/// both absent means the whole file.
+pub fn extract_read_range(params: &Value) -> (Option<i64>, Option<i64>) {
+    let field = |name: &str| params.get(name).and_then(|v| v.as_i64());
+    (field("line"), field("limit"))
+}
+
+/// `content` of an `fs/write_text_file` request or `fs/read_text_file` result.
+pub fn extract_fs_content(value: &Value) -> Option<&str> {
+    value.get("content")?.as_str()
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TextSize {
+    pub bytes: usize,
+    pub chars: usize,
+    pub lines: usize,
+}
+
+pub fn text_size(text: &str) -> TextSize {
+    TextSize {
+        bytes: text.len(),
+        chars: text.chars().count(),
+        lines: text.lines().count(),
+    }
+}
+
+/// ACP reserves `_`-prefixed method names for custom extensions.
+pub fn is_extension_method(method: &str) -> bool {
+    method.starts_with('_')
+}
+
 pub fn is_fs_or_terminal_method(method: &str) -> bool {
     matches!(
-        method,
-        "fs/read_text_file"
-            | "fs/write_text_file"
-            | "terminal/create"
-            | "terminal/write"
-            | "terminal/resize"
-            | "terminal/release"
+        classify_method(method),
+        MethodClass::FileSystem | MethodClass::Terminal
     )
 }
 
@@ -225,6 +971,509 @@ mod tests {
         assert_eq!(map_tool_kind_to_type("unknown"), "extension");
     }
 
+    #[test]
+    fn plan_summary() {
+        let params: Value = serde_json::from_str(r#"{"sessionId":"s1","update":{"sessionUpdate":"plan","entries":[{"content":"Read code","priority":"high","status":"completed"},{"content":"Fix bug","priority":"high","status":"in_progress"},{"content":"Run tests","priority":"medium","status":"pending"},{"content":"Write docs","priority":"low","status":"pending"}]}}"#).unwrap();
+        let summary = summarize_plan(&params).unwrap();
+        assert_eq!(summary.total, 4);
+        assert_eq!(summary.pending, 2);
+        assert_eq!(summary.in_progress, 1);
+        assert_eq!(summary.completed, 1);
+        assert_eq!(
+            summary.titles,
+            vec!["Read code", "Fix bug", "Run tests", "Write docs"]
+        );
+
+        let no_plan: Value = serde_json::from_str(r#"{"sessionId":"s1","update":{}}"#).unwrap();
+        assert_eq!(summarize_plan(&no_plan), None);
+    }
+
+    #[test]
+    fn available_command_names() {
+        let params: Value = serde_json::from_str(r#"{"sessionId":"s1","update":{"sessionUpdate":"available_commands_update","availableCommands":[{"name":"web","description":"Search the web"},{"name":"test","description":"Run tests","input":{"hint":"filter"}}]}}"#).unwrap();
+        assert_eq!(
+            extract_available_command_names(&params),
+            Some(vec!["web", "test"])
+        );
+    }
+
+    #[test]
+    fn tool_call_locations_and_content_types() {
+        let params: Value = serde_json::from_str(r#"{"sessionId":"s1","update":{"sessionUpdate":"tool_call","toolCallId":"tc1","title":"Edit","kind":"edit","locations":[{"path":"/src/a.rs","line":3},{"path":"/src/b.rs"}],"content":[{"type":"diff","path":"/src/a.rs","oldText":"a","newText":"b"},{"type":"content","content":{"type":"text","text":"ok"}},{"type":"diff","path":"/src/b.rs","oldText":null,"newText":"c"}]}}"#).unwrap();
+        assert_eq!(extract_tool_call_locations(&params).map(Vec::len), Some(2));
+        assert_eq!(
+            extract_tool_call_content_types(&params),
+            vec!["diff", "content"]
+        );
+
+        let bare: Value = serde_json::from_str(r#"{"update":{"toolCallId":"tc1"}}"#).unwrap();
+        assert!(extract_tool_call_locations(&bare).is_none());
+        assert!(extract_tool_call_content_types(&bare).is_empty());
+    }
+
+    #[test]
+    fn diff_summary_multi_file() {
+        let params: Value = serde_json::from_str(r#"{"update":{"sessionUpdate":"tool_call_update","toolCallId":"tc1","content":[{"type":"diff","path":"/src/a.rs","oldText":"fn a() {}\nfn b() {}\n","newText":"fn a() {}\nfn b2() {}\nfn c() {}\n"},{"type":"content","content":{"type":"text","text":"done"}},{"type":"diff","path":"/src/b.rs","oldText":"x\ny\nz","newText":"x\nz"}]}}"#).unwrap();
+        let diffs = extract_diffs(&params);
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(
+            diffs[0].stat(),
+            DiffStat {
+                lines_added: 2,
+                lines_removed: 1
+            }
+        );
+        assert_eq!(
+            diffs[1].stat(),
+            DiffStat {
+                lines_added: 0,
+                lines_removed: 1
+            }
+        );
+        assert_eq!(
+            diffs[0].render(),
+            "--- /src/a.rs\n+++ /src/a.rs\n-fn b() {}\n+fn b2() {}\n+fn c() {}\n"
+        );
+    }
+
+    #[test]
+    fn diff_summary_new_file() {
+        let params: Value = serde_json::from_str(r#"{"update":{"content":[{"type":"diff","path":"/src/new.rs","oldText":null,"newText":"line 1\nline 1\nline 2\n"}]}}"#).unwrap();
+        let diffs = extract_diffs(&params);
+        assert_eq!(diffs[0].old_text, None);
+        assert_eq!(
+            diffs[0].stat(),
+            DiffStat {
+                lines_added: 3,
+                lines_removed: 0
+            }
+        );
+        assert!(diffs[0]
+            .render()
+            .starts_with("--- /dev/null\n+++ /src/new.rs\n"));
+    }
+
+    #[test]
+    fn extension_methods() {
+        assert!(is_extension_method("_zed/hint"));
+        assert!(is_extension_method("_meta"));
+        assert!(!is_extension_method("session/prompt"));
+        assert!(!is_extension_method(""));
+    }
+
+    #[test]
+    fn client_capabilities_extraction() {
+        let params: Value = serde_json::from_str(r#"{"protocolVersion":1,"clientCapabilities":{"fs":{"readTextFile":true,"writeTextFile":false},"terminal":true}}"#).unwrap();
+        assert_eq!(
+            extract_client_capabilities(&params),
+            ClientCapabilities {
+                fs_read_text_file: true,
+                fs_write_text_file: false,
+                terminal: true
+            }
+        );
+
+        let partial: Value =
+            serde_json::from_str(r#"{"clientCapabilities":{"fs":{"readTextFile":true}}}"#).unwrap();
+        assert_eq!(
+            extract_client_capabilities(&partial),
+            ClientCapabilities {
+                fs_read_text_file: true,
+                ..Default::default()
+            }
+        );
+        let none: Value = serde_json::from_str(r#"{"protocolVersion":1}"#).unwrap();
+        assert_eq!(
+            extract_client_capabilities(&none),
+            ClientCapabilities::default()
+        );
+    }
+
+    #[test]
+    fn agent_capabilities_extraction() {
+        let full: Value = serde_json::from_str(r#"{"protocolVersion":1,"agentCapabilities":{"loadSession":true,"promptCapabilities":{"image":true,"audio":true,"embeddedContext":true}}}"#).unwrap();
+        assert_eq!(
+            extract_agent_capabilities(&full),
+            AgentCapabilities {
+                load_session: true,
+                prompt_image: true,
+                prompt_audio: true,
+                prompt_embedded_context: true
+            }
+        );
+
+        let partial: Value = serde_json::from_str(
+            r#"{"agentCapabilities":{"promptCapabilities":{"embeddedContext":true}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            extract_agent_capabilities(&partial),
+            AgentCapabilities {
+                prompt_embedded_context: true,
+                ..Default::default()
+            }
+        );
+        let no_prompt: Value =
+            serde_json::from_str(r#"{"agentCapabilities":{"loadSession":true}}"#).unwrap();
+        assert_eq!(
+            extract_agent_capabilities(&no_prompt),
+            AgentCapabilities {
+                load_session: true,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn auth_method_ids_extraction() {
+        let result: Value = serde_json::from_str(r#"{"protocolVersion":1,"authMethods":[{"id":"oauth","name":"Log in"},{"id":"api-key","name":"API key","description":null}]}"#).unwrap();
+        assert_eq!(
+            extract_auth_method_ids(&result),
+            Some(vec!["oauth", "api-key"])
+        );
+        let empty: Value = serde_json::from_str(r#"{"authMethods":[]}"#).unwrap();
+        assert_eq!(extract_auth_method_ids(&empty), Some(vec![]));
+        let missing: Value = serde_json::from_str(r#"{"protocolVersion":1}"#).unwrap();
+        assert_eq!(extract_auth_method_ids(&missing), None);
+    }
+
+    #[test]
+    fn prompt_stats_by_block_type() {
+        let params: Value = serde_json::from_str(r#"{"sessionId":"s1","prompt":[
+            {"type":"text","text":"Fix the bug"},
+            {"type":"resource","resource":{"uri":"file:///src/main.rs","mimeType":"text/x-rust","text":"fn main() {}"}},
+            {"type":"resource_link","uri":"file:///README.md","name":"README.md"},
+            {"type":"image","mimeType":"image/png","data":"iVBORw0KGgo="},
+            {"type":"text","text":"héllo"}
+        ]}"#).unwrap();
+        assert_eq!(
+            prompt_stats(&params),
+            PromptStats {
+                blocks: 5,
+                text_chars: 16,
+                resource_blocks: 2,
+                resource_chars: 12,
+                images: 1,
+                audio: 0
+            }
+        );
+        let empty: Value = serde_json::from_str(r#"{"sessionId":"s1"}"#).unwrap();
+        assert_eq!(prompt_stats(&empty), PromptStats::default());
+    }
+
+    #[test]
+    fn media_blocks_report_size_not_data() {
+        let params: Value = serde_json::from_str(
+            r#"{"prompt":[
+            {"type":"text","text":"what is this"},
+            {"type":"image","mimeType":"image/png","data":"iVBORw0KGgo="},
+            {"type":"audio","mimeType":"audio/wav","data":"UklGRg=="}
+        ]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            extract_prompt_media(&params),
+            vec![
+                MediaBlock {
+                    kind: "image",
+                    mime_type: Some("image/png"),
+                    byte_size: 8
+                },
+                MediaBlock {
+                    kind: "audio",
+                    mime_type: Some("audio/wav"),
+                    byte_size: 4
+                }
+            ]
+        );
+
+        let tool: Value = serde_json::from_str(r#"{"update":{"sessionUpdate":"tool_call","content":[{"type":"content","content":{"type":"image","mimeType":"image/jpeg","data":"/9j/4A"}},{"type":"diff","path":"/a","newText":""}]}}"#).unwrap();
+        let media = extract_tool_call_media(&tool);
+        assert_eq!(media.len(), 1);
+        assert_eq!(media[0].mime_type, Some("image/jpeg"));
+        assert_eq!(media[0].byte_size, 4);
+    }
+
+    #[test]
+    fn prompt_resource_uris() {
+        let params: Value = serde_json::from_str(
+            r#"{"prompt":[
+            {"type":"text","text":"see"},
+            {"type":"resource","resource":{"uri":"file:///src/main.rs","text":"fn main() {}"}},
+            {"type":"resource_link","uri":"file:///README.md","name":"README.md"}
+        ]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            extract_prompt_resource_uris(&params),
+            vec!["file:///src/main.rs", "file:///README.md"]
+        );
+    }
+
+    #[test]
+    fn usage_extraction_locations() {
+        let top: Value = serde_json::from_str(
+            r#"{"stopReason":"end_turn","usage":{"inputTokens":120,"outputTokens":45}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            extract_usage(&top),
+            Some(Usage {
+                input_tokens: Some(120),
+                output_tokens: Some(45)
+            })
+        );
+        let meta: Value = serde_json::from_str(
+            r#"{"stopReason":"end_turn","_meta":{"usage":{"input_tokens":7}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            extract_usage(&meta),
+            Some(Usage {
+                input_tokens: Some(7),
+                output_tokens: None
+            })
+        );
+        let none: Value =
+            serde_json::from_str(r#"{"stopReason":"end_turn","_meta":{"usage":{}}}"#).unwrap();
+        assert_eq!(extract_usage(&none), None);
+    }
+
+    #[test]
+    fn session_setup_extraction() {
+        let result: Value = serde_json::from_str(r#"{"sessionId":"s1","modes":{"currentModeId":"ask","availableModes":[{"id":"ask","name":"Ask"},{"id":"code","name":"Code"}]},"models":{"currentModelId":"sonnet","availableModels":[{"modelId":"sonnet","name":"Sonnet"}]}}"#).unwrap();
+        assert_eq!(
+            extract_session_setup(&result),
+            SessionSetup {
+                session_id: Some("s1"),
+                current_mode: Some("ask"),
+                available_modes: Some(2),
+                current_model: Some("sonnet"),
+                available_models: Some(1)
+            }
+        );
+        let bare: Value = serde_json::from_str(r#"{"sessionId":"s2"}"#).unwrap();
+        assert_eq!(
+            extract_session_setup(&bare),
+            SessionSetup {
+                session_id: Some("s2"),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn error_types_for_codes() {
+        assert_eq!(error_type_for_code(-32700), "parse_error");
+        assert_eq!(error_type_for_code(-32600), "invalid_request");
+        assert_eq!(error_type_for_code(-32601), "method_not_found");
+        assert_eq!(error_type_for_code(-32602), "invalid_params");
+        assert_eq!(error_type_for_code(-32603), "internal_error");
+        assert_eq!(error_type_for_code(-32000), "auth_required");
+        assert_eq!(error_type_for_code(-32002), "_OTHER");
+        assert_eq!(error_type_for_code(-31999), "_OTHER");
+    }
+
+    #[test]
+    fn direction_attribute_values() {
+        assert_eq!(Direction::EditorToAgent.as_str(), "editor_to_agent");
+        assert_eq!(Direction::AgentToEditor.as_str(), "agent_to_editor");
+        assert_eq!(Direction::EditorToAgent.reverse(), Direction::AgentToEditor);
+    }
+
+    #[test]
+    fn read_range_extraction() {
+        let full: Value =
+            serde_json::from_str(r#"{"sessionId":"s1","path":"/src/lib.rs"}"#).unwrap();
+        assert_eq!(extract_read_range(&full), (None, None));
+        let ranged: Value =
+            serde_json::from_str(r#"{"sessionId":"s1","path":"/src/lib.rs","line":40,"limit":25}"#)
+                .unwrap();
+        assert_eq!(extract_read_range(&ranged), (Some(40), Some(25)));
+    }
+
+    #[test]
+    fn text_size_counts_bytes_and_chars() {
+        let params: Value =
+            serde_json::from_str(r#"{"path":"/a.txt","content":"héllo\nwörld 🌍\n"}"#).unwrap();
+        let content = extract_fs_content(&params).unwrap();
+        assert_eq!(
+            text_size(content),
+            TextSize {
+                bytes: 19,
+                chars: 14,
+                lines: 2
+            }
+        );
+        assert_eq!(text_size(""), TextSize::default());
+    }
+
+    #[test]
+    fn truncation_stop_reasons() {
+        assert!(is_truncation_stop_reason("max_tokens"));
+        assert!(is_truncation_stop_reason("max_turn_requests"));
+        assert!(!is_truncation_stop_reason("end_turn"));
+        assert!(!is_truncation_stop_reason("cancelled"));
+        assert!(!is_truncation_stop_reason("refusal"));
+    }
+
+    #[test]
+    fn tool_call_content_text_extraction() {
+        let params = serde_json::json!({"update": {"content": [
+            {"type": "content", "content": {"type": "text", "text": "one"}},
+            {"type": "diff", "path": "/a", "newText": "x"},
+            {"type": "content", "content": {"type": "image", "data": "AA=="}},
+            {"type": "content", "content": {"type": "text", "text": "two"}}
+        ]}});
+        assert_eq!(extract_tool_call_content_text(&params), vec!["one", "two"]);
+        assert!(extract_tool_call_content_text(&serde_json::json!({"update": {}})).is_empty());
+    }
+
+    #[test]
+    fn mcp_tool_detection() {
+        let detect = |update: Value| {
+            let params = serde_json::json!({ "update": update });
+            detect_mcp_tool(&params).map(|m| (m.server.to_string(), m.tool.to_string()))
+        };
+        let pair = |server: &str, tool: &str| Some((server.to_string(), tool.to_string()));
+        // Claude Code
+        assert_eq!(
+            detect(serde_json::json!({"title": "mcp__github__create_issue"})),
+            pair("github", "create_issue")
+        );
+        assert_eq!(
+            detect(serde_json::json!({"title": "mcp__my_server__list__all"})),
+            pair("my_server", "list__all")
+        );
+        // Gemini CLI
+        assert_eq!(
+            detect(serde_json::json!({"title": "search_docs (context7 MCP Server)"})),
+            pair("context7", "search_docs")
+        );
+        // _meta
+        assert_eq!(
+            detect(serde_json::json!({"title": "Search", "_meta": {"mcpServer": "docs"}})),
+            pair("docs", "Search")
+        );
+        assert_eq!(
+            detect(serde_json::json!({"_meta": {"mcpServer": "docs", "mcpTool": "query"}})),
+            pair("docs", "query")
+        );
+        assert_eq!(detect(serde_json::json!({"title": "Read file"})), None);
+        assert_eq!(detect(serde_json::json!({"title": "mcp__server"})), None);
+        assert_eq!(detect(serde_json::json!({"title": "mcp____tool"})), None);
+    }
+
+    #[test]
+    fn response_model_extraction() {
+        let model = |v: Value| extract_response_model(&v).map(|m| m.to_string());
+        assert_eq!(
+            model(serde_json::json!({"stopReason": "end_turn", "_meta": {"model": "opus"}})),
+            Some("opus".into())
+        );
+        assert_eq!(
+            model(serde_json::json!({"_meta": {"model": {"id": "gpt-5", "provider": "x"}}})),
+            Some("gpt-5".into())
+        );
+        assert_eq!(
+            model(serde_json::json!({"_meta": {"modelId": "sonnet"}})),
+            Some("sonnet".into())
+        );
+        assert_eq!(
+            model(serde_json::json!({"modelId": "gemini-2.5-pro"})),
+            Some("gemini-2.5-pro".into())
+        );
+        assert_eq!(model(serde_json::json!({"model": ""})), None);
+        assert_eq!(model(serde_json::json!({"stopReason": "end_turn"})), None);
+    }
+
+    #[test]
+    fn meta_lookup() {
+        let msg = parse(r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","_meta":{"requestId":"r1"},"update":{"sessionUpdate":"agent_message_chunk","_meta":{"cost":{"usd":0.25},"hits":[3,4]}}}}"#).unwrap();
+        let metas = message_meta(&msg);
+        assert_eq!(metas.len(), 2);
+        assert_eq!(
+            lookup_path(metas[0], "cost.usd"),
+            Some(&serde_json::json!(0.25))
+        );
+        assert_eq!(lookup_path(metas[0], "hits.1"), Some(&serde_json::json!(4)));
+        assert_eq!(lookup_path(metas[0], "hits.x"), None);
+        assert_eq!(
+            lookup_path(metas[1], "requestId"),
+            Some(&serde_json::json!("r1"))
+        );
+
+        let msg = parse(r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32603,"message":"x","data":{"_meta":{"trace":"t"}}}}"#).unwrap();
+        let metas = message_meta(&msg);
+        assert_eq!(
+            lookup_path(metas[0], "trace"),
+            Some(&serde_json::json!("t"))
+        );
+
+        let msg = parse(r#"{"jsonrpc":"2.0","id":1,"method":"session/new","params":{}}"#).unwrap();
+        assert!(message_meta(&msg).is_empty());
+    }
+
+    #[test]
+    fn protocol_rules() {
+        let ok = ProtocolContext {
+            initialized: true,
+            ..Default::default()
+        };
+        assert!(protocol_violations(&ok).is_empty());
+
+        let early_prompt = ProtocolContext {
+            method: Some("session/prompt"),
+            from_start: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            protocol_violations(&early_prompt),
+            vec!["prompt_before_initialize"]
+        );
+        // Attached mid-session: the handshake may simply predate the proxy.
+        let attached = ProtocolContext {
+            method: Some("session/prompt"),
+            ..Default::default()
+        };
+        assert!(protocol_violations(&attached).is_empty());
+
+        let late_chunk = ProtocolContext {
+            method: Some("session/update"),
+            update_type: Some("agent_message_chunk"),
+            initialized: true,
+            prompted: true,
+            ..Default::default()
+        };
+        assert_eq!(protocol_violations(&late_chunk), vec!["update_after_turn"]);
+        // Mode and command updates are legitimate between turns.
+        let mode = ProtocolContext {
+            update_type: Some("current_mode_update"),
+            ..late_chunk
+        };
+        assert!(protocol_violations(&mode).is_empty());
+
+        let both = ProtocolContext {
+            has_result: true,
+            has_error: true,
+            ..ok
+        };
+        assert_eq!(protocol_violations(&both), vec!["result_and_error"]);
+
+        let finished = ProtocolContext {
+            method: Some("session/update"),
+            update_type: Some("tool_call_update"),
+            prompted: true,
+            turn_open: true,
+            tool_finished: true,
+            ..ok
+        };
+        assert_eq!(
+            protocol_violations(&finished),
+            vec!["update_for_finished_tool"]
+        );
+    }
+
     #[test]
     fn agent_info_extraction() {
         let result: Value = serde_json::from_str(r#"{"protocolVersion":1,"agentInfo":{"name":"kiro","title":"Kiro","version":"1.25.0"}}"#).unwrap();
@@ -233,11 +1482,166 @@ mod tests {
         assert_eq!(version, Some("1.25.0"));
     }
 
+    #[test]
+    fn auth_method_id_extraction() {
+        let params: Value =
+            serde_json::from_str(r#"{"methodId":"oauth","credentials":{"token":"secret"}}"#)
+                .unwrap();
+        assert_eq!(extract_auth_method_id(&params), Some("oauth"));
+
+        let empty: Value = serde_json::from_str(r#"{}"#).unwrap();
+        assert_eq!(extract_auth_method_id(&empty), None);
+    }
+
+    #[test]
+    fn mode_id_extraction() {
+        let params: Value = serde_json::from_str(r#"{"sessionId":"s1","modeId":"code"}"#).unwrap();
+        assert_eq!(extract_mode_id(&params), Some("code"));
+
+        let update: Value = serde_json::from_str(r#"{"sessionId":"s1","update":{"sessionUpdate":"current_mode_update","currentModeId":"ask"}}"#).unwrap();
+        assert_eq!(extract_current_mode_id(&update), Some("ask"));
+    }
+
+    #[test]
+    fn model_id_extraction() {
+        let params: Value =
+            serde_json::from_str(r#"{"sessionId":"s1","modelId":"claude-sonnet-4"}"#).unwrap();
+        assert_eq!(extract_model_id(&params), Some("claude-sonnet-4"));
+    }
+
+    #[test]
+    fn permission_request_extraction() {
+        let params: Value = serde_json::from_str(r#"{"sessionId":"s1","toolCall":{"toolCallId":"tc1","title":"Write main.rs"},"options":[{"optionId":"allow","name":"Allow","kind":"allow_once"},{"optionId":"deny","name":"Deny","kind":"reject_once"}]}"#).unwrap();
+        assert_eq!(extract_permission_tool_call_id(&params), Some("tc1"));
+        assert_eq!(
+            extract_permission_tool_title(&params),
+            Some("Write main.rs")
+        );
+        assert_eq!(
+            extract_permission_options(&params),
+            vec![("allow", "allow_once"), ("deny", "reject_once")]
+        );
+    }
+
+    #[test]
+    fn permission_outcome_extraction() {
+        let selected: Value =
+            serde_json::from_str(r#"{"outcome":{"outcome":"selected","optionId":"allow"}}"#)
+                .unwrap();
+        assert_eq!(
+            extract_permission_outcome(&selected),
+            Some(("selected", Some("allow")))
+        );
+
+        let cancelled: Value =
+            serde_json::from_str(r#"{"outcome":{"outcome":"cancelled"}}"#).unwrap();
+        assert_eq!(
+            extract_permission_outcome(&cancelled),
+            Some(("cancelled", None))
+        );
+    }
+
+    #[test]
+    fn terminal_output_extraction() {
+        let params: Value =
+            serde_json::from_str(r#"{"sessionId":"s1","terminalId":"term-1"}"#).unwrap();
+        assert_eq!(extract_terminal_id(&params), Some("term-1"));
+
+        let result: Value =
+            serde_json::from_str(r#"{"output":"héllo\n","truncated":true}"#).unwrap();
+        assert_eq!(extract_terminal_output(&result).map(str::len), Some(7));
+        assert!(extract_terminal_truncated(&result));
+
+        let untruncated: Value = serde_json::from_str(r#"{"output":""}"#).unwrap();
+        assert!(!extract_terminal_truncated(&untruncated));
+        let not_truncated: Value =
+            serde_json::from_str(r#"{"output":"","truncated":false}"#).unwrap();
+        assert!(!extract_terminal_truncated(&not_truncated));
+    }
+
+    #[test]
+    fn terminal_exit_status_extraction() {
+        let exited: Value = serde_json::from_str(r#"{"exitCode":2,"signal":null}"#).unwrap();
+        assert_eq!(extract_terminal_exit_status(&exited), (Some(2), None));
+
+        let killed: Value =
+            serde_json::from_str(r#"{"exitCode":null,"signal":"SIGKILL"}"#).unwrap();
+        assert_eq!(
+            extract_terminal_exit_status(&killed),
+            (None, Some("SIGKILL"))
+        );
+
+        let output: Value =
+            serde_json::from_str(r#"{"output":"","truncated":false,"exitStatus":{"exitCode":0}}"#)
+                .unwrap();
+        assert_eq!(extract_terminal_exit_status(&output), (Some(0), None));
+    }
+
+    #[test]
+    fn error_message_extraction() {
+        let err: Value =
+            serde_json::from_str(r#"{"code":-32000,"message":"Authentication required"}"#).unwrap();
+        assert_eq!(extract_error_message(&err), Some("Authentication required"));
+    }
+
     #[test]
     fn fs_method_detection() {
         assert!(is_fs_or_terminal_method("fs/read_text_file"));
         assert!(is_fs_or_terminal_method("terminal/create"));
+        assert!(is_fs_or_terminal_method("terminal/output"));
+        assert!(is_fs_or_terminal_method("terminal/wait_for_exit"));
         assert!(!is_fs_or_terminal_method("session/prompt"));
+        assert!(!is_fs_or_terminal_method("session/request_permission"));
+    }
+
+    #[test]
+    fn method_classification_table() {
+        let table = [
+            // Client methods (agent → editor)
+            ("fs/read_text_file", MethodClass::FileSystem),
+            ("fs/write_text_file", MethodClass::FileSystem),
+            ("terminal/create", MethodClass::Terminal),
+            ("terminal/output", MethodClass::Terminal),
+            ("terminal/wait_for_exit", MethodClass::Terminal),
+            ("terminal/kill", MethodClass::Terminal),
+            ("terminal/release", MethodClass::Terminal),
+            ("session/request_permission", MethodClass::Permission),
+            // Agent methods (editor → agent)
+            ("initialize", MethodClass::Other),
+            ("authenticate", MethodClass::Other),
+            ("session/new", MethodClass::Other),
+            ("session/load", MethodClass::Other),
+            ("session/prompt", MethodClass::Other),
+            ("session/set_mode", MethodClass::Other),
+            ("session/set_model", MethodClass::Other),
+            // Not ACP methods
+            ("terminal/write", MethodClass::Other),
+            ("terminal/resize", MethodClass::Other),
+        ];
+        for (method, class) in table {
+            assert_eq!(classify_method(method), class, "{method}");
+        }
+    }
+
+    #[test]
+    fn method_target_extraction() {
+        let fs: Value =
+            serde_json::from_str(r#"{"sessionId":"s1","path":"/src/main.rs","line":10}"#).unwrap();
+        assert_eq!(
+            extract_method_target("fs/read_text_file", &fs),
+            Some(("acp.fs.path", "/src/main.rs"))
+        );
+
+        let term: Value =
+            serde_json::from_str(r#"{"sessionId":"s1","terminalId":"term-1"}"#).unwrap();
+        assert_eq!(
+            extract_method_target("terminal/kill", &term),
+            Some(("acp.terminal.id", "term-1"))
+        );
+
+        let create: Value = serde_json::from_str(r#"{"sessionId":"s1","command":"ls"}"#).unwrap();
+        assert_eq!(extract_method_target("terminal/create", &create), None);
+        assert_eq!(extract_method_target("session/prompt", &fs), None);
     }
 
     #[test]
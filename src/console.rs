@@ -0,0 +1,93 @@
+use async_trait::async_trait;
+use opentelemetry::trace::Status;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::metrics::data::{Histogram, ResourceMetrics, Sum};
+use opentelemetry_sdk::metrics::exporter::PushMetricExporter;
+use opentelemetry_sdk::metrics::Temporality;
+use opentelemetry_sdk::trace::{SpanData, SpanExporter};
+use std::fmt::Write as _;
+use std::io::Write as _;
+
+/// `--otlp-protocol stdout`: prints a human-readable summary of each span and metric
+/// for local debugging. Writes to stderr, since stdout carries the ACP stream.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConsoleExporter;
+
+fn format_span(span: &SpanData) -> String {
+    let duration = span
+        .end_time
+        .duration_since(span.start_time)
+        .unwrap_or_default();
+    let status = match &span.status {
+        Status::Unset => "unset".to_string(),
+        Status::Ok => "ok".to_string(),
+        Status::Error { description } => format!("error({description})"),
+    };
+    let mut out = format!(
+        "span {} trace={} span={} parent={} {:.3}ms status={status}\n",
+        span.name,
+        span.span_context.trace_id(),
+        span.span_context.span_id(),
+        span.parent_span_id,
+        duration.as_secs_f64() * 1000.0,
+    );
+    for kv in &span.attributes {
+        let _ = writeln!(out, "    {} = {}", kv.key, kv.value);
+    }
+    for event in span.events.iter() {
+        let _ = writeln!(out, "    event {}", event.name);
+    }
+    out
+}
+
+impl SpanExporter for ConsoleExporter {
+    fn export(
+        &mut self,
+        batch: Vec<SpanData>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = OTelSdkResult> + Send>> {
+        let mut stderr = std::io::stderr().lock();
+        for span in &batch {
+            let _ = stderr.write_all(format_span(span).as_bytes());
+        }
+        Box::pin(std::future::ready(Ok(())))
+    }
+}
+
+#[async_trait]
+impl PushMetricExporter for ConsoleExporter {
+    async fn export(&self, metrics: &mut ResourceMetrics) -> OTelSdkResult {
+        let mut out = String::new();
+        for metric in metrics.scope_metrics.iter().flat_map(|sm| &sm.metrics) {
+            let data = metric.data.as_any();
+            let summary = if let Some(h) = data.downcast_ref::<Histogram<f64>>() {
+                let count: u64 = h.data_points.iter().map(|p| p.count).sum();
+                let sum: f64 = h.data_points.iter().map(|p| p.sum).sum();
+                format!("count={count} sum={sum:.3}")
+            } else if let Some(h) = data.downcast_ref::<Histogram<u64>>() {
+                let count: u64 = h.data_points.iter().map(|p| p.count).sum();
+                let sum: u64 = h.data_points.iter().map(|p| p.sum).sum();
+                format!("count={count} sum={sum}")
+            } else if let Some(s) = data.downcast_ref::<Sum<u64>>() {
+                let total: u64 = s.data_points.iter().map(|p| p.value).sum();
+                format!("total={total}")
+            } else {
+                "(unsupported aggregation)".to_string()
+            };
+            let _ = writeln!(out, "metric {} {summary}", metric.name);
+        }
+        let _ = std::io::stderr().lock().write_all(out.as_bytes());
+        Ok(())
+    }
+
+    async fn force_flush(&self) -> OTelSdkResult {
+        Ok(())
+    }
+
+    fn shutdown(&self) -> OTelSdkResult {
+        Ok(())
+    }
+
+    fn temporality(&self) -> Temporality {
+        Temporality::Cumulative
+    }
+}
@@ -0,0 +1,229 @@
+use acp_traces::acp::{self, Direction};
+use acp_traces::driver::{Driver, Reply};
+use acp_traces::spans::SpanManager;
+use serde_json::{json, Value};
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncWrite};
+
+/// `acp-traces doctor` settings.
+pub struct Options<'a> {
+    /// `--session`: also open a session, then cancel it.
+    pub session: bool,
+    /// `--timeout`: how long to wait for each response.
+    pub timeout: Duration,
+    /// Sent as the session's `cwd`.
+    pub cwd: &'a Path,
+}
+
+/// `acp-traces doctor`: run the client half of the ACP handshake against the agent,
+/// tracing every line as if an editor had sent it, and print what the agent said
+/// about itself. Returns whether the agent answered everything it was asked.
+pub async fn run(
+    stdin: impl AsyncWrite + Unpin,
+    stdout: impl AsyncBufRead + Unpin,
+    options: &Options<'_>,
+    span_mgr: &mut SpanManager,
+) -> bool {
+    let mut driver = Driver::new(stdin, stdout);
+    let mut observe = |direction: Direction, line: &str| span_mgr.process_message(direction, line);
+    let mut report = Report::default();
+
+    let params = json!({
+        "protocolVersion": acp::KNOWN_PROTOCOL_VERSIONS.last(),
+        "clientCapabilities": {"fs": {"readTextFile": false, "writeTextFile": false}, "terminal": false},
+        "clientInfo": {"name": "acp-traces", "version": env!("CARGO_PKG_VERSION")},
+    });
+    let initialize = driver
+        .request("initialize", params, options.timeout, &mut observe)
+        .await;
+    report.initialize = Some(step(initialize));
+    if let Some(Step::Answered { result, .. }) = &report.initialize {
+        report.agent = acp::extract_agent_info(result)
+            .map(|(name, version)| (name.to_string(), version.map(str::to_string)));
+        report.protocol_version = acp::extract_protocol_version(result);
+        report.capabilities = capability_names(&acp::extract_agent_capabilities(result));
+        report.auth_methods = acp::extract_auth_method_ids(result)
+            .unwrap_or_default()
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+
+        if options.session {
+            let params = json!({"cwd": options.cwd, "mcpServers": []});
+            let new_session = step(
+                driver
+                    .request("session/new", params, options.timeout, &mut observe)
+                    .await,
+            );
+            if let Step::Answered { result, .. } = &new_session {
+                if let Some(id) = acp::extract_session_setup(result).session_id {
+                    report.session_id = Some(id.to_string());
+                    let params = json!({"sessionId": id});
+                    let cancelled = driver.notify("session/cancel", params, &mut observe).await;
+                    if let Err(e) = cancelled {
+                        tracing::warn!(error = %e, "failed to cancel the doctor session");
+                    }
+                }
+            }
+            report.new_session = Some(new_session);
+        }
+    }
+    report.noise = std::mem::take(&mut driver.noise);
+    print!("{report}");
+    report.healthy()
+}
+
+/// How one request went.
+enum Step {
+    Answered { result: Value, latency: Duration },
+    Rejected { error: Value, latency: Duration },
+    Failed(anyhow::Error),
+}
+
+fn step(reply: anyhow::Result<Reply>) -> Step {
+    match reply {
+        Ok(Reply {
+            outcome: Ok(result),
+            latency,
+        }) => Step::Answered { result, latency },
+        Ok(Reply {
+            outcome: Err(error),
+            latency,
+        }) => Step::Rejected { error, latency },
+        Err(e) => Step::Failed(e),
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Answered { latency, .. } => write!(f, "ok in {} ms", latency.as_millis()),
+            Self::Rejected { error, latency } => {
+                let code = error.get("code").and_then(Value::as_i64);
+                let message = acp::extract_error_message(error).unwrap_or("no message");
+                write!(f, "FAILED in {} ms: ", latency.as_millis())?;
+                match code {
+                    Some(code) => {
+                        write!(f, "{} ({code}): {message}", acp::error_type_for_code(code))
+                    }
+                    None => write!(f, "{message}"),
+                }
+            }
+            Self::Failed(e) => write!(f, "FAILED: {e:#}"),
+        }
+    }
+}
+
+/// Names of the agent capabilities that are switched on, in ACP spelling.
+fn capability_names(caps: &acp::AgentCapabilities) -> Vec<&'static str> {
+    [
+        (caps.load_session, "loadSession"),
+        (caps.prompt_image, "image"),
+        (caps.prompt_audio, "audio"),
+        (caps.prompt_embedded_context, "embeddedContext"),
+    ]
+    .into_iter()
+    .filter_map(|(on, name)| on.then_some(name))
+    .collect()
+}
+
+#[derive(Default)]
+struct Report {
+    initialize: Option<Step>,
+    agent: Option<(String, Option<String>)>,
+    protocol_version: Option<i64>,
+    capabilities: Vec<&'static str>,
+    auth_methods: Vec<String>,
+    new_session: Option<Step>,
+    session_id: Option<String>,
+    noise: Vec<String>,
+}
+
+impl Report {
+    fn healthy(&self) -> bool {
+        let ok =
+            |step: &Option<Step>| !matches!(step, Some(Step::Rejected { .. } | Step::Failed(_)));
+        matches!(self.initialize, Some(Step::Answered { .. })) && ok(&self.new_session)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |items: &[&str]| match items {
+            [] => "none".to_string(),
+            items => items.join(", "),
+        };
+        writeln!(f, "acp-traces doctor")?;
+        if let Some(step) = &self.initialize {
+            writeln!(f, "  initialize: {step}")?;
+        }
+        if let Some(Step::Answered { .. }) = &self.initialize {
+            match &self.agent {
+                Some((name, Some(version))) => writeln!(f, "  agent: {name} {version}")?,
+                Some((name, None)) => writeln!(f, "  agent: {name}")?,
+                None => writeln!(f, "  agent: not reported (no agentInfo)")?,
+            }
+            match self.protocol_version {
+                Some(v) if acp::KNOWN_PROTOCOL_VERSIONS.contains(&v) => {
+                    writeln!(f, "  protocol version: {v}")?
+                }
+                Some(v) => writeln!(
+                    f,
+                    "  protocol version: {v} (unknown to acp-traces; some spans may be incomplete)"
+                )?,
+                None => writeln!(f, "  protocol version: missing")?,
+            }
+            writeln!(f, "  capabilities: {}", list(&self.capabilities))?;
+            let auth: Vec<&str> = self.auth_methods.iter().map(String::as_str).collect();
+            writeln!(f, "  auth methods: {}", list(&auth))?;
+        }
+        if let Some(step) = &self.new_session {
+            write!(f, "  session/new: {step}")?;
+            if let Some(id) = &self.session_id {
+                write!(f, " (session {id}, cancelled)")?;
+            }
+            writeln!(f)?;
+        }
+        if let Some(first) = self.noise.first() {
+            writeln!(
+                f,
+                "  warning: {} stdout line(s) weren't JSON-RPC, first: {first:?}; \
+                 logs belong on stderr",
+                self.noise.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_lists_what_the_agent_advertised() {
+        let report = Report {
+            initialize: Some(Step::Answered {
+                result: Value::Null,
+                latency: Duration::from_millis(42),
+            }),
+            agent: Some(("kiro".to_string(), Some("1.25.0".to_string()))),
+            protocol_version: Some(1),
+            capabilities: vec!["loadSession", "image"],
+            auth_methods: Vec::new(),
+            new_session: Some(Step::Rejected {
+                error: json!({"code": -32000, "message": "Authentication required"}),
+                latency: Duration::from_millis(3),
+            }),
+            session_id: None,
+            noise: vec!["booting".to_string()],
+        };
+        assert_eq!(
+            report.to_string(),
+            "acp-traces doctor\n  initialize: ok in 42 ms\n  agent: kiro 1.25.0\n  protocol version: 1\n  capabilities: loadSession, image\n  auth methods: none\n  session/new: FAILED in 3 ms: auth_required (-32000): Authentication required\n  warning: 1 stdout line(s) weren't JSON-RPC, first: \"booting\"; logs belong on stderr\n"
+        );
+        assert!(!report.healthy());
+    }
+}
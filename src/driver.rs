@@ -0,0 +1,206 @@
+use crate::acp::{self, Direction, MessageType};
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, Lines};
+
+/// The client end of an ACP connection, for `doctor`: writes requests to the
+/// agent's stdin and reads its stdout until the matching response arrives. The
+/// proxy itself never originates messages; this is the one place that does.
+pub struct Driver<W, R> {
+    stdin: W,
+    stdout: Lines<R>,
+    next_id: u64,
+    /// Stdout lines that weren't JSON-RPC messages, in order.
+    pub noise: Vec<String>,
+}
+
+/// The response to one request.
+#[derive(Debug)]
+pub struct Reply {
+    /// The `result`, or the `error` object.
+    pub outcome: Result<Value, Value>,
+    /// From writing the request to reading the response.
+    pub latency: Duration,
+}
+
+impl<W: AsyncWrite + Unpin, R: AsyncBufRead + Unpin> Driver<W, R> {
+    pub fn new(stdin: W, stdout: R) -> Self {
+        Self {
+            stdin,
+            stdout: stdout.lines(),
+            next_id: 0,
+            noise: Vec::new(),
+        }
+    }
+
+    /// Send a `method` request and wait up to `timeout` for its response. Every line
+    /// either way goes to `observe`, so the exchange can be traced like a proxied
+    /// one. Requests from the agent meanwhile are refused; notifications are skipped.
+    pub async fn request(
+        &mut self,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+        observe: &mut impl FnMut(Direction, &str),
+    ) -> Result<Reply> {
+        self.next_id += 1;
+        let id = json!(self.next_id);
+        let started = Instant::now();
+        let request = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+        self.send(&request, observe).await?;
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let line = match tokio::time::timeout_at(deadline, self.stdout.next_line()).await {
+                Err(_) => {
+                    anyhow::bail!("no response to {method} within {}s", timeout.as_secs_f64())
+                }
+                Ok(Ok(Some(line))) => line,
+                Ok(Ok(None)) => {
+                    anyhow::bail!("the agent closed its stdout without answering {method}")
+                }
+                Ok(Err(e)) => return Err(e).context("failed to read the agent's stdout"),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            observe(Direction::AgentToEditor, &line);
+            match acp::parse(&line) {
+                Some(MessageType::Response {
+                    id: got,
+                    result,
+                    error,
+                }) if got == id => {
+                    return Ok(Reply {
+                        outcome: match error {
+                            Some(error) => Err(error),
+                            None => Ok(result.unwrap_or(Value::Null)),
+                        },
+                        latency: started.elapsed(),
+                    });
+                }
+                // No client capabilities were advertised, so nothing can be served.
+                Some(MessageType::Request { id, method, .. }) => {
+                    let refusal = json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {"code": -32601, "message": format!("{method} is not supported by acp-traces doctor")},
+                    });
+                    self.send(&refusal, observe).await?;
+                }
+                Some(_) => {}
+                None => self.noise.push(line),
+            }
+        }
+    }
+
+    /// Send a `method` notification.
+    pub async fn notify(
+        &mut self,
+        method: &str,
+        params: Value,
+        observe: &mut impl FnMut(Direction, &str),
+    ) -> Result<()> {
+        let notification = json!({"jsonrpc": "2.0", "method": method, "params": params});
+        self.send(&notification, observe).await
+    }
+
+    async fn send(
+        &mut self,
+        message: &Value,
+        observe: &mut impl FnMut(Direction, &str),
+    ) -> Result<()> {
+        let line = message.to_string();
+        observe(Direction::EditorToAgent, &line);
+        let stdin = &mut self.stdin;
+        let write = async {
+            stdin.write_all(format!("{line}\n").as_bytes()).await?;
+            stdin.flush().await
+        };
+        write.await.context("failed to write to the agent's stdin")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{duplex, BufReader};
+
+    #[tokio::test]
+    async fn replies_are_matched_by_id_past_noise_and_agent_requests() {
+        let (client, agent) = duplex(4096);
+        let (client_read, client_write) = tokio::io::split(client);
+        let (agent_read, mut agent_write) = tokio::io::split(agent);
+        let mut driver = Driver::new(client_write, BufReader::new(client_read));
+
+        let agent_side = tokio::spawn(async move {
+            let mut requests = BufReader::new(agent_read).lines();
+            let request = requests.next_line().await.unwrap().unwrap();
+            assert!(request.contains(r#""method":"initialize""#));
+            agent_write
+                .write_all(
+                    concat!(
+                        "starting up...\n",
+                        r#"{"jsonrpc":"2.0","method":"session/update","params":{}}"#,
+                        "\n",
+                        r#"{"jsonrpc":"2.0","id":"a1","method":"fs/read_text_file","params":{}}"#,
+                        "\n",
+                        r#"{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":1}}"#,
+                        "\n",
+                    )
+                    .as_bytes(),
+                )
+                .await
+                .unwrap();
+            let refusal = requests.next_line().await.unwrap().unwrap();
+            assert!(refusal.contains(r#""id":"a1""#) && refusal.contains("-32601"));
+            agent_write
+        });
+
+        let mut seen = Vec::new();
+        let mut observe = |direction: Direction, line: &str| seen.push((direction, line.len()));
+        let reply = driver
+            .request(
+                "initialize",
+                json!({}),
+                Duration::from_secs(5),
+                &mut observe,
+            )
+            .await
+            .unwrap();
+        assert_eq!(reply.outcome.unwrap()["protocolVersion"], 1);
+        assert_eq!(driver.noise, vec!["starting up...".to_string()]);
+        // The request, four agent lines, and the refusal.
+        assert_eq!(seen.len(), 6);
+        let _agent_write = agent_side.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn silence_and_hangups_are_readable_errors() {
+        let (client, agent) = duplex(4096);
+        let (client_read, client_write) = tokio::io::split(client);
+        let mut driver = Driver::new(client_write, BufReader::new(client_read));
+        let err = driver
+            .request(
+                "initialize",
+                json!({}),
+                Duration::from_millis(20),
+                &mut |_, _| {},
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "no response to initialize within 0.02s");
+
+        drop(agent);
+        let err = driver
+            .request(
+                "initialize",
+                json!({}),
+                Duration::from_secs(5),
+                &mut |_, _| {},
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "failed to write to the agent's stdin");
+    }
+}
@@ -0,0 +1,188 @@
+use crate::telemetry::{self, ExporterConfig, Signals};
+use anyhow::Result;
+use opentelemetry::metrics::MeterProvider as _;
+use opentelemetry::trace::{Span as _, Tracer as _, TracerProvider as _};
+use opentelemetry_sdk::error::OTelSdkResult;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Metrics are flushed by hand here; the periodic reader shouldn't beat us to it.
+const NO_PERIODIC_EXPORT: Duration = Duration::from_secs(3600);
+
+/// `acp-traces healthcheck`: export one span and one metric point through the
+/// pipeline the proxy would build, flush, and print what happened to each.
+/// Returns whether every enabled signal got through.
+pub fn run(config: &ExporterConfig, signals: Signals, timeout: Duration) -> Result<bool> {
+    let signals = Signals {
+        logs: false,
+        ..signals
+    };
+    if !signals.traces && !signals.metrics {
+        println!("no signal is exported over OTLP: nothing to check");
+        return Ok(true);
+    }
+    let (tracer_provider, meter_provider, _) = telemetry::build(
+        config,
+        signals,
+        timeout,
+        NO_PERIODIC_EXPORT,
+        None,
+        &Arc::default(),
+    )?;
+    if signals.traces {
+        println!("traces endpoint {} ({})", config.endpoint, config.protocol);
+    }
+    if signals.metrics {
+        println!(
+            "metrics endpoint {} ({})",
+            config.metrics_endpoint, config.metrics_protocol
+        );
+    }
+
+    let mut healthy = true;
+    if let Some(provider) = tracer_provider {
+        provider
+            .tracer("acp-traces")
+            .start("acp-traces healthcheck")
+            .end();
+        healthy &= report("traces", provider.force_flush());
+        let _ = provider.shutdown();
+    }
+    if let Some(provider) = meter_provider {
+        let counter = provider
+            .meter("acp-traces")
+            .u64_counter("acp.healthcheck")
+            .build();
+        counter.add(1, &[]);
+        healthy &= report("metrics", provider.force_flush());
+        let _ = provider.shutdown();
+    }
+    Ok(healthy)
+}
+
+fn report(signal: &str, result: OTelSdkResult) -> bool {
+    match result {
+        Ok(()) => {
+            println!("{signal}: ok");
+            true
+        }
+        Err(e) => {
+            let error = e.to_string();
+            println!("{signal}: FAILED ({})\n  {error}", Failure::of(&error));
+            false
+        }
+    }
+}
+
+/// Why an export failed, read off the transport error's text: tonic and reqwest
+/// only expose the cause as a message by the time it reaches the SDK.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    Dns,
+    ConnectionRefused,
+    Tls,
+    Timeout,
+    /// The collector answered with a 4xx (HTTP) or the gRPC equivalent.
+    Rejected,
+    Other,
+}
+
+impl Failure {
+    pub fn of(error: &str) -> Self {
+        let error = error.to_ascii_lowercase();
+        let any = |needles: &[&str]| needles.iter().any(|n| error.contains(n));
+        if any(&[
+            "dns error",
+            "failed to lookup address",
+            "name or service not known",
+            "nodename nor servname",
+        ]) {
+            Self::Dns
+        } else if any(&["connection refused"]) {
+            Self::ConnectionRefused
+        } else if any(&["certificate", "tls", "ssl", "handshake"]) {
+            Self::Tls
+        } else if any(&["timed out", "timeout", "deadline"]) {
+            Self::Timeout
+        } else if any(&[
+            "unauthenticated",
+            "permissiondenied",
+            "permission denied",
+            "unimplemented",
+            "notfound",
+        ]) || has_4xx_status(&error)
+        {
+            Self::Rejected
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// A 4xx right after "status" or "status code" in `error` (not just any number:
+/// the message may well mention port 4317).
+fn has_4xx_status(error: &str) -> bool {
+    let separators: &[char] = &[' ', ':', '='];
+    error.match_indices("status").any(|(at, word)| {
+        let rest = error[at + word.len()..].trim_start_matches(separators);
+        let rest = rest
+            .trim_start_matches("code")
+            .trim_start_matches(separators);
+        let code: String = rest.chars().take_while(char::is_ascii_digit).collect();
+        code.len() == 3 && code.starts_with('4')
+    })
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Dns => "DNS lookup failed: check the endpoint's host name",
+            Self::ConnectionRefused => {
+                "connection refused: nothing is listening on the endpoint's host and port"
+            }
+            Self::Tls => "TLS failure: check http:// vs https:// and --otlp-ca-cert",
+            Self::Timeout => {
+                "timed out: the port may be firewalled, or try a larger --otlp-timeout"
+            }
+            Self::Rejected => {
+                "rejected by the collector: check the endpoint path and --otlp-header credentials"
+            }
+            Self::Other => "export failed",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transport_errors_are_classified() {
+        let cases = [
+            (
+                "status: Unavailable, message: \"dns error: failed to lookup address information\"",
+                Failure::Dns,
+            ),
+            (
+                "error trying to connect: tcp connect error: Connection refused (os error 111)",
+                Failure::ConnectionRefused,
+            ),
+            ("invalid peer certificate: UnknownIssuer", Failure::Tls),
+            ("operation timed out", Failure::Timeout),
+            (
+                "code: Unauthenticated, message: \"bad token\"",
+                Failure::Rejected,
+            ),
+            ("HTTP export failed. Status Code: 404", Failure::Rejected),
+            ("HTTP export failed. Status Code: 503", Failure::Other),
+            (
+                "status: Unknown, error sending request to port 4317",
+                Failure::Other,
+            ),
+        ];
+        for (error, expected) in cases {
+            assert_eq!(Failure::of(error), expected, "{error}");
+        }
+    }
+}
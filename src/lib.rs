@@ -1 +1,6 @@
 pub mod acp;
+pub mod driver;
+pub mod spans;
+pub mod stderr;
+pub mod summary;
+pub mod transcript;
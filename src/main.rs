@@ -1,48 +1,450 @@
-mod acp;
-mod spans;
+mod console;
+mod doctor;
+mod healthcheck;
+mod sampler;
+mod scrape;
+mod span_json;
+mod spool;
+mod tally;
 mod telemetry;
+mod trace_file;
 
+use acp_traces::{acp, spans, stderr, summary, transcript};
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{CommandFactory, Parser};
+use opentelemetry::logs::LoggerProvider as _;
+use opentelemetry::KeyValue;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::Command;
 
+/// How often pending requests are checked against `--request-timeout` and
+/// `--prompt-timeout`.
+const REQUEST_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long open `--prometheus-port` connections get to finish after the agent exits.
+const SCRAPE_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long to keep reading `--capture-stderr` output after the agent exits, in case
+/// a grandchild still holds the pipe open.
+const STDERR_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long to keep relaying the agent's stdout after it exits: lines still in the
+/// pipe are its last answers. Bounded in case a grandchild holds the pipe open.
+const STDOUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long the agent gets to exit after a forwarded SIGINT/SIGTERM before it's
+/// killed.
+const SIGNAL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Sent to the agent when it outlasts `--kill-timeout` after stdin EOF.
+#[cfg(unix)]
+const SIGTERM: i32 = libc::SIGTERM;
+
+/// Never delivered: outside Unix, `forward_signal` stops the agent outright.
+#[cfg(not(unix))]
+const SIGTERM: i32 = 15;
+
+/// `--log-format` values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum LogFormat {
+    /// tracing-subscriber's default human-readable lines
+    Text,
+    /// One JSON object per line
+    Json,
+    /// Shorter human-readable lines
+    Compact,
+}
+
 #[derive(Parser)]
 #[command(
     name = "acp-traces",
     version,
-    about = "OTel tracing proxy for Agent Client Protocol"
+    about = "OTel tracing proxy for Agent Client Protocol",
+    subcommand_negates_reqs = true
 )]
 struct Cli {
-    /// OTLP endpoint
-    #[arg(long, default_value = "http://localhost:4317")]
-    otlp_endpoint: String,
+    /// OTLP endpoint for all signals; /v1/<signal> is added for HTTP if it has no
+    /// path [default: $OTEL_EXPORTER_OTLP_<SIGNAL>_ENDPOINT,
+    /// $OTEL_EXPORTER_OTLP_ENDPOINT, or http://localhost:4317 for grpc,
+    /// http://localhost:4318/v1/<signal> for HTTP]
+    #[arg(global = true, long)]
+    otlp_endpoint: Option<String>,
+
+    /// OTLP protocol for all signals [default: $OTEL_EXPORTER_OTLP_<SIGNAL>_PROTOCOL,
+    /// $OTEL_EXPORTER_OTLP_PROTOCOL, or grpc]
+    #[arg(global = true, long, value_enum)]
+    otlp_protocol: Option<telemetry::OtlpProtocol>,
+
+    /// OTLP endpoint for spans and captured stderr [default: --otlp-endpoint]
+    #[arg(global = true, long)]
+    otlp_traces_endpoint: Option<String>,
+
+    /// OTLP protocol for spans and captured stderr [default: --otlp-protocol]
+    #[arg(global = true, long, value_enum)]
+    otlp_traces_protocol: Option<telemetry::OtlpProtocol>,
+
+    /// OTLP endpoint for metrics [default: --otlp-endpoint, or the traces endpoint
+    /// if the protocols share a transport]
+    #[arg(global = true, long)]
+    otlp_metrics_endpoint: Option<String>,
+
+    /// OTLP protocol for metrics [default: --otlp-protocol]
+    #[arg(global = true, long, value_enum)]
+    otlp_metrics_protocol: Option<telemetry::OtlpProtocol>,
+
+    /// Extra OTLP exporter header, e.g. `authorization=Bearer <token>` (repeatable);
+    /// added to $OTEL_EXPORTER_OTLP_HEADERS and $OTEL_EXPORTER_OTLP_TRACES_HEADERS
+    #[arg(global = true, long = "otlp-header", value_name = "KEY=VALUE", value_parser = telemetry::parse_header)]
+    otlp_headers: Vec<(String, String)>,
+
+    /// Seconds to wait on each export request [default: 10]
+    #[arg(global = true, long, value_name = "SECS", value_parser = telemetry::parse_secs)]
+    otlp_timeout: Option<Duration>,
+
+    /// Seconds to spend flushing telemetry after the agent exits before giving up;
+    /// 0 exits without waiting
+    #[arg(global = true, long, value_name = "SECS", default_value = "5", value_parser = telemetry::parse_timeout)]
+    shutdown_timeout: Duration,
+
+    /// Seconds the agent gets to exit after stdin closes, and again after SIGTERM on
+    /// Unix, before it is killed; 0 kills it at once
+    #[arg(global = true, long, value_name = "SECS", default_value = "2", value_parser = telemetry::parse_timeout)]
+    kill_timeout: Duration,
+
+    /// Seconds between metric exports
+    #[arg(global = true, long, value_name = "SECS", default_value = "60", value_parser = telemetry::parse_secs)]
+    metrics_interval: Duration,
+
+    /// Serve metrics for Prometheus to scrape at http://localhost:PORT/metrics, instead
+    /// of exporting them over OTLP (not with replay)
+    #[arg(
+        global = true,
+        long,
+        value_name = "PORT",
+        conflicts_with = "disable_metrics"
+    )]
+    prometheus_port: Option<u16>,
+
+    /// Buckets for the gen_ai.* histograms: fixed boundaries, or base-2 exponential
+    /// ones that follow the recorded range (not with --prometheus-port)
+    #[arg(global = true, long, value_enum, default_value_t = telemetry::HistogramAggregation::Explicit)]
+    histogram_aggregation: telemetry::HistogramAggregation,
+
+    /// PEM CA certificate to trust for the OTLP endpoint
+    #[arg(global = true, long, value_name = "PATH")]
+    otlp_ca_cert: Option<PathBuf>,
+
+    /// PEM client certificate for mutual TLS (requires --otlp-client-key)
+    #[arg(global = true, long, value_name = "PATH")]
+    otlp_client_cert: Option<PathBuf>,
+
+    /// PEM private key for --otlp-client-cert
+    #[arg(global = true, long, value_name = "PATH")]
+    otlp_client_key: Option<PathBuf>,
+
+    /// Skip verification of the OTLP endpoint's certificate
+    #[arg(global = true, long)]
+    otlp_insecure: bool,
+
+    /// Compress OTLP payloads (gzip and zstd need --otlp-protocol grpc)
+    #[arg(global = true, long, value_enum, default_value_t = telemetry::OtlpCompression::None)]
+    otlp_compression: telemetry::OtlpCompression,
+
+    /// Also write every span to this file as one JSON object per line
+    #[arg(global = true, long, value_name = "PATH")]
+    trace_file: Option<PathBuf>,
+
+    /// Append to an existing --trace-file instead of truncating it
+    #[arg(global = true, long, requires = "trace_file")]
+    trace_file_append: bool,
+
+    /// Save span batches the OTLP collector rejects or can't be reached for in this
+    /// directory, and re-export them once it is back
+    #[arg(global = true, long, value_name = "PATH")]
+    spool_dir: Option<PathBuf>,
+
+    /// Largest --spool-dir size in MiB; the oldest batches are dropped beyond it
+    #[arg(
+        global = true,
+        long,
+        value_name = "MB",
+        default_value = "100",
+        requires = "spool_dir"
+    )]
+    spool_max_mb: u64,
+
+    /// Fraction of traces to keep, from 0.0 to 1.0; a parent-based sampler so a
+    /// session's spans are kept or dropped together
+    #[arg(global = true, long, value_name = "RATIO", default_value = "1.0", value_parser = telemetry::parse_ratio)]
+    sample_ratio: f64,
+
+    /// What one --sample-ratio decision covers: a whole session trace, or each prompt
+    /// turn (session roots are always kept)
+    #[arg(global = true, long, value_enum, default_value_t = telemetry::SamplePer::Session)]
+    sample_per: telemetry::SamplePer,
+
+    /// Spans the batch processor buffers before dropping new ones [default: 2048]
+    #[arg(global = true, long, value_name = "N", value_parser = telemetry::parse_count)]
+    batch_queue_size: Option<usize>,
+
+    /// Spans per export request [default: 512]
+    #[arg(global = true, long, value_name = "N", value_parser = telemetry::parse_count)]
+    batch_max_size: Option<usize>,
+
+    /// Longest a finished span waits before its batch is exported [default: 5000]
+    #[arg(global = true, long, value_name = "MS")]
+    batch_delay_ms: Option<u64>,
 
-    /// OTLP protocol: grpc or http
-    #[arg(long, default_value = "grpc")]
-    otlp_protocol: String,
+    /// Cut content attributes (messages, tool arguments and results, diffs) longer
+    /// than this many bytes, ending them with a `…[truncated N bytes]` marker. Streamed
+    /// text past the limit is counted but not buffered. [default: unlimited]
+    #[arg(
+        global = true,
+        long,
+        value_name = "BYTES",
+        visible_alias = "max-content-length"
+    )]
+    max_attr_length: Option<usize>,
 
-    /// OTel service name
-    #[arg(long, default_value = "acp-agent")]
-    service_name: String,
+    /// Drop attributes past this many per span [default: SDK default, 128]
+    #[arg(global = true, long, value_name = "N")]
+    max_attrs_per_span: Option<u32>,
 
-    /// Record message content (gen_ai.input/output.messages) — contains sensitive data
-    #[arg(long)]
+    /// Drop events past this many per span [default: SDK default, 128]
+    #[arg(global = true, long, value_name = "N")]
+    max_events_per_span: Option<u32>,
+
+    /// Don't export traces; spans are still tracked but go to a no-op provider
+    #[arg(global = true, long)]
+    disable_traces: bool,
+
+    /// Don't export metrics; histograms and counters become no-op instruments
+    #[arg(global = true, long)]
+    disable_metrics: bool,
+
+    /// OTel service name [default: $OTEL_SERVICE_NAME or acp-agent]
+    #[arg(global = true, long)]
+    service_name: Option<String>,
+
+    /// OTel service.version
+    #[arg(global = true, long, value_name = "VERSION", default_value = env!("CARGO_PKG_VERSION"))]
+    service_version: String,
+
+    /// OTel service.instance.id [default: a new UUID per run]
+    #[arg(global = true, long, value_name = "ID")]
+    service_instance_id: Option<String>,
+
+    /// Agent name for span names and gen_ai.agent.name when the agent's initialize
+    /// response has no agentInfo
+    #[arg(global = true, long, value_name = "NAME")]
+    agent_name: Option<String>,
+
+    /// Agent version to go with --agent-name (acp.agent.version)
+    #[arg(global = true, long, value_name = "VERSION", requires = "agent_name")]
+    agent_version: Option<String>,
+
+    /// Extra OTel resource attribute on every span and metric (repeatable);
+    /// added to $OTEL_RESOURCE_ATTRIBUTES, a repeated key keeps the last value
+    #[arg(global = true, long = "resource-attr", value_name = "KEY=VALUE", value_parser = telemetry::parse_resource_attr)]
+    resource_attrs: Vec<(String, String)>,
+
+    /// Facts about the proxy to put on the resource: host (host.name), os (os.type),
+    /// process (pid, executable, command args with --otlp-header/--env values scrubbed),
+    /// or none alone
+    #[arg(
+        global = true,
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = [
+            telemetry::ResourceDetector::Host,
+            telemetry::ResourceDetector::Os,
+            telemetry::ResourceDetector::Process,
+        ]
+    )]
+    resource_detectors: Vec<telemetry::ResourceDetector>,
+
+    /// Record all content: shorthand for the four --record-* flags below — contains
+    /// sensitive data
+    #[arg(global = true, long)]
     record_content: bool,
 
+    /// Record prompts (gen_ai.input.messages)
+    #[arg(global = true, long)]
+    record_input: bool,
+
+    /// Record agent replies, thoughts, and plan titles (gen_ai.output.messages)
+    #[arg(global = true, long)]
+    record_output: bool,
+
+    /// Record tool call arguments (gen_ai.tool.call.arguments) and full locations
+    #[arg(global = true, long)]
+    record_tool_args: bool,
+
+    /// Record tool call results (gen_ai.tool.call.result) and diffs
+    #[arg(global = true, long)]
+    record_tool_results: bool,
+
+    /// Replace matches of REGEX in recorded content with `[REDACTED]` (repeatable).
+    /// Invalid patterns are rejected at startup.
+    #[arg(global = true, long = "redact-pattern", value_name = "REGEX")]
+    redact_patterns: Vec<regex::Regex>,
+
+    /// Copy the `_meta` value at a dotted path onto the affected span as ATTRIBUTE
+    /// (repeatable). Applied regardless of --record-content.
+    #[arg(global = true, long = "meta-attr", value_name = "PATH=ATTRIBUTE")]
+    meta_attrs: Vec<spans::MetaAttr>,
+
+    /// Forward but don't trace requests and notifications with this method
+    /// (repeatable; a trailing * matches a prefix, e.g. terminal/*)
+    #[arg(global = true, long = "ignore-method", value_name = "METHOD")]
+    ignore_methods: Vec<spans::MethodPattern>,
+
+    /// Trace only requests and notifications with this method (repeatable; a trailing
+    /// * matches a prefix). The root session span is always created.
+    #[arg(
+        global = true,
+        long = "only-method",
+        value_name = "METHOD",
+        conflicts_with = "ignore_methods"
+    )]
+    only_methods: Vec<spans::MethodPattern>,
+
+    /// Pipe the agent's stderr: echo each line to our stderr and export it as an OTel
+    /// log record inside the active prompt's trace
+    #[arg(global = true, long)]
+    capture_stderr: bool,
+
+    /// Set an environment variable for the agent (repeatable); the value is everything
+    /// after the first `=`
+    #[arg(global = true, long = "env", value_name = "KEY=VALUE", value_parser = parse_env)]
+    envs: Vec<(String, String)>,
+
+    /// Remove an environment variable from the agent's environment (repeatable)
+    #[arg(global = true, long = "env-remove", value_name = "KEY")]
+    env_removes: Vec<String>,
+
+    /// Run the agent in this directory instead of the current one
+    #[arg(global = true, long, value_name = "DIR")]
+    cwd: Option<PathBuf>,
+
+    /// Copy the agent's stderr to this file instead of the terminal, creating parent
+    /// directories
+    #[arg(global = true, long, value_name = "PATH")]
+    stderr_file: Option<PathBuf>,
+
+    /// Append to an existing --stderr-file instead of truncating it
+    #[arg(global = true, long, requires = "stderr_file")]
+    stderr_file_append: bool,
+
+    /// Prefix each --stderr-file line with the time it was read
+    #[arg(global = true, long, requires = "stderr_file")]
+    stderr_timestamps: bool,
+
+    /// Don't print the session summary (prompts, turn timing, tool calls) at exit
+    #[arg(global = true, long)]
+    no_summary: bool,
+
+    /// Also write the session summary to this file as JSON
+    #[arg(global = true, long, value_name = "PATH")]
+    summary_file: Option<PathBuf>,
+
+    /// Force-export spans after every session/prompt response instead of waiting for
+    /// the next batch
+    #[arg(global = true, long)]
+    flush_on_prompt: bool,
+
+    /// Write every intercepted line to PATH as JSON Lines: {"ts", "dir", "msg"}
+    #[arg(global = true, long, value_name = "PATH")]
+    dump_jsonl: Option<PathBuf>,
+
+    /// End spans of requests left unanswered for this many seconds (not prompts or
+    /// permission requests)
+    #[arg(global = true, long, value_name = "SECS", value_parser = telemetry::parse_secs)]
+    request_timeout: Option<Duration>,
+
+    /// End prompt turns, and their open tool calls, left unanswered for this many seconds
+    #[arg(global = true, long, value_name = "SECS", value_parser = telemetry::parse_secs)]
+    prompt_timeout: Option<Duration>,
+
     /// Increase log verbosity (repeat for more: -v, -vv, -vvv)
-    #[arg(short, long, action = clap::ArgAction::Count)]
+    #[arg(global = true, short, long, action = clap::ArgAction::Count)]
     verbose: u8,
 
+    /// Format of the proxy's own log lines on stderr
+    #[arg(global = true, long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    #[command(subcommand)]
+    mode: Option<Mode>,
+
     /// Agent command and arguments
     #[arg(trailing_var_arg = true, required = true)]
     command: Vec<String>,
 }
 
+#[derive(clap::Subcommand)]
+enum Mode {
+    /// Export spans for a `--dump-jsonl` transcript instead of proxying an agent
+    Replay {
+        /// Transcript written by --dump-jsonl
+        #[arg(value_name = "TRANSCRIPT")]
+        transcript: PathBuf,
+    },
+    /// Export a test span and metric to the collector and report whether they got through
+    Healthcheck,
+    /// Spawn an agent, run the ACP handshake against it, and report what it advertises
+    Doctor {
+        /// Also open a session, then cancel it straight away
+        #[arg(long)]
+        session: bool,
+
+        /// Seconds to wait for each response
+        #[arg(long, value_name = "SECS", default_value = "10", value_parser = telemetry::parse_secs)]
+        timeout: Duration,
+
+        /// Agent command and arguments
+        #[arg(trailing_var_arg = true, required = true)]
+        command: Vec<String>,
+    },
+}
+
+/// Flag combinations clap's attributes can't express; exits like a parse error.
+fn check_flags(cli: &Cli) {
+    let detectors = &cli.resource_detectors;
+    if detectors.len() > 1 && detectors.contains(&telemetry::ResourceDetector::None) {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--resource-detectors none can't be combined with other detectors",
+            )
+            .exit();
+    }
+    if cli.prometheus_port.is_some() && matches!(cli.mode, Some(Mode::Replay { .. })) {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "replay exits without serving --prometheus-port",
+            )
+            .exit();
+    }
+}
+
+/// Write one line of agent stderr to ours.
+async fn echo_line(writer: &mut tokio::io::Stderr, line: &[u8]) -> std::io::Result<()> {
+    writer.write_all(line).await?;
+    writer.flush().await
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    check_flags(&cli);
 
     let log_level = match cli.verbose {
         0 => "warn",
@@ -50,51 +452,289 @@ async fn main() -> Result<()> {
         2 => "debug",
         _ => "trace",
     };
-    tracing_subscriber::fmt()
+    let logs = tracing_subscriber::fmt()
         .with_env_filter(
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(log_level)),
         )
-        .with_writer(std::io::stderr)
-        .init();
+        .with_writer(std::io::stderr);
+    match cli.log_format {
+        LogFormat::Text => logs.init(),
+        // Fields at the top level, next to `message`, for log shippers to index.
+        LogFormat::Json => logs.json().flatten_event(true).init(),
+        LogFormat::Compact => logs.compact().init(),
+    }
 
-    let (tracer_provider, meter_provider) =
-        telemetry::init(&cli.otlp_endpoint, &cli.otlp_protocol, &cli.service_name)?;
+    let otlp_timeout = cli.otlp_timeout.unwrap_or(telemetry::DEFAULT_TIMEOUT);
+    let service_instance_id = cli
+        .service_instance_id
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    tracing::info!(service.instance.id = %service_instance_id, "starting");
+    let exporter_config = telemetry::ExporterConfig {
+        tls: telemetry::TlsOptions {
+            ca_cert: cli.otlp_ca_cert,
+            client_cert: cli.otlp_client_cert,
+            client_key: cli.otlp_client_key,
+            insecure: cli.otlp_insecure,
+        },
+        compression: cli.otlp_compression,
+        service_version: Some(cli.service_version),
+        service_instance_id: Some(service_instance_id),
+        resource_detectors: cli.resource_detectors,
+        trace_file: cli.trace_file,
+        trace_file_append: cli.trace_file_append,
+        sample_ratio: cli.sample_ratio,
+        sample_per: cli.sample_per,
+        histogram_aggregation: cli.histogram_aggregation,
+        max_attrs_per_span: cli.max_attrs_per_span,
+        max_events_per_span: cli.max_events_per_span,
+        batch: telemetry::BatchOptions {
+            queue_size: cli.batch_queue_size,
+            max_batch: cli.batch_max_size,
+            delay: cli.batch_delay_ms.map(Duration::from_millis),
+        },
+        spool: cli.spool_dir.map(|dir| spool::SpoolOptions {
+            dir,
+            max_bytes: cli.spool_max_mb.saturating_mul(1024 * 1024),
+        }),
+        ..telemetry::ExporterConfig::resolve(
+            telemetry::EndpointFlags {
+                endpoint: cli.otlp_endpoint,
+                protocol: cli.otlp_protocol,
+                traces_endpoint: cli.otlp_traces_endpoint,
+                traces_protocol: cli.otlp_traces_protocol,
+                metrics_endpoint: cli.otlp_metrics_endpoint,
+                metrics_protocol: cli.otlp_metrics_protocol,
+            },
+            cli.service_name,
+            cli.otlp_headers,
+            cli.resource_attrs,
+            |key| std::env::var(key).ok(),
+        )?
+    };
+    let signals = telemetry::Signals {
+        traces: !cli.disable_traces,
+        metrics: !cli.disable_metrics,
+        logs: cli.capture_stderr,
+    };
+    if let Some(Mode::Healthcheck) = cli.mode {
+        // Keep the test span whatever the sampling, and send it only to the collector.
+        let config = telemetry::ExporterConfig {
+            sample_ratio: 1.0,
+            trace_file: None,
+            spool: None,
+            ..exporter_config
+        };
+        let signals = telemetry::Signals {
+            metrics: signals.metrics && cli.prometheus_port.is_none(),
+            ..signals
+        };
+        let healthy =
+            tokio::task::spawn_blocking(move || healthcheck::run(&config, signals, otlp_timeout))
+                .await??;
+        std::process::exit(if healthy { 0 } else { 1 });
+    }
+    let prometheus = cli
+        .prometheus_port
+        .map(|port| (port, prometheus::Registry::new()));
+    let otlp_spans = Arc::new(tally::SpanTally::default());
+    let (tracer_provider, meter_provider, logger_provider) = telemetry::init(
+        &exporter_config,
+        signals,
+        otlp_timeout,
+        cli.metrics_interval,
+        prometheus.as_ref().map(|(_, registry)| registry),
+        &otlp_spans,
+    )?;
 
     let tracer = opentelemetry::global::tracer("acp-traces");
     let meter = opentelemetry::global::meter("acp-traces");
-    let span_mgr = spans::SpanManager::new(tracer, meter, cli.record_content);
+    let content_policy = spans::ContentPolicy {
+        input: cli.record_content || cli.record_input,
+        output: cli.record_content || cli.record_output,
+        tool_args: cli.record_content || cli.record_tool_args,
+        tool_results: cli.record_content || cli.record_tool_results,
+    };
+    let mut span_mgr = spans::SpanManager::new(tracer, meter.clone(), false)
+        .with_content_policy(content_policy)
+        .with_meta_attrs(cli.meta_attrs)
+        .with_ignored_methods(cli.ignore_methods)
+        .with_redact_patterns(cli.redact_patterns.clone());
+    if let Some(name) = cli.agent_name {
+        span_mgr = span_mgr.with_agent_info(name, cli.agent_version);
+    }
+    if !cli.only_methods.is_empty() {
+        span_mgr = span_mgr.with_only_methods(cli.only_methods);
+    }
+    if let Some(timeout) = cli.request_timeout {
+        span_mgr = span_mgr.with_request_timeout(timeout);
+    }
+    if let Some(timeout) = cli.prompt_timeout {
+        span_mgr = span_mgr.with_prompt_timeout(timeout);
+    }
+    if let Some(bytes) = cli.max_attr_length {
+        span_mgr = span_mgr.with_max_attr_length(bytes);
+    }
+
+    if let Some(Mode::Replay { transcript }) = &cli.mode {
+        let replayed = transcript::replay(transcript, &mut span_mgr)?;
+        span_mgr.shutdown();
+        report(
+            &span_mgr.summary(),
+            !cli.no_summary,
+            cli.log_format,
+            cli.summary_file.as_deref(),
+        );
+        telemetry::shutdown(
+            tracer_provider,
+            meter_provider,
+            logger_provider,
+            cli.shutdown_timeout,
+            &otlp_spans,
+        );
+        tracing::info!(messages = replayed, "replay finished");
+        return Ok(());
+    }
+    if let Some(Mode::Doctor {
+        session,
+        timeout,
+        command,
+    }) = &cli.mode
+    {
+        let (cmd, args) = command.split_first().context("no command specified")?;
+        let cwd = agent_cwd(cli.cwd.as_deref())?;
+        span_mgr = span_mgr.with_agent_cwd(cwd.display().to_string());
+        let mut child = agent_command(cmd, args, &cwd, &cli.envs, &cli.env_removes)
+            .spawn()
+            .with_context(|| format!("failed to spawn: {cmd}"))?;
+        let stdin = child.stdin.take().context("no child stdin")?;
+        let stdout = child.stdout.take().context("no child stdout")?;
+        let options = doctor::Options {
+            session: *session,
+            timeout: *timeout,
+            cwd: &cwd,
+        };
+        let healthy = doctor::run(stdin, BufReader::new(stdout), &options, &mut span_mgr).await;
+        // The agent's stdin is closed by now, as when an editor goes away.
+        let status = stop_after_eof(&mut child, cli.kill_timeout).await?;
+        span_mgr.shutdown();
+        let mut summary = span_mgr.summary();
+        // Any signal that ended it was the proxy's own.
+        summary.agent_exit_code = Some(match exit_signal(status) {
+            Some(_) => 0,
+            None => exit_code(status),
+        });
+        report(
+            &summary,
+            !cli.no_summary,
+            cli.log_format,
+            cli.summary_file.as_deref(),
+        );
+        telemetry::shutdown(
+            tracer_provider,
+            meter_provider,
+            logger_provider,
+            cli.shutdown_timeout,
+            &otlp_spans,
+        );
+        std::process::exit(if healthy { 0 } else { 1 });
+    }
+
+    let mut transcript = cli
+        .dump_jsonl
+        .as_deref()
+        .map(transcript::Transcript::create)
+        .transpose()?
+        .map(|t| t.with_redact_patterns(cli.redact_patterns));
+
+    let (stop_scrape, scrape_stopped) = tokio::sync::oneshot::channel::<()>();
+    let scrape_server = match prometheus {
+        Some((port, registry)) => {
+            let listener = scrape::bind(port).await?;
+            tracing::info!(port, "serving Prometheus metrics at /metrics");
+            Some(tokio::spawn(scrape::serve(listener, registry, async {
+                let _ = scrape_stopped.await;
+            })))
+        }
+        None => None,
+    };
 
     let (cmd, args) = cli.command.split_first().context("no command specified")?;
     tracing::info!(cmd = %cmd, args = ?args, "spawning agent");
+    let cwd = agent_cwd(cli.cwd.as_deref())?;
+    span_mgr = span_mgr.with_agent_cwd(cwd.display().to_string());
 
-    let mut child = Command::new(cmd)
-        .args(args)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::inherit())
+    // Installed before the agent starts: once a handler exists, Ctrl-C no longer
+    // kills the proxy outright, so open spans still get closed and exported.
+    let stop_signal = stop_signal()?;
+    let stderr_file = cli
+        .stderr_file
+        .as_deref()
+        .map(|path| stderr::StderrFile::open(path, cli.stderr_file_append, cli.stderr_timestamps))
+        .transpose()?;
+    let mut child = agent_command(cmd, args, &cwd, &cli.envs, &cli.env_removes)
+        .stderr(if cli.capture_stderr || stderr_file.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::inherit()
+        })
         .spawn()
         .with_context(|| format!("failed to spawn: {cmd}"))?;
 
     let child_stdin = child.stdin.take().context("no child stdin")?;
     let child_stdout = child.stdout.take().context("no child stdout")?;
+    let child_stderr = child.stderr.take();
 
     let parent_stdin = tokio::io::stdin();
     let parent_stdout = tokio::io::stdout();
 
     let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<(acp::Direction, String)>();
 
+    // Lines relayed but not yet processed, for `acp.proxy.channel_depth`: counted
+    // before each send and after each receive.
+    let depth = Arc::new(AtomicU64::new(0));
+    let _channel_depth = {
+        let depth = depth.clone();
+        meter
+            .u64_observable_gauge("acp.proxy.channel_depth")
+            .with_unit("{message}")
+            .with_description("Messages relayed but not yet traced")
+            .with_callback(move |gauge| gauge.observe(depth.load(Ordering::Relaxed), &[]))
+            .build()
+    };
+
+    // Counted as relayed, so lines that fail to parse still show up as traffic.
+    let io_bytes = meter
+        .u64_counter("acp.io.bytes")
+        .with_unit("By")
+        .with_description("Bytes relayed between editor and agent, newlines included")
+        .build();
+    let io_messages = meter
+        .u64_counter("acp.io.messages")
+        .with_unit("{message}")
+        .with_description("Lines relayed between editor and agent, parsed or not")
+        .build();
+
     let tx_editor = tx.clone();
-    let editor_to_agent = tokio::spawn(async move {
+    let depth_editor = depth.clone();
+    let (bytes_editor, messages_editor) = (io_bytes.clone(), io_messages.clone());
+    let mut editor_to_agent = tokio::spawn(async move {
         let mut reader = BufReader::new(parent_stdin);
         let mut writer = child_stdin;
         let mut line = String::new();
+        let attrs = [KeyValue::new(
+            "acp.direction",
+            acp::Direction::EditorToAgent.as_str(),
+        )];
         loop {
             line.clear();
             let n = reader.read_line(&mut line).await?;
             if n == 0 {
                 break;
             }
+            bytes_editor.add(n as u64, &attrs);
+            messages_editor.add(1, &attrs);
+            depth_editor.fetch_add(1, Ordering::Relaxed);
             let _ = tx_editor.send((acp::Direction::EditorToAgent, line.trim_end().to_string()));
             writer.write_all(line.as_bytes()).await?;
             writer.flush().await?;
@@ -103,16 +743,25 @@ async fn main() -> Result<()> {
     });
 
     let tx_agent = tx;
-    let agent_to_editor = tokio::spawn(async move {
+    let depth_agent = depth.clone();
+    let (bytes_agent, messages_agent) = (io_bytes, io_messages);
+    let mut agent_to_editor = tokio::spawn(async move {
         let mut reader = BufReader::new(child_stdout);
         let mut writer = parent_stdout;
         let mut line = String::new();
+        let attrs = [KeyValue::new(
+            "acp.direction",
+            acp::Direction::AgentToEditor.as_str(),
+        )];
         loop {
             line.clear();
             let n = reader.read_line(&mut line).await?;
             if n == 0 {
                 break;
             }
+            bytes_agent.add(n as u64, &attrs);
+            messages_agent.add(1, &attrs);
+            depth_agent.fetch_add(1, Ordering::Relaxed);
             let _ = tx_agent.send((acp::Direction::AgentToEditor, line.trim_end().to_string()));
             writer.write_all(line.as_bytes()).await?;
             writer.flush().await?;
@@ -120,32 +769,330 @@ async fn main() -> Result<()> {
         anyhow::Ok(())
     });
 
+    // Echo captured stderr unchanged; the processor turns each line into a log record.
+    let (stderr_tx, mut stderr_rx) = tokio::sync::mpsc::unbounded_channel::<(String, SystemTime)>();
+    // One reader feeds both --stderr-file and --capture-stderr.
+    let agent_stderr = child_stderr.map(|child_stderr| {
+        let mut file = stderr_file;
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(child_stderr);
+            // `None` once echoing failed; the lines are still read, filed, and logged.
+            let mut writer = Some(tokio::io::stderr());
+            // Bytes, not lines of text, so non-UTF-8 output is still echoed verbatim.
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                let n = reader.read_until(b'\n', &mut line).await?;
+                if n == 0 {
+                    break;
+                }
+                let at = SystemTime::now();
+                let filed = file.as_mut().is_some_and(|file| file.write(&line, at));
+                if let Some(w) = writer.as_mut().filter(|_| !filed) {
+                    if let Err(e) = echo_line(w, &line).await {
+                        tracing::warn!(error = %e, "echoing agent stderr failed; no longer echoing");
+                        writer = None;
+                    }
+                }
+                let text = String::from_utf8_lossy(&line).trim_end().to_string();
+                let _ = stderr_tx.send((text, at));
+            }
+            if let Some(file) = file.as_mut() {
+                file.flush();
+            }
+            anyhow::Ok(())
+        })
+    });
+    let stderr_logger = logger_provider
+        .as_ref()
+        .map(|provider| stderr::StderrLogger::new(provider.logger("acp-traces")));
+
+    // The signal that killed the agent, if any, for the root span.
+    let (exit_signal_tx, mut exit_signal_rx) = tokio::sync::oneshot::channel::<&'static str>();
+
     // Process intercepted messages — owns span_mgr, no shared state
     let tp_clone = tracer_provider.clone();
+    let flush_on_prompt = cli.flush_on_prompt;
     let processor = tokio::spawn(async move {
         let mut mgr = span_mgr;
-        while let Some((direction, line)) = rx.recv().await {
-            mgr.process_message(direction, &line);
+        let mut sweep = tokio::time::interval(REQUEST_SWEEP_INTERVAL);
+        sweep.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            tokio::select! {
+                msg = rx.recv() => match msg {
+                    Some((direction, line)) => {
+                        depth.fetch_sub(1, Ordering::Relaxed);
+                        if let Some(transcript) = transcript.as_mut() {
+                            transcript.record(direction, &line, SystemTime::now());
+                        }
+                        mgr.process_message(direction, &line);
+                        if mgr.take_turn_completed() && flush_on_prompt {
+                            if let Some(tp) = &tp_clone {
+                                telemetry::flush_in_background(tp);
+                            }
+                        }
+                    }
+                    None => break,
+                },
+                Some((line, at)) = stderr_rx.recv() => {
+                    if let Some(logger) = &stderr_logger {
+                        logger.emit(&line, at, mgr.active_span_context().as_ref());
+                    }
+                }
+                _ = sweep.tick() => mgr.expire_requests(Instant::now()),
+            }
+        }
+        // Stderr read before the agent exited still belongs to its last span.
+        while let Ok((line, at)) = stderr_rx.try_recv() {
+            if let Some(logger) = &stderr_logger {
+                logger.emit(&line, at, mgr.active_span_context().as_ref());
+            }
+        }
+        if let Ok(signal) = exit_signal_rx.try_recv() {
+            mgr.record_exit_signal(signal);
         }
         mgr.shutdown();
-        // Flush immediately so the root span is exported before process exit
-        let _ = tp_clone.force_flush();
+        if let Some(transcript) = transcript.as_mut() {
+            transcript.flush();
+        }
+        mgr.summary()
     });
 
+    // Whether the proxy ended the agent itself after stdin EOF; a signal it sent then
+    // is no failure of the agent's.
+    let mut stopped_on_eof = false;
     let status = tokio::select! {
         s = child.wait() => s?,
-        _ = editor_to_agent => {
-            // stdin EOF — kill child so we can shut down cleanly
-            child.kill().await.ok();
-            child.wait().await?
+        _ = &mut editor_to_agent => {
+            // stdin EOF: the writer is gone, so the agent's stdin is closed. Give it
+            // time to finish in-flight work, still relaying its stdout, then stop it.
+            stopped_on_eof = true;
+            stop_after_eof(&mut child, cli.kill_timeout).await?
+        }
+        signal = stop_signal => {
+            tracing::info!(signal, "forwarding signal to agent");
+            stop_agent(&mut child, signal, SIGNAL_GRACE_PERIOD).await?
+        }
+    };
+    let killed_on_eof = stopped_on_eof && exit_signal(status).is_some();
+    let code = if killed_on_eof { 0 } else { exit_code(status) };
+    // Relay and trace what the agent wrote before it exited, up to EOF on its pipes.
+    let stdout_drained = tokio::time::timeout(STDOUT_DRAIN_TIMEOUT, &mut agent_to_editor);
+    let stderr_drained = async {
+        if let Some(agent_stderr) = agent_stderr {
+            let _ = tokio::time::timeout(STDERR_DRAIN_TIMEOUT, agent_stderr).await;
         }
     };
-    // Abort the agent_to_editor task to drop its tx sender, closing the channel
+    let _ = tokio::join!(stdout_drained, stderr_drained);
+    if let Some(signal) = exit_signal(status).filter(|_| !killed_on_eof) {
+        let _ = exit_signal_tx.send(signal);
+    }
+    // Abort both relay tasks to drop their tx senders, closing the channel: the
+    // editor may keep its end of stdin open after the agent is gone, and the stdout
+    // relay may have outlasted its drain.
+    editor_to_agent.abort();
     agent_to_editor.abort();
-    let _ = processor.await;
+    let mut summary = processor.await.unwrap_or_default();
+    summary.agent_exit_code = Some(code);
+    report(
+        &summary,
+        !cli.no_summary,
+        cli.log_format,
+        cli.summary_file.as_deref(),
+    );
+    let _ = stop_scrape.send(());
+    if let Some(server) = scrape_server {
+        if let Ok(Ok(Err(e))) = tokio::time::timeout(SCRAPE_SHUTDOWN_TIMEOUT, server).await {
+            tracing::warn!(error = %e, "Prometheus endpoint error");
+        }
+    }
 
-    telemetry::shutdown(tracer_provider, meter_provider);
+    telemetry::shutdown(
+        tracer_provider,
+        meter_provider,
+        logger_provider,
+        cli.shutdown_timeout,
+        &otlp_spans,
+    );
 
     tracing::info!(code = ?status.code(), "agent exited");
-    std::process::exit(status.code().unwrap_or(0));
+    std::process::exit(code);
+}
+
+/// Print the exit summary to stderr unless `--no-summary`, and write it to
+/// `--summary-file` as JSON. With `--log-format json` it's logged as an info event
+/// instead, so stderr stays one JSON object per line.
+fn report(summary: &summary::Summary, print: bool, format: LogFormat, file: Option<&Path>) {
+    if print {
+        match format {
+            LogFormat::Json => tracing::info!(
+                summary = %serde_json::to_string(summary).unwrap_or_default(),
+                "acp-traces session summary"
+            ),
+            LogFormat::Text | LogFormat::Compact => eprint!("{summary}"),
+        }
+    }
+    if let Some(path) = file {
+        let json = serde_json::to_string_pretty(summary).unwrap_or_default();
+        if let Err(e) = std::fs::write(path, json + "\n") {
+            tracing::warn!(error = %e, path = %path.display(), "failed to write summary file");
+        }
+    }
+}
+
+/// Parse `--env KEY=VALUE`. The key must be non-empty and `=`-free; the value is
+/// kept verbatim, `=` and all.
+fn parse_env(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got {s:?}")),
+    }
+}
+
+/// `--cwd` resolved to an absolute directory, or the proxy's own working directory.
+fn agent_cwd(dir: Option<&Path>) -> Result<PathBuf> {
+    match dir {
+        Some(dir) => std::fs::canonicalize(dir)
+            .ok()
+            .filter(|dir| dir.is_dir())
+            .with_context(|| format!("--cwd {} is not a directory", dir.display())),
+        None => std::env::current_dir().context("failed to read the current directory"),
+    }
+}
+
+/// The agent process with `--env`, `--env-remove` and `--cwd` applied, speaking ACP
+/// over piped stdin and stdout.
+fn agent_command(
+    cmd: &str,
+    args: &[String],
+    cwd: &Path,
+    envs: &[(String, String)],
+    env_removes: &[String],
+) -> Command {
+    let mut command = Command::new(cmd);
+    for key in env_removes {
+        command.env_remove(key);
+    }
+    command
+        .args(args)
+        .envs(envs.iter().map(|(key, value)| (key, value)))
+        .current_dir(cwd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped());
+    command
+}
+
+/// Once the agent's stdin is closed, give it `grace` to exit on its own, as most do
+/// when their input ends, before stopping it with SIGTERM and the same grace.
+async fn stop_after_eof(
+    child: &mut tokio::process::Child,
+    grace: Duration,
+) -> std::io::Result<std::process::ExitStatus> {
+    if let Ok(status) = tokio::time::timeout(grace, child.wait()).await {
+        return status;
+    }
+    stop_agent(child, SIGTERM, grace).await
+}
+
+/// Ask the agent to exit with `signal`, then kill it if it's still running after
+/// `grace`. A zero grace period kills it outright.
+async fn stop_agent(
+    child: &mut tokio::process::Child,
+    signal: i32,
+    grace: Duration,
+) -> std::io::Result<std::process::ExitStatus> {
+    if !grace.is_zero() {
+        forward_signal(child, signal);
+        if let Ok(status) = tokio::time::timeout(grace, child.wait()).await {
+            return status;
+        }
+        tracing::warn!(grace = ?grace, "agent still running; killing it");
+    }
+    child.kill().await.ok();
+    child.wait().await
+}
+
+/// Resolve to the first SIGINT or SIGTERM the proxy receives.
+#[cfg(unix)]
+fn stop_signal() -> Result<impl std::future::Future<Output = i32>> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut interrupt =
+        signal(SignalKind::interrupt()).context("failed to install SIGINT handler")?;
+    let mut terminate =
+        signal(SignalKind::terminate()).context("failed to install SIGTERM handler")?;
+    Ok(async move {
+        tokio::select! {
+            _ = interrupt.recv() => libc::SIGINT,
+            _ = terminate.recv() => libc::SIGTERM,
+        }
+    })
+}
+
+/// Resolve on Ctrl-C, the only stop signal outside Unix.
+#[cfg(not(unix))]
+fn stop_signal() -> Result<impl std::future::Future<Output = i32>> {
+    Ok(async {
+        let _ = tokio::signal::ctrl_c().await;
+        2
+    })
+}
+
+#[cfg(unix)]
+fn forward_signal(child: &mut tokio::process::Child, signal: i32) {
+    if let Some(pid) = child.id() {
+        // SAFETY: kill(2) takes plain integers and touches no memory of ours.
+        if unsafe { libc::kill(pid as libc::pid_t, signal) } != 0 {
+            let e = std::io::Error::last_os_error();
+            tracing::warn!(error = %e, "failed to signal agent");
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn forward_signal(child: &mut tokio::process::Child, _signal: i32) {
+    if let Err(e) = child.start_kill() {
+        tracing::warn!(error = %e, "failed to stop agent");
+    }
+}
+
+/// The agent's exit code, or 128 + the signal number if a signal ended it, as a
+/// shell would report it.
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return 128 + signal;
+    }
+    status.code().unwrap_or(0)
+}
+
+/// Name of the signal that ended the agent, for `acp.agent.exit_signal`.
+#[cfg(unix)]
+fn exit_signal(status: std::process::ExitStatus) -> Option<&'static str> {
+    let signal = std::os::unix::process::ExitStatusExt::signal(&status)?;
+    Some(match signal {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGUSR2 => "SIGUSR2",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        libc::SIGSYS => "SIGSYS",
+        _ => "unknown",
+    })
+}
+
+#[cfg(not(unix))]
+fn exit_signal(_status: std::process::ExitStatus) -> Option<&'static str> {
+    None
 }
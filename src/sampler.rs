@@ -0,0 +1,133 @@
+use opentelemetry::trace::{
+    Link, SamplingDecision, SamplingResult, SpanKind, TraceContextExt, TraceId,
+};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::trace::ShouldSample;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// `--sample-per prompt`: keeps every session root and decides each `invoke_agent`
+/// turn on its own, so one session's trace holds a sample of its turns. Other spans
+/// follow their parent, which drops a sampled-out turn's tool calls with it.
+#[derive(Debug, Clone)]
+pub struct TurnSampler {
+    pub ratio: f64,
+}
+
+impl TurnSampler {
+    /// Same turn, same decision: hash the trace id with the turn's conversation id and
+    /// index, like `TraceIdRatioBased` does for whole traces.
+    fn keep_turn(&self, trace_id: TraceId, attributes: &[KeyValue]) -> bool {
+        let mut hasher = DefaultHasher::new();
+        trace_id.to_bytes().hash(&mut hasher);
+        for kv in attributes {
+            if matches!(kv.key.as_str(), "gen_ai.conversation.id" | "acp.turn.index") {
+                kv.value.as_str().hash(&mut hasher);
+            }
+        }
+        let unit = (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64;
+        unit < self.ratio
+    }
+}
+
+impl ShouldSample for TurnSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        _name: &str,
+        _span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        _links: &[Link],
+    ) -> SamplingResult {
+        let parent = parent_context
+            .filter(|cx| cx.has_active_span())
+            .map(|cx| cx.span().span_context().clone());
+        let is_turn = attributes.iter().any(|kv| {
+            kv.key.as_str() == "gen_ai.operation.name" && kv.value.as_str() == "invoke_agent"
+        });
+        let keep = if is_turn {
+            self.keep_turn(trace_id, attributes)
+        } else {
+            parent.as_ref().is_none_or(|parent| parent.is_sampled())
+        };
+        SamplingResult {
+            decision: if keep {
+                SamplingDecision::RecordAndSample
+            } else {
+                SamplingDecision::Drop
+            },
+            attributes: Vec::new(),
+            trace_state: parent
+                .map(|parent| parent.trace_state().clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SpanContext, SpanId, TraceFlags, TraceState};
+
+    fn decide(sampler: &TurnSampler, parent: Option<&Context>, attrs: &[KeyValue]) -> bool {
+        let result = sampler.should_sample(
+            parent,
+            TraceId::from_bytes(42u128.to_be_bytes()),
+            "span",
+            &SpanKind::Internal,
+            attrs,
+            &[],
+        );
+        result.decision == SamplingDecision::RecordAndSample
+    }
+
+    fn parent(sampled: bool) -> Context {
+        let flags = if sampled {
+            TraceFlags::SAMPLED
+        } else {
+            TraceFlags::default()
+        };
+        Context::new().with_remote_span_context(SpanContext::new(
+            TraceId::from_bytes(42u128.to_be_bytes()),
+            SpanId::from_bytes(7u64.to_be_bytes()),
+            flags,
+            false,
+            TraceState::default(),
+        ))
+    }
+
+    fn turn(index: i64) -> Vec<KeyValue> {
+        vec![
+            KeyValue::new("gen_ai.operation.name", "invoke_agent"),
+            KeyValue::new("gen_ai.conversation.id", "s1"),
+            KeyValue::new("acp.turn.index", index),
+        ]
+    }
+
+    #[test]
+    fn roots_are_kept_and_children_follow_their_parent() {
+        let none = TurnSampler { ratio: 0.0 };
+        assert!(decide(&none, None, &[]));
+        assert!(decide(&none, Some(&parent(true)), &[]));
+        assert!(!decide(&none, Some(&parent(false)), &[]));
+    }
+
+    #[test]
+    fn turns_are_sampled_individually_and_stably() {
+        let none = TurnSampler { ratio: 0.0 };
+        let all = TurnSampler { ratio: 1.0 };
+        assert!(!decide(&none, Some(&parent(true)), &turn(0)));
+        assert!(decide(&all, Some(&parent(true)), &turn(0)));
+
+        let half = TurnSampler { ratio: 0.5 };
+        let decisions: Vec<bool> = (0..64)
+            .map(|i| decide(&half, Some(&parent(true)), &turn(i)))
+            .collect();
+        assert!(decisions.contains(&true) && decisions.contains(&false));
+        let again: Vec<bool> = (0..64)
+            .map(|i| decide(&half, Some(&parent(true)), &turn(i)))
+            .collect();
+        assert_eq!(decisions, again);
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::{Context, Result};
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use prometheus::{Encoder, Registry, TextEncoder};
+use std::future::Future;
+use std::net::Ipv4Addr;
+use tokio::net::TcpListener;
+
+/// Listen on `localhost:port` for `--prometheus-port`. Done before the agent starts,
+/// so a port already in use fails the run up front.
+pub async fn bind(port: u16) -> Result<TcpListener> {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .await
+        .with_context(|| format!("failed to listen on localhost:{port} for --prometheus-port"))
+}
+
+/// Serve `registry` in the Prometheus text format at `/metrics` until `shutdown`
+/// resolves.
+pub async fn serve(
+    listener: TcpListener,
+    registry: Registry,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    let app = Router::new().route("/metrics", get(move || render(registry.clone())));
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown)
+        .await
+        .context("Prometheus endpoint failed")
+}
+
+async fn render(registry: Registry) -> impl IntoResponse {
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    match encoder.encode(&registry.gather(), &mut body) {
+        Ok(()) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+            body,
+        ),
+        Err(e) => {
+            tracing::warn!(error = %e, "failed to encode Prometheus metrics");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                [(header::CONTENT_TYPE, "text/plain".to_string())],
+                e.to_string().into_bytes(),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry_sdk::metrics::SdkMeterProvider;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn metrics_are_scraped_with_prometheus_names() {
+        let registry = Registry::new();
+        let reader = opentelemetry_prometheus::exporter()
+            .with_registry(registry.clone())
+            .build()
+            .unwrap();
+        let provider = SdkMeterProvider::builder().with_reader(reader).build();
+        let meter = provider.meter("test");
+        meter
+            .f64_histogram("gen_ai.client.operation.duration")
+            .with_unit("s")
+            .build()
+            .record(1.5, &[]);
+        meter
+            .u64_counter("acp.orphan_responses")
+            .build()
+            .add(2, &[]);
+
+        let listener = bind(0).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve(listener, registry, async {
+            let _ = stopped.await;
+        }));
+
+        let mut stream = tokio::net::TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+            .await
+            .unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.contains("gen_ai_client_operation_duration_seconds_bucket"));
+        assert!(response.contains("gen_ai_client_operation_duration_seconds_count"));
+        assert!(response.contains("acp_orphan_responses_total{"));
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,50 @@
+use opentelemetry::trace::Status;
+use opentelemetry::{Array, Value as OtelValue};
+use serde_json::{json, Value};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// JSON pieces shared by the span exporters that write to disk: `--trace-file` and
+// `--spool-dir`.
+
+pub fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+}
+
+pub fn from_unix_nanos(value: &Value) -> Option<SystemTime> {
+    Some(UNIX_EPOCH + Duration::from_nanos(value.as_u64()?))
+}
+
+/// `value` as the JSON it holds; values without a JSON counterpart become strings.
+pub fn value_json(value: &OtelValue) -> Value {
+    match value {
+        OtelValue::Bool(b) => json!(b),
+        OtelValue::I64(i) => json!(i),
+        OtelValue::F64(f) => json!(f),
+        OtelValue::String(s) => json!(s.as_str()),
+        OtelValue::Array(Array::Bool(v)) => json!(v),
+        OtelValue::Array(Array::I64(v)) => json!(v),
+        OtelValue::Array(Array::F64(v)) => json!(v),
+        OtelValue::Array(Array::String(v)) => {
+            Value::Array(v.iter().map(|s| json!(s.as_str())).collect())
+        }
+        other => json!(other.to_string()),
+    }
+}
+
+pub fn status_json(status: &Status) -> Value {
+    match status {
+        Status::Unset => json!({ "code": "unset" }),
+        Status::Ok => json!({ "code": "ok" }),
+        Status::Error { description } => json!({ "code": "error", "description": description }),
+    }
+}
+
+pub fn status_from_json(value: &Value) -> Option<Status> {
+    Some(match value["code"].as_str()? {
+        "ok" => Status::Ok,
+        "error" => Status::error(value["description"].as_str()?.to_string()),
+        _ => Status::Unset,
+    })
+}
@@ -1,20 +1,363 @@
 use crate::acp::{self, Direction, MessageType};
+use crate::summary::{Stats, Summary, ToolCounts};
 use opentelemetry::{
-    metrics::{Histogram, Meter},
-    trace::{Span, SpanContext, SpanKind, Status, TraceContextExt, Tracer},
+    metrics::{Counter, Histogram, Meter},
+    trace::{Link, Span, SpanBuilder, SpanContext, SpanKind, Status, TraceContextExt, Tracer},
     Context, KeyValue,
 };
+use regex::Regex;
 use serde_json::Value;
-use std::collections::HashMap;
-use std::time::Instant;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant, SystemTime};
 
+/// Paths listed in `acp.tool.locations` when content recording is off.
+const MAX_TOOL_LOCATIONS: usize = 10;
+/// URIs listed in `acp.prompt.resources` before summarizing the rest.
+const MAX_PROMPT_RESOURCES: usize = 10;
+/// Minimum gap between orphan-response warnings.
+const ORPHAN_WARN_INTERVAL: Duration = Duration::from_secs(10);
+/// Bytes of rendered diff text attached as `acp.tool.diff` with content recording.
+const MAX_DIFF_TEXT: usize = 8 * 1024;
+/// Bytes of tool output text accumulated from `content` blocks with content recording.
+const MAX_TOOL_OUTPUT: usize = 16 * 1024;
+/// Bytes buffered past a content limit, so a secret straddling the limit is still whole
+/// when the buffer is redacted; the cut to the limit comes after redaction.
+const REDACT_SLACK: usize = 1024;
+/// Abandoned requests remembered so their late responses aren't counted as orphans.
+const MAX_ABANDONED_REQUESTS: usize = 1024;
+
+#[derive(Default)]
 struct SessionState {
     prompt_span: Option<opentelemetry::global::BoxedSpan>,
     prompt_span_context: Option<SpanContext>,
     prompt_start: Option<Instant>,
+    /// When `session/cancel` arrived for the open prompt.
+    cancelled_at: Option<Instant>,
+    /// Pending-map key of the open prompt's request.
+    prompt_request: Option<(Direction, String)>,
     first_chunk_time: Option<Instant>,
+    /// `agent_message_chunk` text — only stored when recording content.
     accumulated_output: String,
-    tool_spans: HashMap<String, opentelemetry::global::BoxedSpan>,
+    output_chunks: u64,
+    output_chars: u64,
+    /// `agent_thought_chunk` text — only stored when recording content.
+    accumulated_thoughts: String,
+    thought_chunks: u64,
+    thought_chars: u64,
+    /// `user_message_chunk` text — only stored when recording content.
+    accumulated_input: String,
+    /// Chunk bytes left out of the buffers above by `--max-attr-length`, counted so
+    /// the truncation marker reports the full size.
+    output_omitted: usize,
+    input_omitted: usize,
+    user_message_chunks: u64,
+    user_message_chars: u64,
+    /// Whether `gen_ai.input.messages` was set from the `session/prompt` params.
+    input_recorded: bool,
+    tool_calls: HashMap<String, ToolCallState>,
+    /// Current session mode, from `session/set_mode` or `current_mode_update`.
+    mode: Option<String>,
+    /// Current model id, from `session/set_model`.
+    model: Option<String>,
+    /// Tool calls that reported completed or failed during the current turn.
+    finished_tools: HashSet<String>,
+    /// Bytes of `session/update` lines received since the current turn started.
+    update_bytes: u64,
+    /// Model an update reported as serving the current turn.
+    response_model: Option<String>,
+    /// Prompts started in this session; the current turn's index is `turns - 1`.
+    turns: u64,
+    /// Tool calls started in the current turn, and how many of them are MCP-backed.
+    turn_tool_calls: u64,
+    turn_mcp_tool_calls: u64,
+    /// Last advertised slash command names, to skip repeated identical updates.
+    available_commands: Option<Vec<String>>,
+}
+
+impl SessionState {
+    /// Build `gen_ai.output.messages` from the turn's accumulated output, if any.
+    fn output_messages(&self, finish_reason: Option<&str>) -> Option<Value> {
+        let mut parts = Vec::new();
+        if !self.accumulated_thoughts.is_empty() {
+            parts.push(
+                serde_json::json!({"type": "reasoning", "content": &self.accumulated_thoughts}),
+            );
+        }
+        if !self.accumulated_output.is_empty() {
+            parts.push(serde_json::json!({"type": "text", "content": &self.accumulated_output}));
+        }
+        if parts.is_empty() {
+            return None;
+        }
+        let mut msg = serde_json::json!({"role": "assistant", "parts": parts});
+        if let Some(finish) = finish_reason {
+            msg["finish_reason"] = finish.into();
+        }
+        Some(serde_json::json!([msg]))
+    }
+}
+
+/// When the message being handled was seen: the clock in a live session, the recorded
+/// time when replaying. `instant` places it on the monotonic timeline for durations.
+#[derive(Debug, Clone, Copy)]
+struct Stamp {
+    time: SystemTime,
+    instant: Instant,
+}
+
+/// An open `execute_tool` span for a `tool_call`, keyed by toolCallId.
+struct ToolCallState {
+    span: opentelemetry::global::BoxedSpan,
+    span_context: SpanContext,
+    /// Paths from the tool call's `locations`, used to parent fs requests.
+    paths: Vec<String>,
+    /// Latest diff per path — agents resend the full content on each update.
+    diffs: BTreeMap<String, DiffRecord>,
+    /// Last status seen, so repeated updates don't emit `acp.tool.status` events.
+    status: String,
+    start: Instant,
+    in_progress_at: Option<Instant>,
+    /// Current title, set as `gen_ai.tool.name` at the end; agents often refine a
+    /// generic one in later updates.
+    title: Option<String>,
+    initial_title_recorded: bool,
+    /// Text streamed in `content` blocks, only kept when recording content.
+    output: String,
+    /// Streamed bytes that didn't fit in `output`.
+    output_omitted: usize,
+    output_chars: usize,
+    /// Set once `rawOutput` has been recorded as the result; it takes precedence.
+    raw_output_recorded: bool,
+    /// `rawInput` merged key-by-key across updates, only kept when recording content.
+    raw_input: Option<Value>,
+    /// `(server, tool)` once recognized as an MCP-backed tool.
+    mcp: Option<(String, String)>,
+    /// ACP `kind` (`other` until known), for `acp.tool.kind`.
+    kind: String,
+    /// Redaction and length limit for content attributes.
+    content: ContentFilter,
+}
+
+struct DiffRecord {
+    stat: acp::DiffStat,
+    /// Rendered diff, only kept when recording content.
+    text: Option<String>,
+}
+
+impl ToolCallState {
+    fn new(
+        span: opentelemetry::global::BoxedSpan,
+        kind: String,
+        status: &str,
+        content: ContentFilter,
+        stamp: Stamp,
+    ) -> Self {
+        let start = stamp.instant;
+        Self {
+            span_context: span.span_context().clone(),
+            span,
+            paths: Vec::new(),
+            diffs: BTreeMap::new(),
+            status: status.to_string(),
+            start,
+            in_progress_at: (status == "in_progress").then_some(start),
+            title: None,
+            initial_title_recorded: false,
+            output: String::new(),
+            output_omitted: 0,
+            output_chars: 0,
+            raw_output_recorded: false,
+            raw_input: None,
+            mcp: None,
+            kind,
+            content,
+        }
+    }
+
+    /// Rename the span for a new title, keeping the first one in `acp.tool.initial_title`.
+    fn record_title(&mut self, title: &str) {
+        match self.title.as_deref() {
+            Some(current) if current == title => return,
+            Some(current) if !self.initial_title_recorded => {
+                self.span
+                    .set_attribute(KeyValue::new("acp.tool.initial_title", current.to_string()));
+                self.initial_title_recorded = true;
+            }
+            _ => {}
+        }
+        self.span.update_name(format!("execute_tool {title}"));
+        self.title = Some(title.to_string());
+    }
+
+    /// Split MCP server and tool names out of the title; returns true the first time.
+    /// `end_at` sets them in place of the title.
+    fn record_mcp(&mut self, params: &Value) -> bool {
+        let detected = acp::detect_mcp_tool(params);
+        let first = detected.is_some() && self.mcp.is_none();
+        if let Some(mcp) = detected {
+            self.mcp = Some((mcp.server.to_string(), mcp.tool.to_string()));
+        }
+        first
+    }
+
+    fn record_locations(&mut self, params: &Value) {
+        for location in acp::extract_tool_call_locations(params)
+            .into_iter()
+            .flatten()
+        {
+            if let Some(path) = location.get("path").and_then(|p| p.as_str()) {
+                if !self.paths.iter().any(|p| p == path) {
+                    self.paths.push(path.to_string());
+                }
+            }
+        }
+    }
+
+    fn record_status(&mut self, status: &str, stamp: Stamp) {
+        if status.is_empty() || status == self.status {
+            return;
+        }
+        self.span.add_event_with_timestamp(
+            "acp.tool.status",
+            stamp.time,
+            vec![KeyValue::new("acp.tool.status", status.to_string())],
+        );
+        if status == "in_progress" && self.in_progress_at.is_none() {
+            self.in_progress_at = Some(stamp.instant);
+        }
+        self.status = status.to_string();
+    }
+
+    /// What `gen_ai.tool.name` is set to: the MCP tool, else the latest title.
+    fn name(&self) -> &str {
+        match &self.mcp {
+            Some((_, tool)) => tool,
+            None => self.title.as_deref().unwrap_or("unknown tool"),
+        }
+    }
+
+    fn end_at(mut self, timestamp: SystemTime) {
+        // Set at the end: a span started from a tool_call_update learns its kind late.
+        self.span.set_attribute(KeyValue::new(
+            "gen_ai.tool.type",
+            acp::map_tool_kind_to_type(&self.kind),
+        ));
+        self.span
+            .set_attribute(KeyValue::new("acp.tool.kind", self.kind.clone()));
+        self.span
+            .set_attribute(KeyValue::new("gen_ai.tool.name", self.name().to_string()));
+        if let Some((server, _)) = &self.mcp {
+            let title = self.title.as_deref().unwrap_or("unknown tool");
+            self.span
+                .set_attribute(KeyValue::new("acp.mcp.server", server.clone()));
+            self.span
+                .set_attribute(KeyValue::new("acp.tool.title", title.to_string()));
+        }
+        self.set_diff_summary();
+        if let Some(at) = self.in_progress_at {
+            let queued = at.duration_since(self.start).as_secs_f64();
+            self.span.set_attribute(KeyValue::new(
+                "acp.tool.queue_duration_ms",
+                (queued * 1000.0) as i64,
+            ));
+        }
+        if self.output_chars > 0 {
+            self.span.set_attribute(KeyValue::new(
+                "acp.tool.output.chars",
+                self.output_chars as i64,
+            ));
+        }
+        if let Some(raw) = self.raw_input {
+            self.content.set(
+                &mut self.span,
+                "gen_ai.tool.call.arguments",
+                raw.to_string(),
+            );
+        }
+        if !self.raw_output_recorded && !self.output.is_empty() {
+            self.content.set_within(
+                &mut self.span,
+                "gen_ai.tool.call.result",
+                self.output,
+                self.output_omitted,
+                MAX_TOOL_OUTPUT,
+            );
+        }
+        self.span.end_with_timestamp(timestamp);
+    }
+
+    /// Shallow-merge the update's `rawInput`; later keys overwrite earlier ones.
+    fn record_raw_input(&mut self, params: &Value, record: bool) {
+        if !record {
+            return;
+        }
+        let Some(raw) = params.get("update").and_then(|u| u.get("rawInput")) else {
+            return;
+        };
+        match (self.raw_input.as_mut(), raw) {
+            (Some(Value::Object(merged)), Value::Object(fields)) => {
+                for (key, value) in fields {
+                    merged.insert(key.clone(), value.clone());
+                }
+            }
+            _ => self.raw_input = Some(raw.clone()),
+        }
+    }
+
+    /// Append the update's text `content` blocks to the accumulated output.
+    fn record_output(&mut self, params: &Value, record: bool) {
+        for text in acp::extract_tool_call_content_text(params) {
+            self.output_chars += text.chars().count();
+            if record {
+                let cap = self.content.buffer_cap_within(MAX_TOOL_OUTPUT);
+                self.output_omitted += push_capped(&mut self.output, text, cap);
+            }
+        }
+    }
+
+    /// Merge the update's `diff` content blocks; `end` sets the summary attributes.
+    fn record_diffs(&mut self, params: &Value, record: bool) {
+        for diff in acp::extract_diffs(params) {
+            self.diffs.insert(
+                diff.path.to_string(),
+                DiffRecord {
+                    stat: diff.stat(),
+                    text: record.then(|| diff.render()),
+                },
+            );
+        }
+    }
+
+    /// Totals over the latest diff per path, and the rendered diffs when recording.
+    fn set_diff_summary(&mut self) {
+        if self.diffs.is_empty() {
+            return;
+        }
+        let added: usize = self.diffs.values().map(|d| d.stat.lines_added).sum();
+        let removed: usize = self.diffs.values().map(|d| d.stat.lines_removed).sum();
+        self.span.set_attribute(KeyValue::new(
+            "acp.tool.diff.files",
+            self.diffs.len() as i64,
+        ));
+        self.span
+            .set_attribute(KeyValue::new("acp.tool.diff.lines_added", added as i64));
+        self.span
+            .set_attribute(KeyValue::new("acp.tool.diff.lines_removed", removed as i64));
+        let text = self
+            .diffs
+            .values()
+            .filter_map(|d| d.text.as_deref())
+            .collect::<Vec<_>>()
+            .join("\n");
+        if !text.is_empty() {
+            self.content.set(
+                &mut self.span,
+                "acp.tool.diff",
+                truncate_utf8(&self.content.redact(text), MAX_DIFF_TEXT).to_string(),
+            );
+        }
+    }
 }
 
 struct PendingRequest {
@@ -22,23 +365,246 @@ struct PendingRequest {
     method: String,
     session_id: Option<String>,
     start: Instant,
+    /// Set for `session/request_permission` only.
+    permission: Option<PermissionRequest>,
+    /// Mode or model id a `session/set_mode` or `session/set_model` asks for; applied
+    /// on a success response.
+    requested: Option<String>,
+}
+
+/// Keys of requests whose span ended before their response, oldest first. Capped at
+/// [`MAX_ABANDONED_REQUESTS`] so a peer that never answers can't grow it.
+#[derive(Default)]
+struct AbandonedRequests(VecDeque<(Direction, String)>);
+
+impl AbandonedRequests {
+    fn insert(&mut self, key: (Direction, String)) {
+        if self.0.len() == MAX_ABANDONED_REQUESTS {
+            self.0.pop_front();
+        }
+        self.0.push_back(key);
+    }
+
+    /// Whether `key` was remembered; it is forgotten either way.
+    fn remove(&mut self, key: &(Direction, String)) -> bool {
+        match self.0.iter().position(|k| k == key) {
+            Some(i) => self.0.remove(i).is_some(),
+            None => false,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+#[derive(Default)]
+struct PermissionRequest {
+    /// Offered `(optionId, kind)` pairs.
+    options: Vec<(String, String)>,
+    tool_call_id: Option<String>,
+}
+
+/// A `--meta-attr path=attribute` mapping: the `_meta` value at the dotted `path`
+/// becomes `attribute` on the span the message affects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetaAttr {
+    pub path: String,
+    pub attribute: String,
+}
+
+impl std::str::FromStr for MetaAttr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((path, attribute)) if !path.is_empty() && !attribute.is_empty() => Ok(Self {
+                path: path.to_string(),
+                attribute: attribute.to_string(),
+            }),
+            _ => Err(format!("expected PATH=ATTRIBUTE, got {s:?}")),
+        }
+    }
+}
+
+/// An `--ignore-method` name: exact, or a prefix when it ends in `*` (`terminal/*`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodPattern {
+    name: String,
+    prefix: bool,
+}
+
+impl MethodPattern {
+    pub fn matches(&self, method: &str) -> bool {
+        if self.prefix {
+            method.starts_with(&self.name)
+        } else {
+            method == self.name
+        }
+    }
+}
+
+impl std::str::FromStr for MethodPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, prefix) = match s.strip_suffix('*') {
+            Some(name) => (name, true),
+            None => (s, false),
+        };
+        if name.contains('*') || (name.is_empty() && !prefix) {
+            return Err(format!(
+                "expected a method name, optionally ending in *, got {s:?}"
+            ));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            prefix,
+        })
+    }
+}
+
+/// Which methods are traced: `--ignore-method` drops matches, `--only-method` keeps
+/// nothing else. Filtered messages are still forwarded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum MethodFilter {
+    #[default]
+    All,
+    Ignore(Vec<MethodPattern>),
+    Only(Vec<MethodPattern>),
+}
+
+impl MethodFilter {
+    pub fn traces(&self, method: &str) -> bool {
+        match self {
+            Self::All => true,
+            Self::Ignore(patterns) => !patterns.iter().any(|p| p.matches(method)),
+            Self::Only(patterns) => patterns.iter().any(|p| p.matches(method)),
+        }
+    }
+}
+
+/// Which kinds of content are recorded as span attributes (`--record-input`,
+/// `--record-output`, `--record-tool-args`, `--record-tool-results`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContentPolicy {
+    /// `gen_ai.input.messages`.
+    pub input: bool,
+    /// `gen_ai.output.messages` and plan entry titles.
+    pub output: bool,
+    /// `gen_ai.tool.call.arguments` and full tool call locations.
+    pub tool_args: bool,
+    /// `gen_ai.tool.call.result` and `acp.tool.diff`.
+    pub tool_results: bool,
+}
+
+impl ContentPolicy {
+    /// Everything, as with `--record-content`.
+    pub fn all() -> Self {
+        Self {
+            input: true,
+            output: true,
+            tool_args: true,
+            tool_results: true,
+        }
+    }
+}
+
+/// Running totals behind [`SpanManager::summary`]; kept across handshakes.
+#[derive(Debug, Default)]
+struct Totals {
+    prompts: u64,
+    turn_durations: Vec<f64>,
+    ttfts: Vec<f64>,
+    tool_calls: BTreeMap<String, ToolCounts>,
+    output_chars: u64,
+}
+
+impl Totals {
+    /// Count a tool call as it ends, under its final name: agents often refine a
+    /// generic title, and a call started from an update learns it late. `failed`
+    /// matches the error status its span ends with.
+    fn count_tool(&mut self, tool: &ToolCallState, failed: bool) {
+        let counts = self.tool_calls.entry(tool.name().to_string()).or_default();
+        counts.calls += 1;
+        counts.failed += u64::from(failed);
+    }
 }
 
 pub struct SpanManager {
     tracer: opentelemetry::global::BoxedTracer,
     duration_histogram: Histogram<f64>,
     ttft_histogram: Histogram<f64>,
-    record_content: bool,
+    cancel_histogram: Histogram<f64>,
+    token_usage_histogram: Histogram<u64>,
+    /// Per turn: `agent_message_chunk` notifications and their characters.
+    output_chunks_histogram: Histogram<u64>,
+    output_chars_histogram: Histogram<u64>,
+    orphan_response_counter: Counter<u64>,
+    /// Proxy health: lines seen, lines that weren't JSON-RPC, and time spent on each.
+    line_counter: Counter<u64>,
+    parse_failure_counter: Counter<u64>,
+    processing_histogram: Histogram<f64>,
+    truncated_turn_counter: Counter<u64>,
+    implicit_session_counter: Counter<u64>,
+    duplicate_request_counter: Counter<u64>,
+    protocol_violation_counter: Counter<u64>,
+    record: ContentPolicy,
+    meta_attrs: Vec<MetaAttr>,
+    method_filter: MethodFilter,
+    /// Filtered-out requests awaiting their response (so it isn't counted as an
+    /// orphan), with their method.
+    ignored_requests: HashMap<(Direction, String), String>,
     agent_name: Option<String>,
     agent_version: Option<String>,
+    /// `--agent-name`/`--agent-version`: used when `initialize` doesn't report `agentInfo`.
+    fallback_agent: Option<(String, Option<String>)>,
+    /// The agent's working directory, for `acp.agent.cwd` on the root span.
+    agent_cwd: Option<String>,
     client_name: Option<String>,
     client_version: Option<String>,
     protocol_version: Option<i64>,
+    /// `protocolVersion` the editor asked for in `initialize`.
+    requested_protocol_version: Option<i64>,
+    /// Whether an `initialize` response has been seen.
+    initialized: bool,
+    /// Whether the first line seen was an `initialize` request, i.e. the proxy saw the
+    /// connection from the start rather than attaching mid-session; unset before any.
+    from_start: Option<bool>,
+    /// Set when a `session/prompt` response ends a turn; see [`Self::take_turn_completed`].
+    turn_completed: bool,
+    totals: Totals,
+    /// Auth method ids from the initialize result, once seen.
+    auth_methods: Option<Vec<String>>,
     sessions: HashMap<String, SessionState>,
-    pending: HashMap<String, PendingRequest>,
+    /// In-flight requests keyed by (sender, id) — each side numbers its own requests.
+    pending: HashMap<(Direction, String), PendingRequest>,
+    /// Requests ended by `--request-timeout`; a late response is counted as an orphan.
+    timed_out: AbandonedRequests,
+    /// Prompts ended by a newer `session/prompt`; a late response is dropped.
+    superseded: AbandonedRequests,
+    request_timeout: Option<Duration>,
+    /// Limit for `session/prompt`, which `request_timeout` doesn't cover.
+    prompt_timeout: Option<Duration>,
+    /// `--redact-pattern` and `--max-attr-length`, applied to every content attribute.
+    content: ContentFilter,
+    /// Recorded time of the latest message from [`Self::process_message_at`].
+    message_time: Option<SystemTime>,
+    /// The first recorded time and the instant it was replayed, to map recorded times
+    /// onto `Instant`s.
+    replay_epoch: Option<(SystemTime, Instant)>,
     /// Root span for the entire ACP session — parents all other spans.
     session_span: Option<opentelemetry::global::BoxedSpan>,
     session_span_context: Option<SpanContext>,
+    /// Plan updates that arrived while no prompt span was open.
+    unattached_plan_updates: u64,
+    /// Responses that matched no pending request.
+    orphan_responses: u64,
+    /// Latest advertised slash command names, set on the root span when it ends.
+    available_commands: Option<Vec<String>>,
+    /// `initialize` requests seen; more than one means the agent was restarted.
+    handshakes: u64,
+    last_orphan_warning: Option<Instant>,
 }
 
 impl SpanManager {
@@ -57,84 +623,725 @@ impl SpanManager {
             .with_unit("s")
             .with_description("Time to generate first token")
             .build();
+        let cancel_histogram = meter
+            .f64_histogram("acp.cancel.duration")
+            .with_unit("s")
+            .with_description("Time from session/cancel to the cancelled prompt response")
+            .build();
+        let token_usage_histogram = meter
+            .u64_histogram("gen_ai.client.token.usage")
+            .with_unit("{token}")
+            .with_description("Number of input and output tokens used")
+            .build();
+        let output_chunks_histogram = meter
+            .u64_histogram("acp.output.chunks")
+            .with_unit("{chunk}")
+            .with_description("Agent message chunks streamed in a prompt turn")
+            .build();
+        let output_chars_histogram = meter
+            .u64_histogram("acp.output.chars")
+            .with_unit("{char}")
+            .with_description("Characters of agent message text streamed in a prompt turn")
+            .build();
+        let orphan_response_counter = meter
+            .u64_counter("acp.orphan_responses")
+            .with_description("Responses with no matching in-flight request")
+            .build();
+        let line_counter = meter
+            .u64_counter("acp.proxy.lines")
+            .with_unit("{line}")
+            .with_description("Lines relayed between editor and agent")
+            .build();
+        let parse_failure_counter = meter
+            .u64_counter("acp.proxy.parse_failures")
+            .with_unit("{line}")
+            .with_description("Relayed lines that weren't JSON-RPC messages")
+            .build();
+        let processing_histogram = meter
+            .f64_histogram("acp.proxy.processing_duration")
+            .with_unit("s")
+            .with_description("Time the proxy spent tracing one relayed line")
+            .with_boundaries(vec![
+                0.00001, 0.000025, 0.00005, 0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01,
+                0.025, 0.05, 0.1,
+            ])
+            .build();
+        let truncated_turn_counter = meter
+            .u64_counter("acp.truncated_turns")
+            .with_description("Prompt turns cut short by max_tokens or max_turn_requests")
+            .build();
+        let implicit_session_counter = meter
+            .u64_counter("acp.sessions.implicit")
+            .with_description("Sessions first seen through a session/update notification")
+            .build();
+        let duplicate_request_counter = meter
+            .u64_counter("acp.duplicate_request_ids")
+            .with_description(
+                "Requests that reused the id of a request still awaiting its response",
+            )
+            .build();
+        let protocol_violation_counter = meter
+            .u64_counter("acp.protocol_violations")
+            .with_description("Messages that broke an ACP protocol rule, by rule")
+            .build();
 
         Self {
             tracer,
             duration_histogram,
             ttft_histogram,
-            record_content,
+            cancel_histogram,
+            token_usage_histogram,
+            output_chunks_histogram,
+            output_chars_histogram,
+            orphan_response_counter,
+            line_counter,
+            parse_failure_counter,
+            processing_histogram,
+            truncated_turn_counter,
+            implicit_session_counter,
+            duplicate_request_counter,
+            protocol_violation_counter,
+            record: if record_content {
+                ContentPolicy::all()
+            } else {
+                ContentPolicy::default()
+            },
+            meta_attrs: Vec::new(),
+            method_filter: MethodFilter::All,
+            ignored_requests: HashMap::new(),
             agent_name: None,
             agent_version: None,
+            fallback_agent: None,
+            agent_cwd: None,
             client_name: None,
             client_version: None,
             protocol_version: None,
+            requested_protocol_version: None,
+            initialized: false,
+            from_start: None,
+            turn_completed: false,
+            totals: Totals::default(),
+            auth_methods: None,
             sessions: HashMap::new(),
             pending: HashMap::new(),
+            timed_out: AbandonedRequests::default(),
+            superseded: AbandonedRequests::default(),
+            request_timeout: None,
+            prompt_timeout: None,
+            content: ContentFilter::default(),
+            message_time: None,
+            replay_epoch: None,
             session_span: None,
             session_span_context: None,
+            unattached_plan_updates: 0,
+            orphan_responses: 0,
+            available_commands: None,
+            handshakes: 0,
+            last_orphan_warning: None,
         }
     }
 
+    /// Record only the kinds of content `policy` allows, replacing the all-or-nothing
+    /// `record_content` given to [`Self::new`].
+    pub fn with_content_policy(mut self, policy: ContentPolicy) -> Self {
+        self.record = policy;
+        self
+    }
+
+    /// Lift `_meta` fields onto spans, applied regardless of content recording.
+    pub fn with_meta_attrs(mut self, meta_attrs: Vec<MetaAttr>) -> Self {
+        self.meta_attrs = meta_attrs;
+        self
+    }
+
+    /// Skip requests and notifications whose method matches one of `patterns`: no
+    /// span, no pending entry, no metrics, and their responses are dropped silently.
+    pub fn with_ignored_methods(mut self, patterns: Vec<MethodPattern>) -> Self {
+        self.method_filter = MethodFilter::Ignore(patterns);
+        self
+    }
+
+    /// Skip every request and notification whose method matches none of `patterns`,
+    /// as [`Self::with_ignored_methods`] skips matches. Replaces an ignore list.
+    pub fn with_only_methods(mut self, patterns: Vec<MethodPattern>) -> Self {
+        self.method_filter = MethodFilter::Only(patterns);
+        self
+    }
+
+    fn is_ignored(&self, method: &str) -> bool {
+        !self.method_filter.traces(method)
+    }
+
+    /// End requests left unanswered longer than `timeout`; see [`Self::expire_requests`].
+    /// Prompts have their own limit ([`Self::with_prompt_timeout`]), and permission
+    /// requests, which wait on the user, never time out.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// End `session/prompt` turns left unanswered longer than `timeout`, with their open
+    /// tool calls; see [`Self::expire_requests`].
+    pub fn with_prompt_timeout(mut self, timeout: Duration) -> Self {
+        self.prompt_timeout = Some(timeout);
+        self
+    }
+
+    /// Cut content attributes (messages, tool arguments and results, diffs) to
+    /// `max_bytes` on a character boundary, marking the span `acp.content.truncated`.
+    pub fn with_max_attr_length(mut self, max_bytes: usize) -> Self {
+        self.content.max_len = Some(max_bytes);
+        self
+    }
+
+    /// Replace every match of `patterns` in content attributes with `[REDACTED]`
+    /// before they are set (and before `--max-attr-length` cuts them).
+    pub fn with_redact_patterns(mut self, patterns: Vec<Regex>) -> Self {
+        self.content.redact = patterns;
+        self
+    }
+
+    /// Name (and version) the agent when its `initialize` response has no `agentInfo`;
+    /// a reported `agentInfo` still wins.
+    pub fn with_agent_info(mut self, name: String, version: Option<String>) -> Self {
+        self.fallback_agent = Some((name, version));
+        self.reset_agent_info();
+        self
+    }
+
+    /// Record the directory the agent runs in as `acp.agent.cwd` on each root span.
+    pub fn with_agent_cwd(mut self, cwd: String) -> Self {
+        self.agent_cwd = Some(cwd);
+        self
+    }
+
+    fn reset_agent_info(&mut self) {
+        self.agent_name = self.fallback_agent.as_ref().map(|(name, _)| name.clone());
+        self.agent_version = self.fallback_agent.as_ref().and_then(|(_, v)| v.clone());
+    }
+
+    /// Record the negotiated protocol version on the initialize span and the root, and
+    /// flag a version other than the requested one, or one the extractors don't know.
+    fn record_protocol_version(
+        &mut self,
+        span: &mut opentelemetry::global::BoxedSpan,
+        negotiated: i64,
+        stamp: Stamp,
+    ) {
+        let kv = KeyValue::new("acp.protocol.version", negotiated);
+        if let Some(root) = self.session_span.as_mut() {
+            root.set_attribute(kv.clone());
+        }
+        span.set_attribute(kv);
+        if let Some(requested) = self.requested_protocol_version {
+            if requested != negotiated {
+                tracing::warn!(
+                    requested,
+                    negotiated,
+                    "agent negotiated a different ACP protocol version"
+                );
+                span.add_event_with_timestamp(
+                    "acp.protocol.version_mismatch",
+                    stamp.time,
+                    vec![
+                        KeyValue::new("acp.protocol.version.requested", requested),
+                        KeyValue::new("acp.protocol.version", negotiated),
+                    ],
+                );
+            }
+        }
+        if !acp::KNOWN_PROTOCOL_VERSIONS.contains(&negotiated) {
+            tracing::warn!(
+                version = negotiated,
+                known = ?acp::KNOWN_PROTOCOL_VERSIONS,
+                may_be_missing = %acp::VERSION_DEPENDENT_FEATURES.join(", "),
+                "unknown ACP protocol version; traces may be incomplete"
+            );
+        }
+    }
+
+    /// Totals for the exit report, over everything processed so far.
+    pub fn summary(&self) -> Summary {
+        Summary {
+            prompts: self.totals.prompts,
+            turn_duration: Stats::of(&self.totals.turn_durations),
+            time_to_first_token: Stats::of(&self.totals.ttfts),
+            tool_calls: self.totals.tool_calls.clone(),
+            output_chars: self.totals.output_chars,
+            agent_exit_code: None,
+        }
+    }
+
+    /// Whether a prompt turn ended since the last call, e.g. to flush its spans
+    /// (`--flush-on-prompt`).
+    pub fn take_turn_completed(&mut self) -> bool {
+        std::mem::take(&mut self.turn_completed)
+    }
+
+    /// The span out-of-band output (such as agent stderr) belongs to: the most recently
+    /// started open prompt, else the root session span.
+    pub fn active_span_context(&self) -> Option<SpanContext> {
+        self.sessions
+            .values()
+            .filter(|s| s.prompt_span.is_some())
+            .max_by_key(|s| s.prompt_start)
+            .and_then(|s| s.prompt_span_context.clone())
+            .or_else(|| self.session_span_context.clone())
+    }
+
+    /// End the spans of requests pending longer than their timeout as of `now`.
+    /// Call periodically; a no-op without a timeout.
+    pub fn expire_requests(&mut self, now: Instant) {
+        let stamp = self.stamp();
+        let expired: Vec<_> = self
+            .pending
+            .iter()
+            .filter(|(_, p)| {
+                let timeout = match p.method.as_str() {
+                    "session/prompt" => self.prompt_timeout,
+                    "session/request_permission" => None,
+                    _ => self.request_timeout,
+                };
+                timeout.is_some_and(|t| now.saturating_duration_since(p.start) >= t)
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            let Some(pending) = self.pending.remove(&key) else {
+                continue;
+            };
+            tracing::warn!(method = %pending.method, id = %key.1, "request timed out");
+            // session/prompt keeps its span on the session rather than the request, and
+            // takes the turn's unfinished tool calls with it.
+            let mut span = pending.span;
+            if let Some(session) = pending
+                .session_id
+                .as_ref()
+                .and_then(|sid| self.sessions.get_mut(sid))
+                .filter(|_| pending.method == "session/prompt")
+            {
+                span = session.prompt_span.take();
+                session.prompt_request = None;
+                for (_, mut tool) in session.tool_calls.drain() {
+                    self.totals.count_tool(&tool, true);
+                    tool.span.set_status(Status::error("prompt timed out"));
+                    tool.end_at(stamp.time);
+                }
+            }
+            if let Some(mut span) = span {
+                span.set_status(Status::error("request timed out"));
+                span.set_attribute(KeyValue::new("error.type", "timeout"));
+                span.set_attribute(KeyValue::new("acp.request.timed_out", true));
+                span.end_with_timestamp(stamp.time);
+            }
+            self.timed_out.insert(key);
+        }
+    }
+
+    /// Process a message seen at `at` rather than now, as when replaying a recorded
+    /// transcript: spans start, end, and measure durations on the recorded timeline.
+    pub fn process_message_at(&mut self, direction: Direction, line: &str, at: SystemTime) {
+        self.replay_epoch.get_or_insert((at, Instant::now()));
+        self.message_time = Some(at);
+        self.process_message(direction, line);
+    }
+
+    /// The current message's time; after a replay, the last recorded time.
+    fn stamp(&self) -> Stamp {
+        match (self.message_time, self.replay_epoch) {
+            (Some(time), Some((epoch, instant))) => Stamp {
+                time,
+                instant: match time.duration_since(epoch) {
+                    Ok(later) => instant + later,
+                    Err(e) => instant.checked_sub(e.duration()).unwrap_or(instant),
+                },
+            },
+            _ => Stamp {
+                time: SystemTime::now(),
+                instant: Instant::now(),
+            },
+        }
+    }
+
+    /// A span builder starting at the current message's time.
+    fn span_builder(&self, name: impl Into<Cow<'static, str>>) -> SpanBuilder {
+        self.tracer
+            .span_builder(name)
+            .with_start_time(self.stamp().time)
+    }
+
     pub fn process_message(&mut self, direction: Direction, line: &str) {
+        let started = Instant::now();
+        let attrs = [KeyValue::new("acp.direction", direction.as_str())];
+        self.line_counter.add(1, &attrs);
+        self.handle_message(direction, line);
+        self.processing_histogram
+            .record(started.elapsed().as_secs_f64(), &attrs);
+    }
+
+    fn handle_message(&mut self, direction: Direction, line: &str) {
         let msg = match acp::parse(line) {
             Some(m) => m,
-            None => return,
+            None => {
+                self.parse_failure_counter
+                    .add(1, &[KeyValue::new("acp.direction", direction.as_str())]);
+                return;
+            }
         };
+        self.from_start.get_or_insert_with(
+            || matches!(&msg, MessageType::Request { method, .. } if method == "initialize"),
+        );
+        let meta = self.meta_attributes(&msg);
+        let violations = acp::protocol_violations(&self.protocol_context(&msg));
 
         match msg {
             MessageType::Request { id, method, params } => {
+                let key = (direction, id.to_string());
+                if self.is_ignored(&method) {
+                    // Kept spans still need their root.
+                    if method == "initialize" {
+                        self.handshakes += 1;
+                        self.start_session_span();
+                    }
+                    self.ignored_requests.insert(key, method.to_string());
+                    return;
+                }
                 self.handle_request(direction, id, &method, &params);
+                self.set_request_attributes(&key, &meta);
+                let size = KeyValue::new("acp.request.size_bytes", line.len() as i64);
+                self.set_request_attributes(&key, &[size]);
+                let session_id = acp::extract_session_id(&params);
+                self.record_violations(&violations, Some(&key), session_id);
             }
             MessageType::Response { id, result, error } => {
-                self.handle_response(id, result.as_ref(), error.as_ref());
+                // Before handling, while the request's span is still open.
+                let key = (direction.reverse(), id.to_string());
+                if let Some(method) = self.ignored_requests.remove(&key) {
+                    self.initialized |= method == "initialize" && result.is_some();
+                    return;
+                }
+                self.set_request_attributes(&key, &meta);
+                let size = KeyValue::new("acp.response.size_bytes", line.len() as i64);
+                self.set_request_attributes(&key, &[size]);
+                self.record_violations(&violations, Some(&key), None);
+                self.handle_response(direction, id, result.as_ref(), error.as_ref());
             }
             MessageType::Notification { method, params } => {
+                // An ignored notification leaves no trace on any span.
+                if self.is_ignored(&method) {
+                    return;
+                }
+                // A completing tool update ends its span; a tool_call starts one.
+                let applied = self.set_notification_attributes(&params, &meta, false);
+                self.record_violations(&violations, None, acp::extract_session_id(&params));
                 self.handle_notification(direction, &method, &params);
+                if !applied {
+                    self.set_notification_attributes(&params, &meta, true);
+                }
+                if method == "session/update" {
+                    let session =
+                        acp::extract_session_id(&params).and_then(|sid| self.sessions.get_mut(sid));
+                    if let Some(session) = session {
+                        session.update_bytes += line.len() as u64;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Attributes for the configured `--meta-attr` paths found in the message's `_meta`.
+    fn meta_attributes(&self, msg: &MessageType) -> Vec<KeyValue> {
+        if self.meta_attrs.is_empty() {
+            return Vec::new();
+        }
+        let metas = acp::message_meta(msg);
+        self.meta_attrs
+            .iter()
+            .filter_map(|m| {
+                let value = metas
+                    .iter()
+                    .find_map(|meta| acp::lookup_path(meta, &m.path))?;
+                let value: opentelemetry::Value = match value {
+                    Value::Null => return None,
+                    Value::Bool(b) => (*b).into(),
+                    Value::Number(n) => match n.as_i64() {
+                        Some(i) => i.into(),
+                        None => n.as_f64()?.into(),
+                    },
+                    Value::String(s) => s.clone().into(),
+                    other => other.to_string().into(),
+                };
+                Some(KeyValue::new(m.attribute.clone(), value))
+            })
+            .collect()
+    }
+
+    /// Span of the pending request under `key` — the prompt span for `session/prompt`.
+    fn request_span_mut(
+        &mut self,
+        key: &(Direction, String),
+    ) -> Option<&mut opentelemetry::global::BoxedSpan> {
+        let pending = self.pending.get_mut(key)?;
+        match pending.span.as_mut() {
+            Some(span) => Some(span),
+            None => pending
+                .session_id
+                .as_ref()
+                .and_then(|sid| self.sessions.get_mut(sid))
+                .and_then(|s| s.prompt_span.as_mut()),
+        }
+    }
+
+    /// Set attributes on the span of the request under `key`.
+    /// Used for `--meta-attr` values and payload sizes.
+    fn set_request_attributes(&mut self, key: &(Direction, String), attrs: &[KeyValue]) {
+        if attrs.is_empty() {
+            return;
+        }
+        if let Some(span) = self.request_span_mut(key) {
+            for kv in attrs {
+                span.set_attribute(kv.clone());
+            }
+        }
+    }
+
+    /// State relevant to the protocol rules, as of just before `msg` is handled.
+    fn protocol_context<'a>(&self, msg: &'a MessageType) -> acp::ProtocolContext<'a> {
+        let mut cx = acp::ProtocolContext {
+            initialized: self.initialized,
+            from_start: self.from_start == Some(true),
+            ..Default::default()
+        };
+        match msg {
+            MessageType::Request { method, .. } => cx.method = Some(method),
+            MessageType::Response { result, error, .. } => {
+                cx.has_result = result.is_some();
+                cx.has_error = error.is_some();
+            }
+            MessageType::Notification { method, params } => {
+                cx.method = Some(method);
+                if method == "session/update" {
+                    cx.update_type = acp::extract_update_type(params);
+                    let session =
+                        acp::extract_session_id(params).and_then(|s| self.sessions.get(s));
+                    if let Some(session) = session {
+                        cx.prompted = session.turns > 0;
+                        cx.turn_open = session.prompt_span.is_some();
+                        cx.tool_finished = acp::extract_tool_call_id(params)
+                            .is_some_and(|id| session.finished_tools.contains(id));
+                    }
+                }
             }
         }
+        cx
+    }
+
+    /// Count protocol violations and add an `acp.protocol_violation` event for each to
+    /// the request's span, else the session's prompt span, else the root span.
+    fn record_violations(
+        &mut self,
+        rules: &[&'static str],
+        key: Option<&(Direction, String)>,
+        session_id: Option<&str>,
+    ) {
+        if rules.is_empty() {
+            return;
+        }
+        let stamp = self.stamp();
+        for rule in rules {
+            tracing::debug!(rule, "protocol violation");
+            self.protocol_violation_counter
+                .add(1, &[KeyValue::new("acp.protocol.rule", *rule)]);
+        }
+        let has_request_span = key.is_some_and(|k| self.request_span_mut(k).is_some());
+        let span = match key {
+            Some(key) if has_request_span => self.request_span_mut(key),
+            _ => session_id
+                .and_then(|sid| self.sessions.get_mut(sid))
+                .and_then(|s| s.prompt_span.as_mut())
+                .or(self.session_span.as_mut()),
+        };
+        if let Some(span) = span {
+            for rule in rules {
+                span.add_event_with_timestamp(
+                    "acp.protocol_violation",
+                    stamp.time,
+                    vec![KeyValue::new("acp.protocol.rule", *rule)],
+                );
+            }
+        }
+    }
+
+    /// Set attributes on the tool span a notification refers to, if open; with
+    /// `fallback`, otherwise on the session's prompt span or the root span.
+    fn set_notification_attributes(
+        &mut self,
+        params: &Value,
+        attrs: &[KeyValue],
+        fallback: bool,
+    ) -> bool {
+        if attrs.is_empty() {
+            return true;
+        }
+        let session = acp::extract_session_id(params).and_then(|sid| self.sessions.get_mut(sid));
+        let span = match (session, acp::extract_tool_call_id(params)) {
+            (Some(session), Some(tool_call_id))
+                if session.tool_calls.contains_key(tool_call_id) =>
+            {
+                session
+                    .tool_calls
+                    .get_mut(tool_call_id)
+                    .map(|t| &mut t.span)
+            }
+            _ if !fallback => None,
+            (Some(session), _) if session.prompt_span.is_some() => session.prompt_span.as_mut(),
+            _ => self.session_span.as_mut(),
+        };
+        match span {
+            Some(span) => {
+                for kv in attrs {
+                    span.set_attribute(kv.clone());
+                }
+                true
+            }
+            None => false,
+        }
     }
 
     fn handle_request(&mut self, direction: Direction, id: Value, method: &str, params: &Value) {
-        tracing::debug!(direction = ?direction, method = %method, "request");
+        let stamp = self.stamp();
+        tracing::debug!(
+            direction = ?direction,
+            method = %method,
+            session = acp::extract_session_id(params),
+            "request"
+        );
+
+        // A reused id would silently replace the earlier request: end that one first.
+        let key = (direction, id.to_string());
+        if let Some(previous) = self.pending.remove(&key) {
+            tracing::warn!(
+                id = %key.1,
+                direction = ?direction,
+                previous = %previous.method,
+                method = %method,
+                "request id reused while still pending"
+            );
+            self.duplicate_request_counter
+                .add(1, &[KeyValue::new("acp.direction", direction.as_str())]);
+            let session = previous
+                .session_id
+                .as_ref()
+                .and_then(|sid| self.sessions.get_mut(sid))
+                .filter(|s| s.prompt_request.as_ref() == Some(&key));
+            let span = match previous.span {
+                Some(span) => Some(span),
+                None => session.and_then(|s| {
+                    s.prompt_request = None;
+                    s.prompt_span.take()
+                }),
+            };
+            if let Some(mut span) = span {
+                span.set_status(Status::error("request id reused before its response"));
+                span.set_attribute(KeyValue::new("acp.request.superseded", true));
+                span.end_with_timestamp(stamp.time);
+            }
+        }
 
         match method {
             "initialize" => {
+                // A second handshake means the agent restarted (or the editor retried):
+                // close out everything from the previous one and start a new trace.
+                if self.session_span.is_some() {
+                    self.end_all("agent restarted", "agent restarted");
+                    self.session_span_context = None;
+                    self.reset_agent_info();
+                    self.client_name = None;
+                    self.client_version = None;
+                    self.protocol_version = None;
+                    self.requested_protocol_version = None;
+                    self.auth_methods = None;
+                    self.initialized = false;
+                }
+                self.handshakes += 1;
                 if let Some((name, version)) = acp::extract_client_info(params) {
                     self.client_name = Some(name.to_string());
                     self.client_version = version.map(|v| v.to_string());
                 }
-                // Create the root session span that parents everything.
-                if self.session_span.is_none() {
-                    let root = self
-                        .tracer
-                        .span_builder("acp_session")
-                        .with_kind(SpanKind::Internal)
-                        .with_attributes(vec![
-                            KeyValue::new("acp.method.name", "session"),
-                            KeyValue::new("network.transport", "pipe"),
-                        ])
-                        .start(&self.tracer);
-                    self.session_span_context = Some(root.span_context().clone());
-                    self.session_span = Some(root);
+                self.start_session_span();
+                let capabilities =
+                    client_capability_attributes(&acp::extract_client_capabilities(params));
+                if let Some(root) = self.session_span.as_mut() {
+                    for kv in &capabilities {
+                        root.set_attribute(kv.clone());
+                    }
+                }
+                let mut attrs = vec![
+                    KeyValue::new("rpc.system", "jsonrpc"),
+                    KeyValue::new("rpc.method", "initialize"),
+                    KeyValue::new("acp.method.name", "initialize"),
+                    KeyValue::new("network.transport", "pipe"),
+                    KeyValue::new("acp.direction", direction.as_str()),
+                ];
+                attrs.extend(capabilities);
+                self.requested_protocol_version = acp::extract_protocol_version(params);
+                if let Some(requested) = self.requested_protocol_version {
+                    let kv = KeyValue::new("acp.protocol.version.requested", requested);
+                    if let Some(root) = self.session_span.as_mut() {
+                        root.set_attribute(kv.clone());
+                    }
+                    attrs.push(kv);
                 }
                 let span = self.start_under_root(
-                    self.tracer
-                        .span_builder("initialize")
-                        .with_kind(SpanKind::Internal)
-                        .with_attributes(vec![
-                            KeyValue::new("rpc.system", "jsonrpc"),
-                            KeyValue::new("rpc.method", "initialize"),
-                            KeyValue::new("acp.method.name", "initialize"),
-                            KeyValue::new("network.transport", "pipe"),
-                        ]),
+                    self.span_builder("initialize")
+                        .with_kind(request_span_kind(direction))
+                        .with_attributes(attrs),
+                );
+                self.pending.insert(
+                    (direction, id.to_string()),
+                    PendingRequest {
+                        span: Some(span),
+                        method: method.to_string(),
+                        session_id: None,
+                        start: stamp.instant,
+                        permission: None,
+                        requested: None,
+                    },
+                );
+            }
+            "authenticate" => {
+                // Params may carry credentials — never record them, whatever the content policy.
+                let mut attrs = vec![
+                    KeyValue::new("rpc.system", "jsonrpc"),
+                    KeyValue::new("rpc.method", "authenticate"),
+                    KeyValue::new("acp.method.name", "authenticate"),
+                    KeyValue::new("network.transport", "pipe"),
+                    KeyValue::new("acp.direction", direction.as_str()),
+                    KeyValue::new("jsonrpc.request.id", id.to_string()),
+                ];
+                if let Some(method_id) = acp::extract_auth_method_id(params) {
+                    attrs.push(KeyValue::new("acp.auth.method_id", method_id.to_string()));
+                    if let Some(offered) = &self.auth_methods {
+                        attrs.push(KeyValue::new(
+                            "acp.auth.method_offered",
+                            offered.iter().any(|m| m == method_id),
+                        ));
+                    }
+                }
+                let span = self.start_under_root(
+                    self.span_builder("authenticate")
+                        .with_kind(request_span_kind(direction))
+                        .with_attributes(attrs),
                 );
                 self.pending.insert(
-                    id.to_string(),
+                    (direction, id.to_string()),
                     PendingRequest {
                         span: Some(span),
                         method: method.to_string(),
                         session_id: None,
-                        start: Instant::now(),
+                        start: stamp.instant,
+                        permission: None,
+                        requested: None,
                     },
                 );
             }
@@ -151,6 +1358,7 @@ impl SpanManager {
                     KeyValue::new("gen_ai.conversation.id", session_id.clone()),
                     KeyValue::new("acp.method.name", "session/prompt"),
                     KeyValue::new("network.transport", "pipe"),
+                    KeyValue::new("acp.direction", direction.as_str()),
                 ];
                 if let Some(ref name) = self.agent_name {
                     attrs.push(KeyValue::new("gen_ai.provider.name", format!("acp.{name}")));
@@ -166,49 +1374,274 @@ impl SpanManager {
                 if let Some(ref v) = self.client_version {
                     attrs.push(KeyValue::new("acp.client.version", v.clone()));
                 }
-                if self.record_content {
-                    if let Some(text) = acp::extract_prompt_text(params) {
-                        let input_msg = serde_json::json!([{
-                            "role": "user",
-                            "parts": [{"type": "text", "content": text}]
-                        }]);
-                        attrs.push(KeyValue::new(
+                if let Some(session) = self.sessions.get(&session_id) {
+                    if let Some(ref mode) = session.mode {
+                        attrs.push(KeyValue::new("acp.session.mode", mode.clone()));
+                    }
+                    if let Some(ref model) = session.model {
+                        attrs.push(KeyValue::new("gen_ai.request.model", model.clone()));
+                    }
+                }
+                let stats = acp::prompt_stats(params);
+                attrs.push(KeyValue::new("acp.prompt.blocks", stats.blocks as i64));
+                attrs.push(KeyValue::new(
+                    "acp.prompt.text_chars",
+                    stats.text_chars as i64,
+                ));
+                attrs.push(KeyValue::new(
+                    "acp.prompt.resource_blocks",
+                    stats.resource_blocks as i64,
+                ));
+                attrs.push(KeyValue::new(
+                    "acp.prompt.resource_chars",
+                    stats.resource_chars as i64,
+                ));
+                let uris = acp::extract_prompt_resource_uris(params);
+                if !uris.is_empty() {
+                    attrs.push(KeyValue::new(
+                        "acp.prompt.resources",
+                        join_capped(&uris, MAX_PROMPT_RESOURCES),
+                    ));
+                    attrs.push(KeyValue::new(
+                        "acp.prompt.resources.count",
+                        uris.len() as i64,
+                    ));
+                }
+                let media = acp::extract_prompt_media(params);
+                attrs.push(KeyValue::new("acp.prompt.images", stats.images as i64));
+                attrs.push(KeyValue::new("acp.prompt.audio", stats.audio as i64));
+                let image_mime_types = distinct_mime_types(&media, "image");
+                if !image_mime_types.is_empty() {
+                    attrs.push(KeyValue::new(
+                        "acp.prompt.image.mime_types",
+                        image_mime_types.join(","),
+                    ));
+                }
+                let turn_index = self.sessions.get(&session_id).map_or(0, |s| s.turns);
+                attrs.push(KeyValue::new("acp.turn.index", turn_index as i64));
+                let mut input_recorded = false;
+                if self.record.input {
+                    let text = acp::extract_prompt_text(params);
+                    if text.is_some() || !media.is_empty() {
+                        attrs.extend(self.content.attributes(
                             "gen_ai.input.messages",
-                            input_msg.to_string(),
+                            input_messages(text.as_deref().unwrap_or(""), &media).to_string(),
                         ));
+                        input_recorded = true;
+                    }
+                }
+                // Link back to the session's previous turn to make the conversation order explicit.
+                let links = self
+                    .sessions
+                    .get(&session_id)
+                    .and_then(|s| s.prompt_span_context.clone())
+                    .map(|previous| {
+                        vec![Link::new(
+                            previous,
+                            vec![KeyValue::new("acp.link.type", "previous_turn")],
+                            0,
+                        )]
+                    })
+                    .unwrap_or_default();
+                // A prompt still awaiting its response was abandoned: end it now and forget
+                // its request so a late response can't land on the new turn.
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    if let Some(mut previous) = session.prompt_span.take() {
+                        previous.set_status(Status::error(
+                            "superseded by a new session/prompt before its response",
+                        ));
+                        previous.set_attribute(KeyValue::new("acp.turn.outcome", "superseded"));
+                        previous.end_with_timestamp(stamp.time);
+                        for (_, mut tool) in session.tool_calls.drain() {
+                            self.totals.count_tool(&tool, true);
+                            tool.span.set_status(Status::error("prompt superseded"));
+                            tool.end_at(stamp.time);
+                        }
+                        if let Some(key) = session.prompt_request.take() {
+                            self.pending.remove(&key);
+                            self.superseded.insert(key);
+                        }
                     }
                 }
                 let span = self.start_under_root(
-                    self.tracer
-                        .span_builder(span_name)
-                        .with_kind(SpanKind::Client)
-                        .with_attributes(attrs),
+                    self.span_builder(span_name)
+                        .with_kind(request_span_kind(direction))
+                        .with_attributes(attrs)
+                        .with_links(links),
                 );
                 let span_context = span.span_context().clone();
-                let now = Instant::now();
-                self.sessions
-                    .entry(session_id.clone())
-                    .or_insert_with(|| SessionState {
-                        prompt_span: None,
-                        prompt_span_context: None,
-                        prompt_start: None,
-                        first_chunk_time: None,
-                        accumulated_output: String::new(),
-                        tool_spans: HashMap::new(),
-                    });
-                let session = self.sessions.get_mut(&session_id).unwrap();
+                let now = stamp.instant;
+                let session = self.sessions.entry(session_id.clone()).or_default();
                 session.prompt_span = Some(span);
                 session.prompt_span_context = Some(span_context);
                 session.prompt_start = Some(now);
+                session.prompt_request = Some((direction, id.to_string()));
+                session.cancelled_at = None;
+                session.turns = turn_index + 1;
+                self.totals.prompts += 1;
+                session.turn_tool_calls = 0;
+                session.turn_mcp_tool_calls = 0;
+                session.response_model = None;
+                session.update_bytes = 0;
+                session.finished_tools.clear();
                 session.first_chunk_time = None;
                 session.accumulated_output.clear();
+                session.output_chunks = 0;
+                session.output_chars = 0;
+                session.accumulated_thoughts.clear();
+                session.thought_chunks = 0;
+                session.thought_chars = 0;
+                session.accumulated_input.clear();
+                session.output_omitted = 0;
+                session.input_omitted = 0;
+                session.user_message_chunks = 0;
+                session.user_message_chars = 0;
+                session.input_recorded = input_recorded;
                 self.pending.insert(
-                    id.to_string(),
+                    (direction, id.to_string()),
                     PendingRequest {
                         span: None,
                         method: method.to_string(),
                         session_id: Some(session_id),
                         start: now,
+                        permission: None,
+                        requested: None,
+                    },
+                );
+            }
+            "session/set_mode" => {
+                let session_id = acp::extract_session_id(params).map(|s| s.to_string());
+                let mode_id = acp::extract_mode_id(params).map(|m| m.to_string());
+                let mut attrs = vec![
+                    KeyValue::new("rpc.system", "jsonrpc"),
+                    KeyValue::new("rpc.method", "session/set_mode"),
+                    KeyValue::new("acp.method.name", "session/set_mode"),
+                    KeyValue::new("network.transport", "pipe"),
+                    KeyValue::new("acp.direction", direction.as_str()),
+                    KeyValue::new("jsonrpc.request.id", id.to_string()),
+                ];
+                if let Some(ref sid) = session_id {
+                    attrs.push(KeyValue::new("gen_ai.conversation.id", sid.clone()));
+                }
+                if let Some(ref mode) = mode_id {
+                    attrs.push(KeyValue::new("acp.session.mode", mode.clone()));
+                }
+                let span = self.start_under_root(
+                    self.span_builder("session/set_mode")
+                        .with_kind(request_span_kind(direction))
+                        .with_attributes(attrs),
+                );
+                self.pending.insert(
+                    (direction, id.to_string()),
+                    PendingRequest {
+                        span: Some(span),
+                        method: method.to_string(),
+                        session_id,
+                        start: stamp.instant,
+                        permission: None,
+                        requested: mode_id,
+                    },
+                );
+            }
+            "session/set_model" => {
+                let session_id = acp::extract_session_id(params).map(|s| s.to_string());
+                let model_id = acp::extract_model_id(params).map(|m| m.to_string());
+                let mut attrs = vec![
+                    KeyValue::new("rpc.system", "jsonrpc"),
+                    KeyValue::new("rpc.method", "session/set_model"),
+                    KeyValue::new("acp.method.name", "session/set_model"),
+                    KeyValue::new("network.transport", "pipe"),
+                    KeyValue::new("acp.direction", direction.as_str()),
+                    KeyValue::new("jsonrpc.request.id", id.to_string()),
+                ];
+                if let Some(ref sid) = session_id {
+                    attrs.push(KeyValue::new("gen_ai.conversation.id", sid.clone()));
+                }
+                if let Some(ref model) = model_id {
+                    attrs.push(KeyValue::new("gen_ai.request.model", model.clone()));
+                }
+                let span = self.start_under_root(
+                    self.span_builder("session/set_model")
+                        .with_kind(request_span_kind(direction))
+                        .with_attributes(attrs),
+                );
+                self.pending.insert(
+                    (direction, id.to_string()),
+                    PendingRequest {
+                        span: Some(span),
+                        method: method.to_string(),
+                        session_id,
+                        start: stamp.instant,
+                        permission: None,
+                        requested: model_id,
+                    },
+                );
+            }
+            "session/request_permission" => {
+                let session_id = acp::extract_session_id(params).map(|s| s.to_string());
+                let options = acp::extract_permission_options(params);
+                let mut attrs = vec![
+                    KeyValue::new("rpc.system", "jsonrpc"),
+                    KeyValue::new("rpc.method", "session/request_permission"),
+                    KeyValue::new("acp.method.name", "session/request_permission"),
+                    KeyValue::new("network.transport", "pipe"),
+                    KeyValue::new("acp.direction", direction.as_str()),
+                    KeyValue::new("jsonrpc.request.id", id.to_string()),
+                    KeyValue::new(
+                        "acp.permission.options",
+                        options
+                            .iter()
+                            .map(|(_, kind)| *kind)
+                            .collect::<Vec<_>>()
+                            .join(","),
+                    ),
+                ];
+                if let Some(ref sid) = session_id {
+                    attrs.push(KeyValue::new("gen_ai.conversation.id", sid.clone()));
+                }
+                let tool_call_id = acp::extract_permission_tool_call_id(params);
+                if let Some(tool_call_id) = tool_call_id {
+                    attrs.push(KeyValue::new(
+                        "gen_ai.tool.call.id",
+                        tool_call_id.to_string(),
+                    ));
+                    attrs.push(KeyValue::new(
+                        "acp.permission.tool_call_id",
+                        tool_call_id.to_string(),
+                    ));
+                }
+                if let Some(title) = acp::extract_permission_tool_title(params) {
+                    attrs.push(KeyValue::new("gen_ai.tool.name", title.to_string()));
+                }
+                let builder = self
+                    .span_builder("session/request_permission")
+                    .with_kind(request_span_kind(direction))
+                    .with_attributes(attrs);
+                // Under the tool call asking for permission while it's open, else the prompt.
+                let parent = session_id.as_deref().and_then(|sid| {
+                    tool_call_id
+                        .and_then(|tc| self.tool_call_context(sid, tc))
+                        .or_else(|| self.parent_context_for_session(sid))
+                });
+                let span = match parent {
+                    Some(cx) => builder.start_with_context(&self.tracer, &cx),
+                    None => self.start_under_root(builder),
+                };
+                self.pending.insert(
+                    (direction, id.to_string()),
+                    PendingRequest {
+                        span: Some(span),
+                        method: method.to_string(),
+                        session_id,
+                        start: stamp.instant,
+                        permission: Some(PermissionRequest {
+                            options: options
+                                .into_iter()
+                                .map(|(id, kind)| (id.to_string(), kind.to_string()))
+                                .collect(),
+                            tool_call_id: tool_call_id.map(|id| id.to_string()),
+                        }),
+                        requested: None,
                     },
                 );
             }
@@ -222,70 +1655,146 @@ impl SpanManager {
                     KeyValue::new("gen_ai.tool.type", "function"),
                     KeyValue::new("acp.method.name", m.to_string()),
                     KeyValue::new("network.transport", "pipe"),
+                    KeyValue::new("acp.direction", direction.as_str()),
                 ];
                 if let Some(ref sid) = session_id {
                     attrs.push(KeyValue::new("gen_ai.conversation.id", sid.clone()));
                 }
-                if self.record_content {
-                    attrs.push(KeyValue::new(
-                        "gen_ai.tool.call.arguments",
-                        params.to_string(),
-                    ));
+                if let Some((key, target)) = acp::extract_method_target(m, params) {
+                    attrs.push(KeyValue::new(key, target.to_string()));
+                }
+                if m == "fs/write_text_file" {
+                    if let Some(content) = acp::extract_fs_content(params) {
+                        attrs.extend(content_size_attributes(content));
+                    }
+                }
+                if m == "fs/read_text_file" {
+                    let (line, limit) = acp::extract_read_range(params);
+                    if let Some(line) = line {
+                        attrs.push(KeyValue::new("acp.fs.line", line));
+                    }
+                    if let Some(limit) = limit {
+                        attrs.push(KeyValue::new("acp.fs.limit", limit));
+                    }
+                }
+                if self.record.tool_args {
+                    attrs.extend(
+                        self.content
+                            .attributes("gen_ai.tool.call.arguments", params.to_string()),
+                    );
                 }
                 let builder = self
-                    .tracer
                     .span_builder(span_name)
-                    .with_kind(SpanKind::Internal)
+                    .with_kind(request_span_kind(direction))
                     .with_attributes(attrs);
+                let path = acp::extract_method_target(m, params)
+                    .filter(|(key, _)| *key == "acp.fs.path")
+                    .map(|(_, path)| path);
                 let span = match session_id
                     .as_deref()
-                    .and_then(|sid| self.parent_context_for_session(sid))
+                    .and_then(|sid| self.parent_context_for_client_call(sid, path))
                 {
                     Some(cx) => builder.start_with_context(&self.tracer, &cx),
                     None => builder.start(&self.tracer),
                 };
                 self.pending.insert(
-                    id.to_string(),
+                    (direction, id.to_string()),
                     PendingRequest {
                         span: Some(span),
                         method: m.to_string(),
                         session_id,
-                        start: Instant::now(),
+                        start: stamp.instant,
+                        permission: None,
+                        requested: None,
+                    },
+                );
+            }
+            m if acp::is_extension_method(m) => {
+                let session_id = acp::extract_session_id(params).map(|s| s.to_string());
+                let builder = self
+                    .span_builder(format!("extension {m}"))
+                    .with_kind(request_span_kind(direction))
+                    .with_attributes(vec![
+                        KeyValue::new("rpc.system", "jsonrpc"),
+                        KeyValue::new("rpc.method", m.to_string()),
+                        KeyValue::new("acp.method.name", m.to_string()),
+                        KeyValue::new("acp.extension", true),
+                        KeyValue::new("acp.extension.method", m.to_string()),
+                        KeyValue::new("network.transport", "pipe"),
+                        KeyValue::new("acp.direction", direction.as_str()),
+                        KeyValue::new("jsonrpc.request.id", id.to_string()),
+                    ]);
+                let span = match session_id
+                    .as_deref()
+                    .and_then(|sid| self.parent_context_for_session(sid))
+                {
+                    Some(cx) => builder.start_with_context(&self.tracer, &cx),
+                    None => self.start_under_root(builder),
+                };
+                self.pending.insert(
+                    (direction, id.to_string()),
+                    PendingRequest {
+                        span: Some(span),
+                        method: m.to_string(),
+                        session_id,
+                        start: stamp.instant,
+                        permission: None,
+                        requested: None,
                     },
                 );
             }
             _ => {
                 // Other requests: session/new, session/load, authenticate, etc.
                 let span = self.start_under_root(
-                    self.tracer
-                        .span_builder(method.to_string())
-                        .with_kind(SpanKind::Internal)
+                    self.span_builder(method.to_string())
+                        .with_kind(request_span_kind(direction))
                         .with_attributes(vec![
                             KeyValue::new("rpc.system", "jsonrpc"),
                             KeyValue::new("rpc.method", method.to_string()),
                             KeyValue::new("acp.method.name", method.to_string()),
                             KeyValue::new("network.transport", "pipe"),
+                            KeyValue::new("acp.direction", direction.as_str()),
                             KeyValue::new("jsonrpc.request.id", id.to_string()),
                         ]),
                 );
                 self.pending.insert(
-                    id.to_string(),
+                    (direction, id.to_string()),
                     PendingRequest {
                         span: Some(span),
                         method: method.to_string(),
                         session_id: acp::extract_session_id(params).map(|s| s.to_string()),
-                        start: Instant::now(),
+                        start: stamp.instant,
+                        permission: None,
+                        requested: None,
                     },
                 );
             }
         }
     }
 
-    fn handle_response(&mut self, id: Value, result: Option<&Value>, error: Option<&Value>) {
-        let key = id.to_string();
+    fn handle_response(
+        &mut self,
+        direction: Direction,
+        id: Value,
+        result: Option<&Value>,
+        error: Option<&Value>,
+    ) {
+        let stamp = self.stamp();
+        // Responses travel back to whoever sent the request.
+        let key = (direction.reverse(), id.to_string());
         let pending = match self.pending.remove(&key) {
             Some(p) => p,
-            None => return,
+            None => {
+                if self.superseded.remove(&key) {
+                    tracing::debug!(id = %id, "response to a superseded prompt");
+                    return;
+                }
+                if self.timed_out.remove(&key) {
+                    tracing::debug!(id = %id, "response after request timeout");
+                }
+                self.record_orphan_response(direction, &id, error.is_some());
+                return;
+            }
         };
 
         tracing::debug!(method = %pending.method, "response");
@@ -297,75 +1806,187 @@ impl SpanManager {
                         if let Some((name, version)) = acp::extract_agent_info(res) {
                             self.agent_name = Some(name.to_string());
                             self.agent_version = version.map(|v| v.to_string());
+                        }
+                        self.initialized = true;
+                        self.protocol_version = acp::extract_protocol_version(res);
+                        if let Some(pv) = self.protocol_version {
+                            self.record_protocol_version(&mut span, pv, stamp);
+                        }
+                        if let Some(methods) = acp::extract_auth_method_ids(res) {
                             span.set_attribute(KeyValue::new(
-                                "gen_ai.agent.name",
-                                name.to_string(),
+                                "acp.agent.auth_methods",
+                                methods.join(","),
                             ));
-                            span.set_attribute(KeyValue::new("gen_ai.agent.id", name.to_string()));
+                            span.set_attribute(KeyValue::new(
+                                "acp.agent.auth_methods.count",
+                                methods.len() as i64,
+                            ));
+                            self.auth_methods =
+                                Some(methods.into_iter().map(|m| m.to_string()).collect());
                         }
-                        self.protocol_version = res.get("protocolVersion").and_then(|v| v.as_i64());
-                        if let Some(pv) = self.protocol_version {
-                            span.set_attribute(KeyValue::new("acp.protocol.version", pv));
+                        let capabilities =
+                            agent_capability_attributes(&acp::extract_agent_capabilities(res));
+                        if let Some(root) = self.session_span.as_mut() {
+                            for kv in &capabilities {
+                                root.set_attribute(kv.clone());
+                            }
+                        }
+                        for kv in capabilities {
+                            span.set_attribute(kv);
                         }
                     }
                     if let Some(err) = error {
                         span.set_status(Status::error(err.to_string()));
-                        span.set_attribute(KeyValue::new(
-                            "error.type",
-                            err.get("code")
-                                .map(|c| c.to_string())
-                                .unwrap_or_else(|| "_OTHER".to_string()),
-                        ));
+                        set_error_attributes(&mut span, err);
                     }
-                    // Update root session span with agent info
+                    // Update this span and the root session span with agent info
                     if let Some(ref name) = self.agent_name {
+                        span.set_attribute(KeyValue::new("gen_ai.agent.name", name.clone()));
+                        span.set_attribute(KeyValue::new("gen_ai.agent.id", name.clone()));
                         if let Some(ref mut root) = self.session_span {
                             root.set_attribute(KeyValue::new("gen_ai.agent.name", name.clone()));
                         }
                     }
-                    span.end();
+                    span.end_with_timestamp(stamp.time);
+                }
+            }
+            "authenticate" => {
+                if let Some(mut span) = pending.span {
+                    match error {
+                        Some(err) => {
+                            let message = acp::extract_error_message(err)
+                                .map(|m| m.to_string())
+                                .unwrap_or_else(|| err.to_string());
+                            span.set_status(Status::error(message));
+                            set_error_attributes(&mut span, err);
+                        }
+                        None => span.set_status(Status::Ok),
+                    }
+                    span.end_with_timestamp(stamp.time);
                 }
             }
             "session/prompt" => {
                 if let Some(ref session_id) = pending.session_id {
                     if let Some(session) = self.sessions.get_mut(session_id) {
                         if let Some(mut span) = session.prompt_span.take() {
-                            let duration = pending.start.elapsed().as_secs_f64();
-                            if let Some(res) = result {
-                                if let Some(reason) = acp::extract_stop_reason(res) {
+                            let responded_at = stamp.time;
+                            let duration = stamp
+                                .instant
+                                .saturating_duration_since(pending.start)
+                                .as_secs_f64();
+                            let stop_reason = result.and_then(acp::extract_stop_reason);
+                            let response_model = result
+                                .and_then(acp::extract_response_model)
+                                .map(|m| m.to_string())
+                                .or_else(|| session.response_model.take())
+                                .or_else(|| session.model.clone());
+                            if let Some(ref model) = response_model {
+                                span.set_attribute(KeyValue::new(
+                                    "gen_ai.response.model",
+                                    model.clone(),
+                                ));
+                            }
+                            if let Some(cancelled_at) = session.cancelled_at.take() {
+                                if stop_reason == Some("cancelled") {
+                                    let latency = stamp
+                                        .instant
+                                        .saturating_duration_since(cancelled_at)
+                                        .as_secs_f64();
                                     span.set_attribute(KeyValue::new(
-                                        "gen_ai.response.finish_reasons",
-                                        format!("[\"{reason}\"]"),
+                                        "acp.cancel.latency_ms",
+                                        (latency * 1000.0) as i64,
                                     ));
-                                    if self.record_content && !session.accumulated_output.is_empty()
-                                    {
-                                        let finish = acp::map_stop_reason_to_finish_reason(reason);
-                                        let output_msg = serde_json::json!([{
-                                            "role": "assistant",
-                                            "parts": [{"type": "text", "content": &session.accumulated_output}],
-                                            "finish_reason": finish
-                                        }]);
-                                        span.set_attribute(KeyValue::new(
-                                            "gen_ai.output.messages",
-                                            output_msg.to_string(),
-                                        ));
-                                    }
+                                    self.cancel_histogram.record(
+                                        latency,
+                                        &[KeyValue::new("gen_ai.operation.name", "invoke_agent")],
+                                    );
+                                } else {
+                                    span.set_attribute(KeyValue::new("acp.cancel.ignored", true));
                                 }
                             }
-                            if self.record_content
-                                && !session.accumulated_output.is_empty()
-                                && result.and_then(|r| acp::extract_stop_reason(r)).is_none()
-                            {
-                                // No stop reason available — emit without finish_reason
-                                let output_msg = serde_json::json!([{
-                                    "role": "assistant",
-                                    "parts": [{"type": "text", "content": &session.accumulated_output}]
-                                }]);
+                            if session.update_bytes > 0 {
+                                span.set_attribute(KeyValue::new(
+                                    "acp.updates.size_bytes",
+                                    session.update_bytes as i64,
+                                ));
+                            }
+                            if session.turn_tool_calls > 0 {
                                 span.set_attribute(KeyValue::new(
-                                    "gen_ai.output.messages",
-                                    output_msg.to_string(),
+                                    "acp.turn.tool_calls.mcp",
+                                    session.turn_mcp_tool_calls as i64,
                                 ));
+                                span.set_attribute(KeyValue::new(
+                                    "acp.turn.tool_calls.builtin",
+                                    (session.turn_tool_calls - session.turn_mcp_tool_calls) as i64,
+                                ));
+                            }
+                            if let Some(reason) = stop_reason {
+                                span.set_attribute(KeyValue::new(
+                                    "gen_ai.response.finish_reasons",
+                                    format!("[\"{reason}\"]"),
+                                ));
+                                if acp::is_truncation_stop_reason(reason) {
+                                    span.set_attribute(KeyValue::new("acp.turn.truncated", true));
+                                    span.add_event_with_timestamp(
+                                        "acp.turn_truncated",
+                                        stamp.time,
+                                        vec![KeyValue::new("acp.stop_reason", reason.to_string())],
+                                    );
+                                    self.truncated_turn_counter.add(
+                                        1,
+                                        &[KeyValue::new("acp.stop_reason", reason.to_string())],
+                                    );
+                                }
+                            }
+                            if self.record.output {
+                                // Without a stop reason the message is emitted without finish_reason
+                                let finish = stop_reason.map(acp::map_stop_reason_to_finish_reason);
+                                if let Some(output_msg) = session.output_messages(finish) {
+                                    self.content.set_omitting(
+                                        &mut span,
+                                        "gen_ai.output.messages",
+                                        output_msg.to_string(),
+                                        session.output_omitted,
+                                    );
+                                }
+                            }
+                            // Context-only prompts (e.g. just resource blocks) have no text in
+                            // the params; fall back to the echoed user_message_chunk text.
+                            if self.record.input
+                                && !session.input_recorded
+                                && !session.accumulated_input.is_empty()
+                            {
+                                self.content.set_omitting(
+                                    &mut span,
+                                    "gen_ai.input.messages",
+                                    input_messages(&session.accumulated_input, &[]).to_string(),
+                                    session.input_omitted,
+                                );
                             }
+                            span.set_attribute(KeyValue::new(
+                                "acp.user_message.chunks",
+                                session.user_message_chunks as i64,
+                            ));
+                            span.set_attribute(KeyValue::new(
+                                "acp.user_message.chars",
+                                session.user_message_chars as i64,
+                            ));
+                            span.set_attribute(KeyValue::new(
+                                "acp.output.chunks",
+                                session.output_chunks as i64,
+                            ));
+                            span.set_attribute(KeyValue::new(
+                                "acp.output.chars",
+                                session.output_chars as i64,
+                            ));
+                            span.set_attribute(KeyValue::new(
+                                "acp.thought.chunks",
+                                session.thought_chunks as i64,
+                            ));
+                            span.set_attribute(KeyValue::new(
+                                "acp.thought.chars",
+                                session.thought_chars as i64,
+                            ));
                             if let Some(first) = session.first_chunk_time {
                                 if let Some(start) = session.prompt_start {
                                     let ttft = first.duration_since(start).as_secs_f64();
@@ -373,25 +1994,144 @@ impl SpanManager {
                                         "acp.time_to_first_token_ms",
                                         (ttft * 1000.0) as i64,
                                     ));
-                                    self.ttft_histogram.record(
-                                        ttft,
-                                        &[KeyValue::new("gen_ai.operation.name", "invoke_agent")],
-                                    );
+                                    let mut attrs = vec![KeyValue::new(
+                                        "gen_ai.operation.name",
+                                        "invoke_agent",
+                                    )];
+                                    if let Some(ref model) = response_model {
+                                        attrs.push(KeyValue::new(
+                                            "gen_ai.response.model",
+                                            model.clone(),
+                                        ));
+                                    }
+                                    self.ttft_histogram.record(ttft, &attrs);
+                                    self.totals.ttfts.push(ttft);
                                 }
                             }
                             if let Some(err) = error {
                                 span.set_status(Status::error(err.to_string()));
+                                set_error_attributes(&mut span, err);
+                            }
+                            let usage = result.and_then(acp::extract_usage);
+                            if let Some(usage) = usage {
+                                if let Some(tokens) = usage.input_tokens {
+                                    span.set_attribute(KeyValue::new(
+                                        "gen_ai.usage.input_tokens",
+                                        tokens as i64,
+                                    ));
+                                }
+                                if let Some(tokens) = usage.output_tokens {
+                                    span.set_attribute(KeyValue::new(
+                                        "gen_ai.usage.output_tokens",
+                                        tokens as i64,
+                                    ));
+                                }
+                            }
+                            // The turn is over, so tools the agent never reported finished with
+                            // it — and failed with it when the prompt got an error response.
+                            for (_, mut tool) in session.tool_calls.drain() {
+                                tool.span.set_status(match error {
+                                    Some(_) => Status::error("prompt request failed"),
+                                    None => Status::Ok,
+                                });
+                                tool.span
+                                    .set_attribute(KeyValue::new("acp.tool.outcome", "unreported"));
+                                self.totals.count_tool(&tool, error.is_some());
+                                tool.end_at(responded_at);
+                            }
+                            span.end_with_timestamp(stamp.time);
+                            self.turn_completed = true;
+                            let mut metric_attrs = vec![
+                                KeyValue::new("gen_ai.operation.name", "invoke_agent"),
+                                KeyValue::new(
+                                    "acp.turn.index",
+                                    session.turns.saturating_sub(1) as i64,
+                                ),
+                            ];
+                            if let Some(ref model) = session.model {
+                                metric_attrs
+                                    .push(KeyValue::new("gen_ai.request.model", model.clone()));
+                            }
+                            if let Some(model) = response_model {
+                                metric_attrs.push(KeyValue::new("gen_ai.response.model", model));
+                            }
+                            self.duration_histogram.record(duration, &metric_attrs);
+                            self.output_chunks_histogram
+                                .record(session.output_chunks, &metric_attrs);
+                            self.output_chars_histogram
+                                .record(session.output_chars, &metric_attrs);
+                            self.totals.turn_durations.push(duration);
+                            if let Some(usage) = usage {
+                                let token_types = [
+                                    ("input", usage.input_tokens),
+                                    ("output", usage.output_tokens),
+                                ];
+                                for (token_type, tokens) in token_types {
+                                    if let Some(tokens) = tokens {
+                                        let mut attrs = metric_attrs.clone();
+                                        attrs.push(KeyValue::new("gen_ai.token.type", token_type));
+                                        self.token_usage_histogram.record(tokens, &attrs);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            "session/request_permission" => {
+                // Cancelling or erroring out is a user decision, not a failure —
+                // record it as an attribute and leave the span status unset.
+                if let Some(mut span) = pending.span {
+                    let permission = pending.permission.unwrap_or_default();
+                    // "granted"/"denied" for the tool span; None when the outcome is unknown.
+                    let mut decision = None;
+                    match (result.and_then(acp::extract_permission_outcome), error) {
+                        (Some((outcome, option_id)), None) => {
+                            span.set_attribute(KeyValue::new(
+                                "acp.permission.outcome",
+                                outcome.to_string(),
+                            ));
+                            if outcome == "cancelled" {
+                                decision = Some("denied");
+                            }
+                            if let Some(option_id) = option_id {
                                 span.set_attribute(KeyValue::new(
-                                    "error.type",
-                                    err.get("code")
-                                        .map(|c| c.to_string())
-                                        .unwrap_or_else(|| "_OTHER".to_string()),
+                                    "acp.permission.option_id",
+                                    option_id.to_string(),
                                 ));
+                                if let Some((_, kind)) =
+                                    permission.options.iter().find(|(id, _)| id == option_id)
+                                {
+                                    span.set_attribute(KeyValue::new(
+                                        "acp.permission.option_kind",
+                                        kind.clone(),
+                                    ));
+                                    decision = Some(if kind.starts_with("reject") {
+                                        "denied"
+                                    } else {
+                                        "granted"
+                                    });
+                                }
                             }
-                            span.end();
-                            self.duration_histogram.record(
-                                duration,
-                                &[KeyValue::new("gen_ai.operation.name", "invoke_agent")],
+                        }
+                        _ => {
+                            span.set_attribute(KeyValue::new("acp.permission.outcome", "error"));
+                        }
+                    }
+                    span.end_with_timestamp(stamp.time);
+                    let tool = pending
+                        .session_id
+                        .as_ref()
+                        .zip(permission.tool_call_id.as_ref())
+                        .and_then(|(sid, tc)| self.sessions.get_mut(sid)?.tool_calls.get_mut(tc));
+                    if let (Some(tool), Some(decision)) = (tool, decision) {
+                        tool.span
+                            .set_attribute(KeyValue::new("acp.tool.permission", decision));
+                        if decision == "denied" {
+                            tool.span.add_event_with_timestamp(
+                                "acp.permission_denied",
+                                stamp.time,
+                                vec![],
                             );
                         }
                     }
@@ -399,37 +2139,163 @@ impl SpanManager {
             }
             m if acp::is_fs_or_terminal_method(m) => {
                 if let Some(mut span) = pending.span {
-                    if self.record_content {
-                        if let Some(res) = result {
+                    if let Some(res) = result {
+                        // terminal/create returns the id later requests refer to
+                        if let Some(terminal_id) = acp::extract_terminal_id(res) {
+                            span.set_attribute(KeyValue::new(
+                                "acp.terminal.id",
+                                terminal_id.to_string(),
+                            ));
+                        }
+                        if let Some(output) = acp::extract_terminal_output(res) {
+                            span.set_attribute(KeyValue::new(
+                                "acp.terminal.output_bytes",
+                                output.len() as i64,
+                            ));
+                        }
+                        if pending.method == "fs/read_text_file" {
+                            if let Some(content) = acp::extract_fs_content(res) {
+                                for kv in content_size_attributes(content) {
+                                    span.set_attribute(kv);
+                                }
+                            }
+                        }
+                        if acp::extract_terminal_truncated(res) {
+                            span.set_attribute(KeyValue::new("acp.terminal.truncated", true));
+                        }
+                        let (exit_code, signal) = acp::extract_terminal_exit_status(res);
+                        if let Some(code) = exit_code {
+                            span.set_attribute(KeyValue::new("acp.terminal.exit_code", code));
+                            if code != 0 {
+                                span.set_status(Status::error(format!("exit code {code}")));
+                            }
+                        }
+                        if let Some(signal) = signal {
                             span.set_attribute(KeyValue::new(
-                                "gen_ai.tool.call.result",
-                                res.to_string(),
+                                "acp.terminal.signal",
+                                signal.to_string(),
                             ));
+                            span.set_status(Status::error(format!("terminated by {signal}")));
+                        }
+                    }
+                    if self.record.tool_results {
+                        if let Some(res) = result {
+                            self.content
+                                .set(&mut span, "gen_ai.tool.call.result", res.to_string());
                         }
                     }
                     if let Some(err) = error {
                         span.set_status(Status::error(err.to_string()));
+                        set_error_attributes(&mut span, err);
+                    }
+                    span.end_with_timestamp(stamp.time);
+                }
+            }
+            "session/new" | "session/load" => {
+                if let Some(mut span) = pending.span {
+                    let setup = result.map(acp::extract_session_setup).unwrap_or_default();
+                    // session/load echoes the id from the request instead of returning one.
+                    let session_id = setup
+                        .session_id
+                        .map(|s| s.to_string())
+                        .or(pending.session_id);
+                    if let Some(ref sid) = session_id {
+                        span.set_attribute(KeyValue::new("gen_ai.conversation.id", sid.clone()));
+                    }
+                    if let Some(mode) = setup.current_mode {
+                        span.set_attribute(KeyValue::new(
+                            "acp.session.initial_mode",
+                            mode.to_string(),
+                        ));
+                    }
+                    if let Some(count) = setup.available_modes {
                         span.set_attribute(KeyValue::new(
-                            "error.type",
-                            err.get("code")
-                                .map(|c| c.to_string())
-                                .unwrap_or_else(|| "_OTHER".to_string()),
+                            "acp.session.available_modes.count",
+                            count as i64,
                         ));
                     }
-                    span.end();
+                    if let Some(model) = setup.current_model {
+                        span.set_attribute(KeyValue::new(
+                            "gen_ai.request.model",
+                            model.to_string(),
+                        ));
+                    }
+                    if let Some(count) = setup.available_models {
+                        span.set_attribute(KeyValue::new(
+                            "acp.session.available_models.count",
+                            count as i64,
+                        ));
+                    }
+                    // Seed what the first invoke_agent span reports; explicit
+                    // set_mode/set_model calls already recorded take precedence.
+                    if let Some(sid) = session_id {
+                        let session = self.sessions.entry(sid).or_default();
+                        if session.mode.is_none() {
+                            session.mode = setup.current_mode.map(|m| m.to_string());
+                        }
+                        if session.model.is_none() {
+                            session.model = setup.current_model.map(|m| m.to_string());
+                        }
+                    }
+                    if let Some(err) = error {
+                        span.set_status(Status::error(err.to_string()));
+                        set_error_attributes(&mut span, err);
+                    }
+                    span.end_with_timestamp(stamp.time);
+                }
+            }
+            "session/set_mode" | "session/set_model" => {
+                // An agent that rejects the change keeps the old mode or model.
+                if error.is_none() {
+                    if let (Some(sid), Some(value)) = (pending.session_id, pending.requested) {
+                        let session = self.sessions.entry(sid).or_default();
+                        if pending.method == "session/set_mode" {
+                            session.mode = Some(value);
+                        } else {
+                            session.model = Some(value);
+                        }
+                    }
+                }
+                if let Some(mut span) = pending.span {
+                    if let Some(err) = error {
+                        span.set_status(Status::error(err.to_string()));
+                        set_error_attributes(&mut span, err);
+                    }
+                    span.end_with_timestamp(stamp.time);
                 }
             }
             _ => {
                 if let Some(mut span) = pending.span {
                     if let Some(err) = error {
                         span.set_status(Status::error(err.to_string()));
+                        set_error_attributes(&mut span, err);
                     }
-                    span.end();
+                    span.end_with_timestamp(stamp.time);
                 }
             }
         }
     }
 
+    fn record_orphan_response(&mut self, direction: Direction, id: &Value, is_error: bool) {
+        self.orphan_responses += 1;
+        self.orphan_response_counter
+            .add(1, &[KeyValue::new("acp.direction", direction.as_str())]);
+        let now = Instant::now();
+        let due = self
+            .last_orphan_warning
+            .is_none_or(|last| now.duration_since(last) >= ORPHAN_WARN_INTERVAL);
+        if due {
+            self.last_orphan_warning = Some(now);
+            tracing::warn!(
+                id = %id,
+                direction = ?direction,
+                is_error,
+                total = self.orphan_responses,
+                "response with no matching request"
+            );
+        }
+    }
+
     /// Get a parent Context for creating child spans under the active prompt span.
     fn parent_context_for_session(&self, session_id: &str) -> Option<Context> {
         self.sessions
@@ -438,6 +2304,40 @@ impl SpanManager {
             .map(|sc| Context::new().with_remote_span_context(sc.clone()))
     }
 
+    /// Context of an open tool call span.
+    fn tool_call_context(&self, session_id: &str, tool_call_id: &str) -> Option<Context> {
+        let tool = self
+            .sessions
+            .get(session_id)?
+            .tool_calls
+            .get(tool_call_id)?;
+        Some(Context::new().with_remote_span_context(tool.span_context.clone()))
+    }
+
+    /// Parent for an fs/terminal request: the open tool call whose locations
+    /// include `path`, else the only open tool call, else the prompt span.
+    fn parent_context_for_client_call(
+        &self,
+        session_id: &str,
+        path: Option<&str>,
+    ) -> Option<Context> {
+        let session = self.sessions.get(session_id)?;
+        let by_path = path.and_then(|path| {
+            session
+                .tool_calls
+                .values()
+                .find(|t| t.paths.iter().any(|p| p == path))
+        });
+        let tool = by_path.or_else(|| match session.tool_calls.len() {
+            1 => session.tool_calls.values().next(),
+            _ => None,
+        });
+        match tool {
+            Some(tool) => Some(Context::new().with_remote_span_context(tool.span_context.clone())),
+            None => self.parent_context_for_session(session_id),
+        }
+    }
+
     /// Get the root session context for parenting top-level spans.
     fn root_context(&self) -> Option<Context> {
         self.session_span_context
@@ -445,6 +2345,28 @@ impl SpanManager {
             .map(|sc| Context::new().with_remote_span_context(sc.clone()))
     }
 
+    /// Create the root session span that parents everything, unless one is open.
+    fn start_session_span(&mut self) {
+        if self.session_span.is_some() {
+            return;
+        }
+        let mut attributes = vec![
+            KeyValue::new("acp.method.name", "session"),
+            KeyValue::new("network.transport", "pipe"),
+            KeyValue::new("acp.handshakes", self.handshakes as i64),
+        ];
+        if let Some(cwd) = &self.agent_cwd {
+            attributes.push(KeyValue::new("acp.agent.cwd", cwd.clone()));
+        }
+        let root = self
+            .span_builder("acp_session")
+            .with_kind(SpanKind::Internal)
+            .with_attributes(attributes)
+            .start(&self.tracer);
+        self.session_span_context = Some(root.span_context().clone());
+        self.session_span = Some(root);
+    }
+
     /// Start a span as a child of the root session span (or as root if none exists).
     fn start_under_root(
         &self,
@@ -456,7 +2378,121 @@ impl SpanManager {
         }
     }
 
-    fn handle_notification(&mut self, _direction: Direction, method: &str, params: &Value) {
+    /// Span name and attributes for a tool call, from whichever fields the
+    /// `tool_call` or `tool_call_update` carries.
+    fn tool_call_attributes(
+        &self,
+        direction: Direction,
+        session_id: &str,
+        tool_call_id: &str,
+        params: &Value,
+    ) -> (String, Vec<KeyValue>) {
+        let title = acp::extract_tool_call_title(params).unwrap_or("unknown tool");
+        let span_name = format!("execute_tool {title}");
+        let mut attrs = vec![
+            KeyValue::new("gen_ai.operation.name", "execute_tool"),
+            KeyValue::new("gen_ai.tool.call.id", tool_call_id.to_string()),
+            KeyValue::new("gen_ai.conversation.id", session_id.to_string()),
+            KeyValue::new("acp.method.name", "session/update"),
+            KeyValue::new("network.transport", "pipe"),
+            KeyValue::new("acp.direction", direction.as_str()),
+        ];
+        if let Some(locations) = acp::extract_tool_call_locations(params) {
+            attrs.push(KeyValue::new(
+                "acp.tool.locations.count",
+                locations.len() as i64,
+            ));
+            let listed = if self.record.tool_args {
+                serde_json::json!(locations)
+            } else {
+                serde_json::json!(locations
+                    .iter()
+                    .filter_map(|l| l.get("path")?.as_str())
+                    .take(MAX_TOOL_LOCATIONS)
+                    .collect::<Vec<_>>())
+            };
+            attrs.push(KeyValue::new("acp.tool.locations", listed.to_string()));
+        }
+        let content_types = acp::extract_tool_call_content_types(params);
+        if !content_types.is_empty() {
+            attrs.push(KeyValue::new(
+                "acp.tool.content.types",
+                content_types.join(","),
+            ));
+        }
+        let media = acp::extract_tool_call_media(params);
+        let images = media.iter().filter(|m| m.kind == "image").count();
+        if images > 0 {
+            attrs.push(KeyValue::new("acp.tool.content.images", images as i64));
+            attrs.push(KeyValue::new(
+                "acp.tool.content.image.mime_types",
+                distinct_mime_types(&media, "image").join(","),
+            ));
+        }
+        (span_name, attrs)
+    }
+
+    /// Start an `execute_tool` span under the session's prompt and track it.
+    fn start_tool_call(
+        &mut self,
+        session_id: &str,
+        tool_call_id: &str,
+        span_name: String,
+        attrs: Vec<KeyValue>,
+        kind: Option<&str>,
+        status: &str,
+    ) -> Option<&mut ToolCallState> {
+        if !self.sessions.contains_key(session_id) {
+            return None;
+        }
+        let kind = kind.unwrap_or("other").to_string();
+        let builder = self
+            .span_builder(span_name)
+            .with_kind(SpanKind::Internal)
+            .with_attributes(attrs);
+        let span = match self.parent_context_for_session(session_id) {
+            Some(cx) => builder.start_with_context(&self.tracer, &cx),
+            None => self.start_under_root(builder),
+        };
+        let content = self.content.clone();
+        let stamp = self.stamp();
+        let session = self.sessions.get_mut(session_id)?;
+        session.turn_tool_calls += 1;
+        let tool = session
+            .tool_calls
+            .entry(tool_call_id.to_string())
+            .or_insert(ToolCallState::new(span, kind, status, content, stamp));
+        Some(tool)
+    }
+
+    fn handle_notification(&mut self, direction: Direction, method: &str, params: &Value) {
+        let stamp = self.stamp();
+        if acp::is_extension_method(method) {
+            let attrs = vec![
+                KeyValue::new("acp.extension", true),
+                KeyValue::new("acp.extension.method", method.to_string()),
+                KeyValue::new("acp.direction", direction.as_str()),
+            ];
+            let prompt_span = acp::extract_session_id(params)
+                .and_then(|sid| self.sessions.get_mut(sid))
+                .and_then(|s| s.prompt_span.as_mut());
+            if let Some(span) = prompt_span {
+                span.add_event_with_timestamp("acp.extension_notification", stamp.time, attrs);
+            } else if let Some(root) = self.session_span.as_mut() {
+                root.add_event_with_timestamp("acp.extension_notification", stamp.time, attrs);
+            }
+            return;
+        }
+        if method == "session/cancel" {
+            let session =
+                acp::extract_session_id(params).and_then(|sid| self.sessions.get_mut(sid));
+            if let Some(session) = session {
+                if session.prompt_span.is_some() && session.cancelled_at.is_none() {
+                    session.cancelled_at = Some(stamp.instant);
+                }
+            }
+            return;
+        }
         if method != "session/update" {
             return;
         }
@@ -470,53 +2506,204 @@ impl SpanManager {
             None => return,
         };
 
-        tracing::debug!(session = %session_id, update = %update_type, "notification");
+        tracing::debug!(
+            direction = ?direction,
+            session = %session_id,
+            update = %update_type,
+            "notification"
+        );
+
+        // Attached mid-conversation (or after a session/load we didn't see): track the
+        // session anyway. Prompt-dependent accounting waits for a real session/prompt.
+        if !self.sessions.contains_key(&session_id) {
+            self.implicit_session_counter.add(1, &[]);
+            self.sessions
+                .insert(session_id.clone(), SessionState::default());
+        }
+        if let Some(model) = params.get("update").and_then(acp::extract_response_model) {
+            if let Some(session) = self.sessions.get_mut(&session_id) {
+                session.response_model = Some(model.to_string());
+            }
+        }
 
         match update_type.as_str() {
             "agent_message_chunk" => {
                 if let Some(session) = self.sessions.get_mut(&session_id) {
                     if session.first_chunk_time.is_none() {
-                        session.first_chunk_time = Some(Instant::now());
+                        session.first_chunk_time = Some(stamp.instant);
+                    }
+                    session.output_chunks += 1;
+                    if let Some(text) = acp::extract_chunk_text(params) {
+                        let chars = text.chars().count() as u64;
+                        session.output_chars += chars;
+                        self.totals.output_chars += chars;
+                        if self.record.output {
+                            let cap = self.content.buffer_cap();
+                            session.output_omitted +=
+                                push_capped(&mut session.accumulated_output, text, cap);
+                        }
+                    }
+                }
+            }
+            "user_message_chunk" => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    session.user_message_chunks += 1;
+                    if let Some(text) = acp::extract_chunk_text(params) {
+                        session.user_message_chars += text.chars().count() as u64;
+                        if self.record.input {
+                            let cap = self.content.buffer_cap();
+                            session.input_omitted +=
+                                push_capped(&mut session.accumulated_input, text, cap);
+                        }
+                    }
+                }
+            }
+            "agent_thought_chunk" => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    // Thoughts usually precede the message, so they count toward TTFT.
+                    if session.first_chunk_time.is_none() {
+                        session.first_chunk_time = Some(stamp.instant);
                     }
+                    session.thought_chunks += 1;
                     if let Some(text) = acp::extract_chunk_text(params) {
-                        session.accumulated_output.push_str(text);
+                        session.thought_chars += text.chars().count() as u64;
+                        if self.record.output {
+                            let cap = self.content.buffer_cap();
+                            session.output_omitted +=
+                                push_capped(&mut session.accumulated_thoughts, text, cap);
+                        }
                     }
                 }
             }
+            "plan" => {
+                let summary = match acp::summarize_plan(params) {
+                    Some(s) => s,
+                    None => return,
+                };
+                let span = self
+                    .sessions
+                    .get_mut(&session_id)
+                    .and_then(|s| s.prompt_span.as_mut());
+                let span = match span {
+                    Some(span) => span,
+                    None => {
+                        self.unattached_plan_updates += 1;
+                        return;
+                    }
+                };
+                let mut attrs = vec![
+                    KeyValue::new("acp.plan.entries", summary.total as i64),
+                    KeyValue::new("acp.plan.pending", summary.pending as i64),
+                    KeyValue::new("acp.plan.in_progress", summary.in_progress as i64),
+                    KeyValue::new("acp.plan.completed", summary.completed as i64),
+                ];
+                if self.record.output {
+                    attrs.push(KeyValue::new(
+                        "acp.plan.titles",
+                        serde_json::json!(summary.titles).to_string(),
+                    ));
+                }
+                span.add_event_with_timestamp("acp.plan_update", stamp.time, attrs);
+            }
+            "available_commands_update" => {
+                let names: Vec<String> = match acp::extract_available_command_names(params) {
+                    Some(names) => names.into_iter().map(|n| n.to_string()).collect(),
+                    None => return,
+                };
+                let session = self.sessions.entry(session_id.clone()).or_default();
+                if session.available_commands.as_ref() == Some(&names) {
+                    return;
+                }
+                let joined = names.join(",");
+                let count = names.len() as i64;
+                session.available_commands = Some(names.clone());
+                if let Some(ref mut root) = self.session_span {
+                    self.available_commands = Some(names);
+                    root.add_event_with_timestamp(
+                        "acp.available_commands_update",
+                        stamp.time,
+                        vec![
+                            KeyValue::new("gen_ai.conversation.id", session_id),
+                            KeyValue::new("acp.session.available_commands", joined),
+                            KeyValue::new("acp.session.available_commands.count", count),
+                        ],
+                    );
+                }
+            }
+            "current_mode_update" => {
+                let mode = match acp::extract_current_mode_id(params) {
+                    Some(m) => m.to_string(),
+                    None => return,
+                };
+                let session = self.sessions.entry(session_id).or_default();
+                let previous = session.mode.replace(mode.clone());
+                if previous.as_deref() == Some(mode.as_str()) {
+                    return;
+                }
+                let mut attrs = vec![KeyValue::new("acp.mode.to", mode)];
+                if let Some(from) = previous {
+                    attrs.push(KeyValue::new("acp.mode.from", from));
+                }
+                // Prefer the turn the change happened in; otherwise the root span.
+                if let Some(span) = session.prompt_span.as_mut() {
+                    span.add_event_with_timestamp("acp.mode_changed", stamp.time, attrs);
+                } else if let Some(root) = self.session_span.as_mut() {
+                    root.add_event_with_timestamp("acp.mode_changed", stamp.time, attrs);
+                }
+            }
             "tool_call" => {
                 let tool_call_id = match acp::extract_tool_call_id(params) {
                     Some(id) => id.to_string(),
                     None => return,
                 };
-                let title = acp::extract_tool_call_title(params).unwrap_or("unknown tool");
-                let kind = acp::extract_tool_call_kind(params).unwrap_or("other");
-                let span_name = format!("execute_tool {title}");
-                let mut attrs = vec![
-                    KeyValue::new("gen_ai.operation.name", "execute_tool"),
-                    KeyValue::new("gen_ai.tool.name", title.to_string()),
-                    KeyValue::new("gen_ai.tool.call.id", tool_call_id.clone()),
-                    KeyValue::new("gen_ai.tool.type", acp::map_tool_kind_to_type(kind)),
-                    KeyValue::new("gen_ai.conversation.id", session_id.clone()),
-                    KeyValue::new("acp.method.name", "session/update"),
-                    KeyValue::new("acp.tool.kind", kind.to_string()),
-                    KeyValue::new("network.transport", "pipe"),
-                ];
-                if self.record_content {
-                    if let Some(raw) = params.get("update").and_then(|u| u.get("rawInput")) {
-                        attrs.push(KeyValue::new("gen_ai.tool.call.arguments", raw.to_string()));
+                let (span_name, attrs) =
+                    self.tool_call_attributes(direction, &session_id, &tool_call_id, params);
+                let kind = acp::extract_tool_call_kind(params);
+                let status = acp::extract_tool_call_status(params);
+                let record = self.record;
+                let existing = self
+                    .sessions
+                    .get_mut(&session_id)
+                    .and_then(|s| s.tool_calls.get_mut(&tool_call_id));
+                let tool = match existing {
+                    // Started early by a tool_call_update; fill in what the update lacked,
+                    // keeping what it already said.
+                    Some(tool) => {
+                        for kv in attrs {
+                            tool.span.set_attribute(kv);
+                        }
+                        if let Some(kind) = kind {
+                            tool.kind = kind.to_string();
+                        }
+                        if let Some(status) = status {
+                            tool.record_status(status, stamp);
+                        }
+                        tool
                     }
-                }
-                let builder = self
-                    .tracer
-                    .span_builder(span_name)
-                    .with_kind(SpanKind::Internal)
-                    .with_attributes(attrs);
-                let span = match self.parent_context_for_session(&session_id) {
-                    Some(cx) => builder.start_with_context(&self.tracer, &cx),
-                    None => builder.start(&self.tracer),
+                    None => match self.start_tool_call(
+                        &session_id,
+                        &tool_call_id,
+                        span_name,
+                        attrs,
+                        kind,
+                        status.unwrap_or("pending"),
+                    ) {
+                        Some(tool) => tool,
+                        None => return,
+                    },
                 };
-                if let Some(session) = self.sessions.get_mut(&session_id) {
-                    session.tool_spans.insert(tool_call_id, span);
+                if let Some(title) = acp::extract_tool_call_title(params) {
+                    tool.record_title(title);
+                }
+                let mcp = tool.record_mcp(params);
+                tool.record_locations(params);
+                tool.record_diffs(params, record.tool_results);
+                tool.record_output(params, record.tool_results);
+                tool.record_raw_input(params, record.tool_args);
+                if mcp {
+                    if let Some(session) = self.sessions.get_mut(&session_id) {
+                        session.turn_mcp_tool_calls += 1;
+                    }
                 }
             }
             "tool_call_update" => {
@@ -524,25 +2711,65 @@ impl SpanManager {
                     Some(id) => id.to_string(),
                     None => return,
                 };
+                let known = self
+                    .sessions
+                    .get(&session_id)
+                    .is_some_and(|s| s.tool_calls.contains_key(&tool_call_id));
+                if !known {
+                    // Some agents never send the initial tool_call; start from the update.
+                    let (span_name, mut attrs) =
+                        self.tool_call_attributes(direction, &session_id, &tool_call_id, params);
+                    attrs.push(KeyValue::new("acp.tool.lazy", true));
+                    let kind = acp::extract_tool_call_kind(params);
+                    let status = acp::extract_tool_call_status(params).unwrap_or("pending");
+                    self.start_tool_call(
+                        &session_id,
+                        &tool_call_id,
+                        span_name,
+                        attrs,
+                        kind,
+                        status,
+                    );
+                }
                 let status = acp::extract_tool_call_status(params).unwrap_or("");
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    if let Some(tool) = session.tool_calls.get_mut(&tool_call_id) {
+                        if let Some(title) = acp::extract_tool_call_title(params) {
+                            tool.record_title(title);
+                        }
+                        if tool.record_mcp(params) {
+                            session.turn_mcp_tool_calls += 1;
+                        }
+                        tool.record_status(status, stamp);
+                        tool.record_locations(params);
+                        tool.record_diffs(params, self.record.tool_results);
+                        tool.record_output(params, self.record.tool_results);
+                        tool.record_raw_input(params, self.record.tool_args);
+                    }
+                }
                 if status == "completed" || status == "failed" {
                     if let Some(session) = self.sessions.get_mut(&session_id) {
-                        if let Some(mut span) = session.tool_spans.remove(&tool_call_id) {
+                        session.finished_tools.insert(tool_call_id.clone());
+                        if let Some(mut tool) = session.tool_calls.remove(&tool_call_id) {
+                            self.totals.count_tool(&tool, status == "failed");
                             if status == "failed" {
-                                span.set_status(Status::error("tool call failed"));
-                                span.set_attribute(KeyValue::new("error.type", "tool_error"));
+                                tool.span.set_status(Status::error("tool call failed"));
+                                tool.span
+                                    .set_attribute(KeyValue::new("error.type", "tool_error"));
                             }
-                            if self.record_content {
+                            if self.record.tool_results {
                                 if let Some(raw) =
                                     params.get("update").and_then(|u| u.get("rawOutput"))
                                 {
-                                    span.set_attribute(KeyValue::new(
+                                    self.content.set(
+                                        &mut tool.span,
                                         "gen_ai.tool.call.result",
                                         raw.to_string(),
-                                    ));
+                                    );
+                                    tool.raw_output_recorded = true;
                                 }
                             }
-                            span.end();
+                            tool.end_at(stamp.time);
                         }
                     }
                 }
@@ -551,27 +2778,324 @@ impl SpanManager {
         }
     }
 
+    /// Record that a signal (e.g. `"SIGSEGV"`) killed the agent, as an error on the
+    /// root session span. Call before [`shutdown`](Self::shutdown).
+    pub fn record_exit_signal(&mut self, signal: &str) {
+        if let Some(root) = self.session_span.as_mut() {
+            root.set_attribute(KeyValue::new("acp.agent.exit_signal", signal.to_string()));
+            root.set_status(Status::error(format!("agent killed by {signal}")));
+        }
+    }
+
     pub fn shutdown(&mut self) {
+        self.end_all(
+            "session ended unexpectedly",
+            "process exited before response",
+        );
+    }
+
+    /// End every open span — sessions, pending requests, then the root — with the given
+    /// error descriptions, and reset the per-root counters.
+    fn end_all(&mut self, session_status: &'static str, pending_status: &'static str) {
+        let stamp = self.stamp();
+        let turns: u64 = self.sessions.values().map(|s| s.turns).sum();
         // End any lingering spans
         for (_, mut session) in self.sessions.drain() {
             if let Some(mut span) = session.prompt_span.take() {
-                span.set_status(Status::error("session ended unexpectedly"));
-                span.end();
+                span.set_status(Status::error(session_status));
+                span.end_with_timestamp(stamp.time);
             }
-            for (_, mut span) in session.tool_spans.drain() {
-                span.set_status(Status::error("session ended unexpectedly"));
-                span.end();
+            for (_, mut tool) in session.tool_calls.drain() {
+                self.totals.count_tool(&tool, true);
+                tool.span.set_status(Status::error(session_status));
+                tool.end_at(stamp.time);
             }
         }
         for (_, pending) in self.pending.drain() {
             if let Some(mut span) = pending.span {
-                span.set_status(Status::error("process exited before response"));
-                span.end();
+                span.set_status(Status::error(pending_status));
+                span.end_with_timestamp(stamp.time);
             }
         }
+        self.timed_out.clear();
+        self.superseded.clear();
+        self.ignored_requests.clear();
         // End the root session span last
         if let Some(mut root) = self.session_span.take() {
-            root.end();
+            root.set_attribute(KeyValue::new("acp.session.turns", turns as i64));
+            if self.orphan_responses > 0 {
+                root.set_attribute(KeyValue::new(
+                    "acp.orphan_responses",
+                    self.orphan_responses as i64,
+                ));
+            }
+            if self.unattached_plan_updates > 0 {
+                root.set_attribute(KeyValue::new(
+                    "acp.plan.unattached_updates",
+                    self.unattached_plan_updates as i64,
+                ));
+            }
+            if let Some(names) = self.available_commands.take() {
+                root.set_attribute(KeyValue::new(
+                    "acp.session.available_commands.count",
+                    names.len() as i64,
+                ));
+                root.set_attribute(KeyValue::new(
+                    "acp.session.available_commands",
+                    names.join(","),
+                ));
+            }
+            root.end_with_timestamp(stamp.time);
+        }
+        self.orphan_responses = 0;
+        self.available_commands = None;
+        self.unattached_plan_updates = 0;
+    }
+}
+
+/// Build `gen_ai.input.messages` for a single user text message.
+/// Media blocks become size-only parts so base64 payloads never reach the exporter.
+fn input_messages(text: &str, media: &[acp::MediaBlock]) -> Value {
+    let mut parts = Vec::new();
+    if !text.is_empty() {
+        parts.push(serde_json::json!({"type": "text", "content": text}));
+    }
+    for block in media {
+        parts.push(serde_json::json!({
+            "type": "blob",
+            "modality": block.kind,
+            "mime_type": block.mime_type,
+            "byte_size": block.byte_size,
+        }));
+    }
+    serde_json::json!([{
+        "role": "user",
+        "parts": parts
+    }])
+}
+
+fn distinct_mime_types<'a>(media: &[acp::MediaBlock<'a>], kind: &str) -> Vec<&'a str> {
+    let mut types = Vec::new();
+    for mime in media
+        .iter()
+        .filter(|m| m.kind == kind)
+        .filter_map(|m| m.mime_type)
+    {
+        if !types.contains(&mime) {
+            types.push(mime);
+        }
+    }
+    types
+}
+
+fn client_capability_attributes(caps: &acp::ClientCapabilities) -> Vec<KeyValue> {
+    vec![
+        KeyValue::new(
+            "acp.client.capabilities.fs.read_text_file",
+            caps.fs_read_text_file,
+        ),
+        KeyValue::new(
+            "acp.client.capabilities.fs.write_text_file",
+            caps.fs_write_text_file,
+        ),
+        KeyValue::new("acp.client.capabilities.terminal", caps.terminal),
+    ]
+}
+
+fn agent_capability_attributes(caps: &acp::AgentCapabilities) -> Vec<KeyValue> {
+    vec![
+        KeyValue::new("acp.agent.capabilities.load_session", caps.load_session),
+        KeyValue::new("acp.agent.capabilities.prompt.image", caps.prompt_image),
+        KeyValue::new("acp.agent.capabilities.prompt.audio", caps.prompt_audio),
+        KeyValue::new(
+            "acp.agent.capabilities.prompt.embedded_context",
+            caps.prompt_embedded_context,
+        ),
+    ]
+}
+
+/// Comma-join the first `max` items, noting how many were left out.
+fn join_capped(items: &[&str], max: usize) -> String {
+    let mut joined = items[..items.len().min(max)].join(",");
+    if items.len() > max {
+        joined.push_str(&format!(",...+{} more", items.len() - max));
+    }
+    joined
+}
+
+fn content_size_attributes(content: &str) -> [KeyValue; 3] {
+    let size = acp::text_size(content);
+    [
+        KeyValue::new("acp.fs.content_bytes", size.bytes as i64),
+        KeyValue::new("acp.fs.content_chars", size.chars as i64),
+        KeyValue::new("acp.fs.content_lines", size.lines as i64),
+    ]
+}
+
+/// Requests the editor sends are calls out to the agent (Client); requests the
+/// agent sends are served by the editor (Server).
+fn request_span_kind(direction: Direction) -> SpanKind {
+    match direction {
+        Direction::EditorToAgent => SpanKind::Client,
+        Direction::AgentToEditor => SpanKind::Server,
+    }
+}
+
+/// `error.type`, `rpc.jsonrpc.error_code` and `error.message` for a JSON-RPC error object.
+fn set_error_attributes(span: &mut impl Span, err: &Value) {
+    match err.get("code").and_then(|c| c.as_i64()) {
+        Some(code) => {
+            span.set_attribute(KeyValue::new("error.type", acp::error_type_for_code(code)));
+            span.set_attribute(KeyValue::new("rpc.jsonrpc.error_code", code));
+        }
+        None => span.set_attribute(KeyValue::new("error.type", "_OTHER")),
+    }
+    if let Some(message) = acp::extract_error_message(err) {
+        span.set_attribute(KeyValue::new("error.message", message.to_string()));
+    }
+}
+
+/// Replace matches of every pattern with `[REDACTED]`. Matches are collected from
+/// the original text and overlapping ones merged, so no pattern sees another's
+/// marker and no part of an overlap is left behind.
+pub(crate) fn redact(patterns: &[Regex], value: String) -> String {
+    let mut ranges: Vec<(usize, usize)> = patterns
+        .iter()
+        .flat_map(|re| re.find_iter(&value).map(|m| (m.start(), m.end())))
+        .filter(|(start, end)| start < end)
+        .collect();
+    if ranges.is_empty() {
+        return value;
+    }
+    ranges.sort_unstable();
+    let mut out = String::with_capacity(value.len());
+    let mut copied = 0;
+    let mut pending = ranges[0];
+    for (start, end) in ranges.into_iter().skip(1).chain([(usize::MAX, usize::MAX)]) {
+        if start <= pending.1 {
+            pending.1 = pending.1.max(end);
+            continue;
+        }
+        out.push_str(&value[copied..pending.0]);
+        out.push_str("[REDACTED]");
+        copied = pending.1;
+        pending = (start, end);
+    }
+    out.push_str(&value[copied..]);
+    out
+}
+
+/// How content attributes are recorded: redacted, then cut to a length limit.
+#[derive(Debug, Clone, Default)]
+struct ContentFilter {
+    max_len: Option<usize>,
+    redact: Vec<Regex>,
+}
+
+impl ContentFilter {
+    fn redact(&self, value: String) -> String {
+        redact(&self.redact, value)
+    }
+
+    /// Most bytes worth buffering for one content attribute.
+    fn buffer_cap(&self) -> usize {
+        self.buffer_cap_within(usize::MAX)
+    }
+
+    /// Most bytes worth buffering for a content attribute also cut to `limit`: the
+    /// effective limit plus [`REDACT_SLACK`].
+    fn buffer_cap_within(&self, limit: usize) -> usize {
+        self.max_len
+            .unwrap_or(usize::MAX)
+            .min(limit)
+            .saturating_add(REDACT_SLACK)
+    }
+
+    fn attributes(&self, key: &'static str, value: String) -> Vec<KeyValue> {
+        self.attributes_omitting(key, value, 0)
+    }
+
+    fn attributes_omitting(
+        &self,
+        key: &'static str,
+        value: String,
+        omitted: usize,
+    ) -> Vec<KeyValue> {
+        self.attributes_within(key, value, omitted, usize::MAX)
+    }
+
+    /// `key = value` for a content attribute whose source lost `omitted` bytes while
+    /// buffering. The value is redacted first; then past `max_len` (or `limit`, if
+    /// lower) bytes, or with anything omitted, it is cut on a character boundary and
+    /// ends in `…[truncated N bytes]` (the whole value stays within the limit), and
+    /// `acp.content.truncated = true` is added.
+    fn attributes_within(
+        &self,
+        key: &'static str,
+        value: String,
+        omitted: usize,
+        limit: usize,
+    ) -> Vec<KeyValue> {
+        let value = self.redact(value);
+        let limit = self.max_len.unwrap_or(usize::MAX).min(limit);
+        if value.len() <= limit && omitted == 0 {
+            return vec![KeyValue::new(key, value)];
+        }
+        let total = value.len() + omitted;
+        // Size the marker for the largest possible count so the result fits.
+        let marker_len = format!("…[truncated {total} bytes]").len();
+        let kept = truncate_utf8(&value, limit.saturating_sub(marker_len));
+        let cut = format!("{kept}…[truncated {} bytes]", total - kept.len());
+        vec![
+            KeyValue::new(key, cut),
+            KeyValue::new("acp.content.truncated", true),
+        ]
+    }
+
+    fn set(&self, span: &mut opentelemetry::global::BoxedSpan, key: &'static str, value: String) {
+        self.set_omitting(span, key, value, 0);
+    }
+
+    fn set_omitting(
+        &self,
+        span: &mut opentelemetry::global::BoxedSpan,
+        key: &'static str,
+        value: String,
+        omitted: usize,
+    ) {
+        self.set_within(span, key, value, omitted, usize::MAX);
+    }
+
+    fn set_within(
+        &self,
+        span: &mut opentelemetry::global::BoxedSpan,
+        key: &'static str,
+        value: String,
+        omitted: usize,
+        limit: usize,
+    ) {
+        for kv in self.attributes_within(key, value, omitted, limit) {
+            span.set_attribute(kv);
         }
     }
 }
+
+/// Append as much of `text` as fits while `buf` holds at most `cap` bytes, cutting on a
+/// character boundary. Returns the bytes left out.
+fn push_capped(buf: &mut String, text: &str, cap: usize) -> usize {
+    let kept = truncate_utf8(text, cap.saturating_sub(buf.len()));
+    buf.push_str(kept);
+    text.len() - kept.len()
+}
+
+/// Truncate to at most `max` bytes without splitting a UTF-8 character.
+fn truncate_utf8(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
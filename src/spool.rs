@@ -0,0 +1,635 @@
+use crate::span_json::{from_unix_nanos, status_from_json, status_json, unix_nanos, value_json};
+use anyhow::{Context, Result};
+use opentelemetry::metrics::Counter;
+use opentelemetry::trace::{
+    Event, Link, SpanContext, SpanId, SpanKind, TraceFlags, TraceId, TraceState,
+};
+use opentelemetry::{Array, InstrumentationScope, KeyValue, Value as OtelValue};
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::trace::{SpanData, SpanEvents, SpanExporter, SpanLinks};
+use opentelemetry_sdk::Resource;
+use serde_json::{json, Value};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Least time between attempts to re-export spooled batches.
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Most spooled batches re-exported in one attempt; the rest wait for the next.
+const MAX_RETRY_BATCHES: usize = 16;
+
+/// `--spool-dir` and `--spool-max-mb`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpoolOptions {
+    pub dir: PathBuf,
+    /// Spool size past which the oldest batches are evicted.
+    pub max_bytes: u64,
+}
+
+/// `--spool-dir`: wraps the OTLP exporter so a batch it fails to export is written to
+/// the spool directory (one JSON Lines file per batch) instead of being dropped.
+/// Spooled batches are re-exported oldest first, up to [`MAX_RETRY_BATCHES`] at a
+/// time: at startup, every [`RETRY_INTERVAL`], and after a successful export once the
+/// interval has passed. Each is deleted once the collector takes it.
+#[derive(Debug)]
+pub struct SpoolExporter<E> {
+    spool: Arc<Spool<E>>,
+    max_bytes: u64,
+}
+
+/// State shared by the exporter and its retry timer.
+#[derive(Debug)]
+struct Spool<E> {
+    inner: Mutex<E>,
+    dir: PathBuf,
+    retry_interval: Duration,
+    /// When the last retry started; `None` before the first.
+    last_retry: Mutex<Option<Instant>>,
+    /// Set while a retry runs, so the timer and an export never send a batch twice.
+    retrying: AtomicBool,
+    /// `acp.spool.batches`; built on first use, once the global meter provider is set.
+    batches: OnceLock<Counter<u64>>,
+}
+
+impl<E: SpanExporter + 'static> SpoolExporter<E> {
+    /// Wrap `inner`, creating the spool directory if needed. Inside a tokio runtime,
+    /// a timer thread retries the spool now and then every [`RETRY_INTERVAL`].
+    pub fn open(inner: E, options: &SpoolOptions) -> Result<Self> {
+        let exporter = Self::with_retry_interval(inner, options, RETRY_INTERVAL)?;
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let spool = Arc::downgrade(&exporter.spool);
+            std::thread::spawn(move || {
+                while let Some(spool) = spool.upgrade() {
+                    runtime.block_on(spool.retry());
+                    let interval = spool.retry_interval;
+                    drop(spool);
+                    std::thread::sleep(interval);
+                }
+            });
+        }
+        Ok(exporter)
+    }
+
+    fn with_retry_interval(inner: E, options: &SpoolOptions, interval: Duration) -> Result<Self> {
+        std::fs::create_dir_all(&options.dir).with_context(|| {
+            format!("failed to create spool directory {}", options.dir.display())
+        })?;
+        Ok(Self {
+            spool: Arc::new(Spool {
+                inner: Mutex::new(inner),
+                dir: options.dir.clone(),
+                retry_interval: interval,
+                last_retry: Mutex::new(None),
+                retrying: AtomicBool::new(false),
+                batches: OnceLock::new(),
+            }),
+            max_bytes: options.max_bytes,
+        })
+    }
+}
+
+impl<E: SpanExporter> Spool<E> {
+    fn batches(&self) -> &Counter<u64> {
+        self.batches.get_or_init(|| {
+            opentelemetry::global::meter("acp-traces")
+                .u64_counter("acp.spool.batches")
+                .with_description("Span batches spooled, recovered, or dropped, by outcome")
+                .build()
+        })
+    }
+
+    /// Whether the retry interval has passed since the last retry.
+    fn retry_due(&self) -> bool {
+        let last_retry = *self.last_retry.lock().unwrap();
+        last_retry.is_none_or(|at| at.elapsed() >= self.retry_interval)
+    }
+
+    /// Re-export up to [`MAX_RETRY_BATCHES`] spooled batches, oldest first, stopping
+    /// at the first failure. Unreadable files are deleted and counted as dropped.
+    async fn retry(&self) {
+        if self.retrying.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        *self.last_retry.lock().unwrap() = Some(Instant::now());
+        let mut sent = 0;
+        for (path, _) in spooled(&self.dir) {
+            if sent == MAX_RETRY_BATCHES {
+                break;
+            }
+            let Some(batch) = read_batch(&path) else {
+                tracing::warn!(path = %path.display(), "dropping unreadable spooled batch");
+                let _ = std::fs::remove_file(&path);
+                count(self.batches(), "dropped", 1);
+                continue;
+            };
+            sent += 1;
+            let export = self.inner.lock().unwrap().export(batch);
+            if export.await.is_err() {
+                break;
+            }
+            let _ = std::fs::remove_file(&path);
+            count(self.batches(), "recovered", 1);
+        }
+        self.retrying.store(false, Ordering::SeqCst);
+    }
+}
+
+type ExportFuture = Pin<Box<dyn Future<Output = OTelSdkResult> + Send>>;
+
+impl<E: SpanExporter + 'static> SpanExporter for SpoolExporter<E> {
+    fn export(&mut self, batch: Vec<SpanData>) -> ExportFuture {
+        let copy = batch.clone();
+        let current = self.spool.inner.lock().unwrap().export(batch);
+        let (spool, max_bytes) = (self.spool.clone(), self.max_bytes);
+        Box::pin(async move {
+            let batches = spool.batches();
+            if let Err(e) = current.await {
+                tracing::warn!(error = %e, "span export failed; spooling the batch");
+                if let Err(e) = write_batch(&spool.dir, &batch_lines(&copy)) {
+                    return Err(OTelSdkError::InternalFailure(format!("span spool: {e}")));
+                }
+                count(batches, "spooled", 1);
+                let evicted = evict(&spool.dir, max_bytes);
+                count(batches, "dropped", evicted);
+                // Collector still down; leave the spool for the next retry.
+                return Ok(());
+            }
+            if spool.retry_due() {
+                spool.retry().await;
+            }
+            Ok(())
+        })
+    }
+
+    fn shutdown(&mut self) -> OTelSdkResult {
+        self.spool.inner.lock().unwrap().shutdown()
+    }
+
+    fn force_flush(&mut self) -> OTelSdkResult {
+        self.spool.inner.lock().unwrap().force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.spool.inner.lock().unwrap().set_resource(resource);
+    }
+}
+
+fn count(batches: &Counter<u64>, outcome: &'static str, n: u64) {
+    if n > 0 {
+        batches.add(n, &[KeyValue::new("acp.spool.outcome", outcome)]);
+    }
+}
+
+/// Spool files with their sizes, oldest first (names sort by creation time).
+fn spooled(dir: &Path) -> Vec<(PathBuf, u64)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+        .filter_map(|path| {
+            let len = path.metadata().ok()?.len();
+            Some((path, len))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+fn write_batch(dir: &Path, lines: &str) -> std::io::Result<()> {
+    let nanos = unix_nanos(SystemTime::now());
+    let name = format!("{nanos:020}-{}", std::process::id());
+    // Write under a temporary name so a retry never reads a half-written batch.
+    let tmp = dir.join(format!("{name}.tmp"));
+    std::fs::write(&tmp, lines)?;
+    std::fs::rename(&tmp, dir.join(format!("{name}.jsonl")))
+}
+
+fn read_batch(path: &Path) -> Option<Vec<SpanData>> {
+    std::fs::read_to_string(path)
+        .ok()?
+        .lines()
+        .map(|line| span_from_json(&serde_json::from_str(line).ok()?))
+        .collect()
+}
+
+/// Delete the oldest spool files until the spool fits in `max_bytes`; returns how
+/// many were deleted.
+fn evict(dir: &Path, max_bytes: u64) -> u64 {
+    let files = spooled(dir);
+    let mut total: u64 = files.iter().map(|(_, len)| len).sum();
+    let mut evicted = 0;
+    for (path, len) in files {
+        if total <= max_bytes {
+            break;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            tracing::warn!(path = %path.display(), "span spool full; dropped oldest batch");
+            evicted += 1;
+        }
+        total -= len;
+    }
+    evicted
+}
+
+fn batch_lines(batch: &[SpanData]) -> String {
+    batch
+        .iter()
+        .map(|span| span_json(span).to_string() + "\n")
+        .collect()
+}
+
+// Unlike `--trace-file`, the spool must read spans back, so values keep their types.
+
+/// `value` tagged with its type, e.g. `{"i64": 3}`.
+fn typed_value_json(value: &OtelValue) -> Value {
+    let tag = match value {
+        OtelValue::Bool(_) => "bool",
+        OtelValue::I64(_) => "i64",
+        OtelValue::F64(_) => "f64",
+        OtelValue::Array(Array::Bool(_)) => "bool[]",
+        OtelValue::Array(Array::I64(_)) => "i64[]",
+        OtelValue::Array(Array::F64(_)) => "f64[]",
+        OtelValue::Array(Array::String(_)) => "str[]",
+        _ => "str",
+    };
+    json!({ tag: value_json(value) })
+}
+
+fn value_from_json(value: &Value) -> Option<OtelValue> {
+    let (kind, v) = value.as_object()?.iter().next()?;
+    let list = || v.as_array();
+    Some(match kind.as_str() {
+        "bool" => OtelValue::Bool(v.as_bool()?),
+        "i64" => OtelValue::I64(v.as_i64()?),
+        "f64" => OtelValue::F64(v.as_f64()?),
+        "str" => OtelValue::String(v.as_str()?.to_string().into()),
+        "bool[]" => OtelValue::Array(Array::Bool(
+            list()?.iter().map(Value::as_bool).collect::<Option<_>>()?,
+        )),
+        "i64[]" => OtelValue::Array(Array::I64(
+            list()?.iter().map(Value::as_i64).collect::<Option<_>>()?,
+        )),
+        "f64[]" => OtelValue::Array(Array::F64(
+            list()?.iter().map(Value::as_f64).collect::<Option<_>>()?,
+        )),
+        "str[]" => OtelValue::Array(Array::String(
+            list()?
+                .iter()
+                .map(|s| Some(s.as_str()?.to_string().into()))
+                .collect::<Option<_>>()?,
+        )),
+        _ => return None,
+    })
+}
+
+fn attributes_json<'a>(attrs: impl IntoIterator<Item = &'a KeyValue>) -> Value {
+    Value::Array(
+        attrs
+            .into_iter()
+            .map(|kv| json!([kv.key.as_str(), typed_value_json(&kv.value)]))
+            .collect(),
+    )
+}
+
+fn attributes_from_json(value: &Value) -> Option<Vec<KeyValue>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|pair| {
+            let key = pair.get(0)?.as_str()?.to_string();
+            Some(KeyValue::new(key, value_from_json(pair.get(1)?)?))
+        })
+        .collect()
+}
+
+fn context_json(cx: &SpanContext) -> Value {
+    json!({
+        "trace_id": cx.trace_id().to_string(),
+        "span_id": cx.span_id().to_string(),
+        "flags": cx.trace_flags().to_u8(),
+        "remote": cx.is_remote(),
+        "state": cx.trace_state().header(),
+    })
+}
+
+fn context_from_json(value: &Value) -> Option<SpanContext> {
+    Some(SpanContext::new(
+        TraceId::from_hex(value["trace_id"].as_str()?).ok()?,
+        SpanId::from_hex(value["span_id"].as_str()?).ok()?,
+        TraceFlags::new(u8::try_from(value["flags"].as_u64()?).ok()?),
+        value["remote"].as_bool()?,
+        value["state"].as_str()?.parse::<TraceState>().ok()?,
+    ))
+}
+
+fn kind_name(kind: &SpanKind) -> &'static str {
+    match kind {
+        SpanKind::Client => "client",
+        SpanKind::Server => "server",
+        SpanKind::Producer => "producer",
+        SpanKind::Consumer => "consumer",
+        SpanKind::Internal => "internal",
+    }
+}
+
+fn span_json(span: &SpanData) -> Value {
+    let events: Vec<Value> = span
+        .events
+        .iter()
+        .map(|event| {
+            json!({
+                "name": event.name,
+                "time": unix_nanos(event.timestamp),
+                "attributes": attributes_json(&event.attributes),
+                "dropped_attributes": event.dropped_attributes_count,
+            })
+        })
+        .collect();
+    let links: Vec<Value> = span
+        .links
+        .iter()
+        .map(|link| {
+            json!({
+                "context": context_json(&link.span_context),
+                "attributes": attributes_json(&link.attributes),
+                "dropped_attributes": link.dropped_attributes_count,
+            })
+        })
+        .collect();
+    let scope = &span.instrumentation_scope;
+    json!({
+        "context": context_json(&span.span_context),
+        "parent_span_id": span.parent_span_id.to_string(),
+        "kind": kind_name(&span.span_kind),
+        "name": span.name,
+        "start": unix_nanos(span.start_time),
+        "end": unix_nanos(span.end_time),
+        "attributes": attributes_json(&span.attributes),
+        "dropped_attributes": span.dropped_attributes_count,
+        "events": events,
+        "dropped_events": span.events.dropped_count,
+        "links": links,
+        "dropped_links": span.links.dropped_count,
+        "status": status_json(&span.status),
+        "scope": {
+            "name": scope.name(),
+            "version": scope.version(),
+            "schema_url": scope.schema_url(),
+            "attributes": attributes_json(scope.attributes()),
+        },
+    })
+}
+
+fn span_from_json(value: &Value) -> Option<SpanData> {
+    let dropped = |key: &str| u32::try_from(value[key].as_u64()?).ok();
+    let span_kind = match value["kind"].as_str()? {
+        "client" => SpanKind::Client,
+        "server" => SpanKind::Server,
+        "producer" => SpanKind::Producer,
+        "consumer" => SpanKind::Consumer,
+        _ => SpanKind::Internal,
+    };
+    let mut events = SpanEvents::default();
+    events.dropped_count = dropped("dropped_events")?;
+    for event in value["events"].as_array()? {
+        events.events.push(Event::new(
+            event["name"].as_str()?.to_string(),
+            from_unix_nanos(&event["time"])?,
+            attributes_from_json(&event["attributes"])?,
+            u32::try_from(event["dropped_attributes"].as_u64()?).ok()?,
+        ));
+    }
+    let mut links = SpanLinks::default();
+    links.dropped_count = dropped("dropped_links")?;
+    for link in value["links"].as_array()? {
+        links.links.push(Link::new(
+            context_from_json(&link["context"])?,
+            attributes_from_json(&link["attributes"])?,
+            u32::try_from(link["dropped_attributes"].as_u64()?).ok()?,
+        ));
+    }
+    let scope = &value["scope"];
+    let mut scope_builder = InstrumentationScope::builder(scope["name"].as_str()?.to_string())
+        .with_attributes(attributes_from_json(&scope["attributes"])?);
+    if let Some(version) = scope["version"].as_str() {
+        scope_builder = scope_builder.with_version(version.to_string());
+    }
+    if let Some(schema_url) = scope["schema_url"].as_str() {
+        scope_builder = scope_builder.with_schema_url(schema_url.to_string());
+    }
+    Some(SpanData {
+        span_context: context_from_json(&value["context"])?,
+        parent_span_id: SpanId::from_hex(value["parent_span_id"].as_str()?).ok()?,
+        span_kind,
+        name: value["name"].as_str()?.to_string().into(),
+        start_time: from_unix_nanos(&value["start"])?,
+        end_time: from_unix_nanos(&value["end"])?,
+        attributes: attributes_from_json(&value["attributes"])?,
+        dropped_attributes_count: dropped("dropped_attributes")?,
+        events,
+        links,
+        status: status_from_json(&value["status"])?,
+        instrumentation_scope: scope_builder.build(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{Span, Status, Tracer, TracerProvider};
+    use opentelemetry::StringValue;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    /// Fails every export while `down` is set; records what it was given either way.
+    #[derive(Debug, Clone, Default)]
+    struct FlakyExporter {
+        down: Arc<AtomicBool>,
+        attempted: Arc<Mutex<Vec<SpanData>>>,
+        exported: Arc<Mutex<Vec<SpanData>>>,
+    }
+
+    impl SpanExporter for FlakyExporter {
+        fn export(&mut self, batch: Vec<SpanData>) -> ExportFuture {
+            self.attempted.lock().unwrap().extend(batch.iter().cloned());
+            let result = if self.down.load(Ordering::SeqCst) {
+                Err(OTelSdkError::InternalFailure("collector down".into()))
+            } else {
+                self.exported.lock().unwrap().extend(batch);
+                Ok(())
+            };
+            Box::pin(std::future::ready(result))
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("acp-traces-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// A spool retried after every successful export, with no timer.
+    fn spool(inner: FlakyExporter, dir: &Path, max_bytes: u64) -> SpoolExporter<FlakyExporter> {
+        let options = SpoolOptions {
+            dir: dir.to_path_buf(),
+            max_bytes,
+        };
+        SpoolExporter::with_retry_interval(inner, &options, Duration::ZERO).unwrap()
+    }
+
+    fn export_one(provider: &SdkTracerProvider, name: &'static str) {
+        let tracer = provider.tracer("spool-test");
+        let mut span = tracer.start(name);
+        span.set_attribute(KeyValue::new("acp.method.name", "session/prompt"));
+        span.set_attribute(KeyValue::new("gen_ai.usage.input_tokens", 12i64));
+        span.set_attribute(KeyValue::new("ratio", 0.5));
+        span.set_attribute(KeyValue::new("ok", true));
+        span.set_attribute(KeyValue::new(
+            "process.command_args",
+            OtelValue::Array(vec![StringValue::from("agent"), "--acp".into()].into()),
+        ));
+        span.add_event(
+            "acp.tool.status",
+            vec![KeyValue::new("acp.tool.status", "completed")],
+        );
+        span.set_status(Status::error("boom"));
+        span.end();
+    }
+
+    #[test]
+    fn failed_batches_are_spooled_and_recovered_intact() {
+        let dir = temp_dir("spool-recover");
+        let inner = FlakyExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(spool(inner.clone(), &dir, u64::MAX))
+            .build();
+
+        inner.down.store(true, Ordering::SeqCst);
+        export_one(&provider, "first");
+        export_one(&provider, "second");
+        assert!(inner.exported.lock().unwrap().is_empty());
+        assert_eq!(spooled(&dir).len(), 2);
+
+        inner.down.store(false, Ordering::SeqCst);
+        export_one(&provider, "third");
+        assert!(spooled(&dir).is_empty());
+
+        let exported = inner.exported.lock().unwrap();
+        let names: Vec<_> = exported.iter().map(|s| s.name.as_ref()).collect();
+        assert_eq!(names, vec!["third", "first", "second"]);
+        // What comes back from disk is exactly what failed to export.
+        let attempted = inner.attempted.lock().unwrap();
+        assert_eq!(exported[1], attempted[0]);
+        assert_eq!(exported[2], attempted[1]);
+        drop((exported, attempted));
+        provider.shutdown().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn spooled_batches_wait_for_the_retry_interval() {
+        let dir = temp_dir("spool-interval");
+        let inner = FlakyExporter::default();
+        let options = SpoolOptions {
+            dir: dir.clone(),
+            max_bytes: u64::MAX,
+        };
+        let exporter =
+            SpoolExporter::with_retry_interval(inner.clone(), &options, Duration::from_secs(3600))
+                .unwrap();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter)
+            .build();
+
+        for name in ["spooled", "live", "spooled again", "live again"] {
+            inner
+                .down
+                .store(name.starts_with("spooled"), Ordering::SeqCst);
+            export_one(&provider, name);
+        }
+        // The first success retried; the next retry is an hour away.
+        let exported = inner.exported.lock().unwrap();
+        let names: Vec<_> = exported.iter().map(|s| s.name.as_ref()).collect();
+        assert_eq!(names, vec!["live", "spooled", "live again"]);
+        assert_eq!(spooled(&dir).len(), 1);
+        drop(exported);
+        provider.shutdown().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn retries_wait_for_a_success_and_are_bounded() {
+        let dir = temp_dir("spool-bounded");
+        let inner = FlakyExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(spool(inner.clone(), &dir, u64::MAX))
+            .build();
+
+        inner.down.store(true, Ordering::SeqCst);
+        for _ in 0..MAX_RETRY_BATCHES + 4 {
+            export_one(&provider, "spooled");
+        }
+        // A failed export doesn't try the spool.
+        assert_eq!(inner.attempted.lock().unwrap().len(), MAX_RETRY_BATCHES + 4);
+
+        inner.down.store(false, Ordering::SeqCst);
+        export_one(&provider, "live");
+        assert_eq!(inner.exported.lock().unwrap().len(), 1 + MAX_RETRY_BATCHES);
+        assert_eq!(spooled(&dir).len(), 4);
+        provider.shutdown().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn full_spool_evicts_oldest_batches() {
+        let dir = temp_dir("spool-evict");
+        let inner = FlakyExporter::default();
+        inner.down.store(true, Ordering::SeqCst);
+        // Room for about two single-span batches.
+        let one_batch = {
+            let provider = SdkTracerProvider::builder()
+                .with_simple_exporter(spool(inner.clone(), &dir, u64::MAX))
+                .build();
+            export_one(&provider, "probe");
+            let size = spooled(&dir)[0].1;
+            std::fs::remove_dir_all(&dir).unwrap();
+            size
+        };
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(spool(inner.clone(), &dir, one_batch * 2 + one_batch / 2))
+            .build();
+        for name in ["oldest", "middle", "newest"] {
+            export_one(&provider, name);
+            std::thread::sleep(Duration::from_millis(2));
+        }
+
+        let left: Vec<_> = spooled(&dir)
+            .into_iter()
+            .map(|(path, _)| read_batch(&path).unwrap()[0].name.to_string())
+            .collect();
+        assert_eq!(left, vec!["middle", "newest"]);
+        provider.shutdown().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_spool_files_are_dropped() {
+        let dir = temp_dir("spool-corrupt");
+        let inner = FlakyExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(spool(inner.clone(), &dir, u64::MAX))
+            .build();
+        std::fs::write(dir.join("00000000000000000001-1.jsonl"), "not json\n").unwrap();
+        export_one(&provider, "live");
+        assert!(spooled(&dir).is_empty());
+        assert_eq!(inner.exported.lock().unwrap().len(), 1);
+        provider.shutdown().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,239 @@
+use crate::transcript::rfc3339;
+use anyhow::{Context, Result};
+use opentelemetry::logs::{AnyValue, LogRecord, Logger, Severity};
+use opentelemetry::trace::SpanContext;
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// `--capture-stderr`: the agent's stderr lines as OTel log records, tied to the
+/// span that was active when each line was read.
+pub struct StderrLogger<L> {
+    logger: L,
+}
+
+impl<L: Logger> StderrLogger<L> {
+    pub fn new(logger: L) -> Self {
+        Self { logger }
+    }
+
+    /// Emit one stderr line, read at `at`. `context` (typically
+    /// [`SpanManager::active_span_context`](crate::spans::SpanManager::active_span_context))
+    /// puts the record inline in that span's trace.
+    pub fn emit(&self, line: &str, at: SystemTime, context: Option<&SpanContext>) {
+        let (severity, text) = severity(line);
+        let mut record = self.logger.create_log_record();
+        record.set_timestamp(at);
+        record.set_observed_timestamp(at);
+        record.set_severity_number(severity);
+        record.set_severity_text(text);
+        record.set_body(AnyValue::from(line.to_string()));
+        record.add_attribute("log.iostream", "stderr");
+        if let Some(cx) = context.filter(|cx| cx.is_valid()) {
+            record.set_trace_context(cx.trace_id(), cx.span_id(), Some(cx.trace_flags()));
+        }
+        self.logger.emit(record);
+    }
+}
+
+/// `--stderr-file`: the agent's stderr copied to a file instead of the terminal.
+pub struct StderrFile {
+    /// `None` once a write failed; the caller goes back to echoing lines itself.
+    writer: Option<LineWriter<File>>,
+    timestamps: bool,
+}
+
+impl StderrFile {
+    /// Open `path`, creating its parent directories, appending to an existing file or
+    /// truncating it. With `timestamps`, each line is prefixed with its RFC 3339 time.
+    pub fn open(path: &Path, append: bool, timestamps: bool) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .with_context(|| format!("failed to open stderr file {}", path.display()))?;
+        Ok(Self {
+            writer: Some(LineWriter::new(file)),
+            timestamps,
+        })
+    }
+
+    /// Write one raw line of stderr, newline included, read at `at`. False if the file
+    /// is unusable and the line should be passed through instead.
+    pub fn write(&mut self, line: &[u8], at: SystemTime) -> bool {
+        let Some(writer) = self.writer.as_mut() else {
+            return false;
+        };
+        let result = if self.timestamps {
+            write!(writer, "{} ", rfc3339(at)).and_then(|()| writer.write_all(line))
+        } else {
+            writer.write_all(line)
+        };
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                self.disable(e);
+                false
+            }
+        }
+    }
+
+    /// Flush a trailing line with no newline; call once the agent's stderr closes.
+    pub fn flush(&mut self) {
+        if let Some(Err(e)) = self.writer.as_mut().map(|w| w.flush()) {
+            self.disable(e);
+        }
+    }
+
+    fn disable(&mut self, error: std::io::Error) {
+        tracing::warn!(error = %error, "stderr file write failed; passing agent stderr through");
+        self.writer = None;
+    }
+}
+
+/// Guess a severity from the line's text: `panic` is FATAL, `error` ERROR, `warn`
+/// WARN (any case), anything else INFO.
+fn severity(line: &str) -> (Severity, &'static str) {
+    let line = line.to_ascii_lowercase();
+    if line.contains("panic") {
+        (Severity::Fatal, "FATAL")
+    } else if line.contains("error") {
+        (Severity::Error, "ERROR")
+    } else if line.contains("warn") {
+        (Severity::Warn, "WARN")
+    } else {
+        (Severity::Info, "INFO")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SpanId, TraceFlags, TraceId, TraceState};
+    use opentelemetry::Key;
+    use std::borrow::Cow;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Debug, Default)]
+    struct Record {
+        severity: Option<Severity>,
+        body: Option<AnyValue>,
+        trace: Option<(TraceId, SpanId)>,
+    }
+
+    impl LogRecord for Record {
+        fn set_event_name(&mut self, _name: &'static str) {}
+        fn set_target<T: Into<Cow<'static, str>>>(&mut self, _target: T) {}
+        fn set_timestamp(&mut self, _timestamp: SystemTime) {}
+        fn set_observed_timestamp(&mut self, _timestamp: SystemTime) {}
+        fn set_severity_text(&mut self, _text: &'static str) {}
+        fn set_severity_number(&mut self, number: Severity) {
+            self.severity = Some(number);
+        }
+        fn set_body(&mut self, body: AnyValue) {
+            self.body = Some(body);
+        }
+        fn add_attributes<I, K, V>(&mut self, _attributes: I)
+        where
+            I: IntoIterator<Item = (K, V)>,
+            K: Into<Key>,
+            V: Into<AnyValue>,
+        {
+        }
+        fn add_attribute<K: Into<Key>, V: Into<AnyValue>>(&mut self, _key: K, _value: V) {}
+        fn set_trace_context(&mut self, trace_id: TraceId, span_id: SpanId, _: Option<TraceFlags>) {
+            self.trace = Some((trace_id, span_id));
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingLogger(Arc<Mutex<Vec<Record>>>);
+
+    impl Logger for RecordingLogger {
+        type LogRecord = Record;
+        fn create_log_record(&self) -> Record {
+            Record::default()
+        }
+        fn emit(&self, record: Record) {
+            self.0.lock().unwrap().push(record);
+        }
+    }
+
+    #[test]
+    fn stderr_file_creates_directories_and_stamps_lines() {
+        let dir = std::env::temp_dir().join(format!("acp-traces-stderr-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("nested").join("agent.log");
+        let at = std::time::UNIX_EPOCH + std::time::Duration::from_millis(1_500);
+
+        let mut file = StderrFile::open(&path, false, true).unwrap();
+        assert!(file.write(b"starting\n", at));
+        assert!(file.write(b"no newline", at));
+        file.flush();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "1970-01-01T00:00:01.500Z starting\n1970-01-01T00:00:01.500Z no newline"
+        );
+
+        let mut file = StderrFile::open(&path, true, false).unwrap();
+        assert!(file.write(b"\nagain\n", at));
+        file.flush();
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .ends_with("no newline\nagain\n"));
+
+        let mut file = StderrFile::open(&path, false, false).unwrap();
+        file.flush();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn severity_is_raised_for_errors_and_panics() {
+        assert_eq!(severity("listening on stdio").0, Severity::Info);
+        assert_eq!(severity("WARNING: deprecated flag").0, Severity::Warn);
+        assert_eq!(severity("Error: connection refused").0, Severity::Error);
+        assert_eq!(
+            severity("thread 'main' panicked at src/main.rs:3").0,
+            Severity::Fatal
+        );
+    }
+
+    #[test]
+    fn lines_carry_the_active_span_context() {
+        let logger = RecordingLogger::default();
+        let records = logger.0.clone();
+        let stderr = StderrLogger::new(logger);
+        let cx = SpanContext::new(
+            TraceId::from(7),
+            SpanId::from(9),
+            TraceFlags::SAMPLED,
+            false,
+            TraceState::default(),
+        );
+        stderr.emit("error: boom", SystemTime::now(), Some(&cx));
+        stderr.emit("idle", SystemTime::now(), None);
+        stderr.emit(
+            "idle",
+            SystemTime::now(),
+            Some(&SpanContext::empty_context()),
+        );
+
+        let records = records.lock().unwrap();
+        assert_eq!(records[0].trace, Some((TraceId::from(7), SpanId::from(9))));
+        assert_eq!(records[0].severity, Some(Severity::Error));
+        assert_eq!(
+            records[0].body,
+            Some(AnyValue::from("error: boom".to_string()))
+        );
+        assert_eq!(records[1].trace, None);
+        assert_eq!(records[2].trace, None);
+    }
+}
@@ -0,0 +1,139 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Session totals for the report printed at exit (`--no-summary`, `--summary-file`).
+/// Built from the message flow alone, so it's there even when nothing is exported.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Summary {
+    pub prompts: u64,
+    /// Prompt turns that got a response, in seconds.
+    pub turn_duration: Option<Stats>,
+    /// Time to the first streamed chunk of each turn, in seconds.
+    pub time_to_first_token: Option<Stats>,
+    /// Tool calls by name, as in `gen_ai.tool.name`: the MCP tool, else the final title.
+    pub tool_calls: BTreeMap<String, ToolCounts>,
+    /// Characters of agent message text streamed.
+    pub output_chars: u64,
+    /// As the proxy reports it: 128 + the signal number if a signal ended the agent.
+    pub agent_exit_code: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Stats {
+    pub count: usize,
+    pub p50: f64,
+    pub p95: f64,
+    pub max: f64,
+}
+
+impl Stats {
+    /// Nearest-rank percentiles of `samples`, or `None` if there are none.
+    pub fn of(samples: &[f64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let rank = |p: f64| sorted[((p * sorted.len() as f64).ceil() as usize).max(1) - 1];
+        Some(Self {
+            count: sorted.len(),
+            p50: rank(0.5),
+            p95: rank(0.95),
+            max: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ToolCounts {
+    pub calls: u64,
+    pub failed: u64,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "acp-traces session summary")?;
+        writeln!(f, "  prompts: {}", self.prompts)?;
+        let stats = |f: &mut fmt::Formatter<'_>, name: &str, stats: &Option<Stats>| match stats {
+            Some(s) => writeln!(
+                f,
+                "  {name}: p50 {:.2}s, p95 {:.2}s, max {:.2}s",
+                s.p50, s.p95, s.max
+            ),
+            None => Ok(()),
+        };
+        stats(f, "turn duration", &self.turn_duration)?;
+        stats(f, "time to first token", &self.time_to_first_token)?;
+        let calls: u64 = self.tool_calls.values().map(|t| t.calls).sum();
+        let failed: u64 = self.tool_calls.values().map(|t| t.failed).sum();
+        write!(f, "  tool calls: {calls}")?;
+        if failed > 0 {
+            write!(f, " ({failed} failed)")?;
+        }
+        for (i, (name, counts)) in self.tool_calls.iter().enumerate() {
+            write!(
+                f,
+                "{}{name} {}",
+                if i == 0 { " — " } else { ", " },
+                counts.calls
+            )?;
+            if counts.failed > 0 {
+                write!(f, " ({} failed)", counts.failed)?;
+            }
+        }
+        writeln!(f)?;
+        writeln!(f, "  output: {} chars", self.output_chars)?;
+        if let Some(code) = self.agent_exit_code {
+            writeln!(f, "  agent exit code: {code}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_use_nearest_rank() {
+        assert_eq!(Stats::of(&[]), None);
+        let one = Stats::of(&[2.0]).unwrap();
+        assert_eq!((one.p50, one.p95, one.max), (2.0, 2.0, 2.0));
+        let samples: Vec<f64> = (1..=20).rev().map(f64::from).collect();
+        let stats = Stats::of(&samples).unwrap();
+        assert_eq!(stats.count, 20);
+        assert_eq!((stats.p50, stats.p95, stats.max), (10.0, 19.0, 20.0));
+    }
+
+    #[test]
+    fn report_lists_tools_by_name() {
+        let summary = Summary {
+            prompts: 2,
+            turn_duration: Stats::of(&[1.0, 3.0]),
+            time_to_first_token: None,
+            tool_calls: BTreeMap::from([
+                (
+                    "Edit main.rs".to_string(),
+                    ToolCounts {
+                        calls: 1,
+                        failed: 1,
+                    },
+                ),
+                (
+                    "read_file".to_string(),
+                    ToolCounts {
+                        calls: 3,
+                        failed: 0,
+                    },
+                ),
+            ]),
+            output_chars: 42,
+            agent_exit_code: Some(0),
+        };
+        assert_eq!(
+            summary.to_string(),
+            "acp-traces session summary\n  prompts: 2\n  turn duration: p50 1.00s, p95 3.00s, max 3.00s\n  tool calls: 4 (1 failed) — Edit main.rs 1 (1 failed), read_file 3\n  output: 42 chars\n  agent exit code: 0\n"
+        );
+    }
+}
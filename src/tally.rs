@@ -0,0 +1,154 @@
+use opentelemetry::Context;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanExporter, SpanProcessor};
+use opentelemetry_sdk::Resource;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Spans queued for export versus spans the exporter is done with (sent, spooled,
+/// or failed), so a shutdown that runs out of time can say how many it dropped.
+#[derive(Debug, Default)]
+pub struct SpanTally {
+    queued: AtomicU64,
+    exported: AtomicU64,
+}
+
+impl SpanTally {
+    /// Spans still waiting in the queue or in an unfinished export request.
+    pub fn pending(&self) -> u64 {
+        let exported = self.exported.load(Ordering::Relaxed);
+        self.queued.load(Ordering::Relaxed).saturating_sub(exported)
+    }
+}
+
+/// Counts sampled spans on their way into `inner`, typically a batch processor.
+#[derive(Debug)]
+pub struct TalliedProcessor<P> {
+    inner: P,
+    tally: Arc<SpanTally>,
+}
+
+impl<P> TalliedProcessor<P> {
+    pub fn new(inner: P, tally: Arc<SpanTally>) -> Self {
+        Self { inner, tally }
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for TalliedProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        // The batch processor discards unsampled spans; don't count them as pending.
+        if span.span_context.is_sampled() {
+            self.tally.queued.fetch_add(1, Ordering::Relaxed);
+        }
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown(&self) -> OTelSdkResult {
+        self.inner.shutdown()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+/// Counts spans whose export request finished, whatever the outcome.
+#[derive(Debug)]
+pub struct TalliedExporter<E> {
+    inner: E,
+    tally: Arc<SpanTally>,
+}
+
+impl<E> TalliedExporter<E> {
+    pub fn new(inner: E, tally: Arc<SpanTally>) -> Self {
+        Self { inner, tally }
+    }
+}
+
+type ExportFuture = Pin<Box<dyn Future<Output = OTelSdkResult> + Send>>;
+
+impl<E: SpanExporter> SpanExporter for TalliedExporter<E> {
+    fn export(&mut self, batch: Vec<SpanData>) -> ExportFuture {
+        let (n, tally) = (batch.len() as u64, self.tally.clone());
+        let export = self.inner.export(batch);
+        Box::pin(async move {
+            let result = export.await;
+            tally.exported.fetch_add(n, Ordering::Relaxed);
+            result
+        })
+    }
+
+    fn shutdown(&mut self) -> OTelSdkResult {
+        self.inner.shutdown()
+    }
+
+    fn force_flush(&mut self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{Tracer, TracerProvider};
+    use opentelemetry_sdk::trace::{BatchConfigBuilder, BatchSpanProcessor, SdkTracerProvider};
+    use std::sync::atomic::AtomicBool;
+
+    /// Holds every export until `open` is set, like an unreachable collector.
+    #[derive(Debug, Clone, Default)]
+    struct GatedExporter {
+        open: Arc<AtomicBool>,
+    }
+
+    impl SpanExporter for GatedExporter {
+        fn export(&mut self, _batch: Vec<SpanData>) -> ExportFuture {
+            let open = self.open.clone();
+            Box::pin(async move {
+                while !open.load(Ordering::SeqCst) {
+                    std::thread::sleep(std::time::Duration::from_millis(5));
+                }
+                Ok(())
+            })
+        }
+    }
+
+    #[test]
+    fn pending_spans_drain_once_exports_finish() {
+        let tally = Arc::new(SpanTally::default());
+        let exporter = GatedExporter::default();
+        let processor =
+            BatchSpanProcessor::builder(TalliedExporter::new(exporter.clone(), tally.clone()))
+                .with_batch_config(
+                    BatchConfigBuilder::default()
+                        .with_max_export_batch_size(2)
+                        .build(),
+                )
+                .build();
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(TalliedProcessor::new(processor, tally.clone()))
+            .build();
+        let tracer = provider.tracer("test");
+        for _ in 0..3 {
+            tracer.start("span");
+        }
+        assert_eq!(tally.pending(), 3);
+
+        exporter.open.store(true, Ordering::SeqCst);
+        provider.force_flush().unwrap();
+        assert_eq!(tally.pending(), 0);
+    }
+}
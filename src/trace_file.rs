@@ -0,0 +1,203 @@
+use crate::span_json::{status_json, unix_nanos, value_json};
+use anyhow::{Context, Result};
+use opentelemetry::trace::SpanId;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::trace::{SpanData, SpanExporter};
+use opentelemetry_sdk::Resource;
+use serde_json::{json, Map, Value};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// `--trace-file`: writes each span as one JSON object per line (JSON Lines).
+#[derive(Debug)]
+pub struct FileExporter {
+    writer: BufWriter<File>,
+    resource: Value,
+}
+
+impl FileExporter {
+    /// Open `path`, appending to an existing file or truncating it.
+    pub fn open(path: &Path, append: bool) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .with_context(|| format!("failed to open trace file {}", path.display()))?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+            resource: Value::Object(Map::new()),
+        })
+    }
+
+    fn flush(&mut self) -> OTelSdkResult {
+        self.writer
+            .flush()
+            .map_err(|e| OTelSdkError::InternalFailure(format!("trace file flush: {e}")))
+    }
+}
+
+fn attributes_json<'a>(attrs: impl IntoIterator<Item = &'a opentelemetry::KeyValue>) -> Value {
+    Value::Object(
+        attrs
+            .into_iter()
+            .map(|kv| (kv.key.to_string(), value_json(&kv.value)))
+            .collect(),
+    )
+}
+
+fn span_json(span: &SpanData, resource: &Value) -> Value {
+    let parent = (span.parent_span_id != SpanId::INVALID).then(|| span.parent_span_id.to_string());
+    let events: Vec<Value> = span
+        .events
+        .iter()
+        .map(|event| {
+            json!({
+                "name": event.name,
+                "time_unix_nano": unix_nanos(event.timestamp),
+                "attributes": attributes_json(&event.attributes),
+            })
+        })
+        .collect();
+    json!({
+        "name": span.name,
+        "trace_id": span.span_context.trace_id().to_string(),
+        "span_id": span.span_context.span_id().to_string(),
+        "parent_span_id": parent,
+        "kind": format!("{:?}", span.span_kind),
+        "start_time_unix_nano": unix_nanos(span.start_time),
+        "end_time_unix_nano": unix_nanos(span.end_time),
+        "attributes": attributes_json(&span.attributes),
+        "events": events,
+        "status": status_json(&span.status),
+        "resource": resource,
+    })
+}
+
+impl SpanExporter for FileExporter {
+    fn export(
+        &mut self,
+        batch: Vec<SpanData>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = OTelSdkResult> + Send>> {
+        let mut result = Ok(());
+        for span in &batch {
+            let line = span_json(span, &self.resource).to_string();
+            if let Err(e) = writeln!(self.writer, "{line}") {
+                result = Err(OTelSdkError::InternalFailure(format!(
+                    "trace file write: {e}"
+                )));
+                break;
+            }
+        }
+        // Flush per batch so a crash loses at most the spans still queued.
+        let result = result.and_then(|()| self.flush());
+        Box::pin(std::future::ready(result))
+    }
+
+    fn shutdown(&mut self) -> OTelSdkResult {
+        self.flush()
+    }
+
+    fn force_flush(&mut self) -> OTelSdkResult {
+        self.flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.resource = Value::Object(
+            resource
+                .iter()
+                .map(|(k, v)| (k.to_string(), value_json(v)))
+                .collect(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use acp_traces::acp::Direction;
+    use acp_traces::spans::SpanManager;
+    use opentelemetry::trace::{Tracer, TracerProvider};
+    use opentelemetry::KeyValue;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    fn read_spans(path: &Path) -> Vec<Value> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("acp-traces-{name}-{}.jsonl", std::process::id()))
+    }
+
+    #[test]
+    fn spans_round_trip_with_hierarchy_and_resource() {
+        let path = temp_path("trace-file");
+        let provider = SdkTracerProvider::builder()
+            .with_resource(
+                Resource::builder_empty()
+                    .with_attribute(KeyValue::new("service.name", "file-test"))
+                    .build(),
+            )
+            .with_batch_exporter(FileExporter::open(&path, false).unwrap())
+            .build();
+        let tracer = opentelemetry::global::BoxedTracer::new(Box::new(provider.tracer("test")));
+        let mut mgr = SpanManager::new(tracer, opentelemetry::global::meter("test"), false);
+        mgr.process_message(
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+        );
+        mgr.process_message(
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":0,"result":{"protocolVersion":1}}"#,
+        );
+        mgr.shutdown();
+        provider.force_flush().unwrap();
+
+        let spans = read_spans(&path);
+        let root = spans.iter().find(|s| s["name"] == "acp_session").unwrap();
+        let init = spans.iter().find(|s| s["name"] == "initialize").unwrap();
+        assert_eq!(root["parent_span_id"], Value::Null);
+        assert_eq!(init["parent_span_id"], root["span_id"]);
+        assert_eq!(init["trace_id"], root["trace_id"]);
+        assert_eq!(init["attributes"]["rpc.method"], "initialize");
+        assert_eq!(init["resource"]["service.name"], "file-test");
+        assert!(init["end_time_unix_nano"].as_u64() >= init["start_time_unix_nano"].as_u64());
+
+        provider.shutdown().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn append_keeps_existing_lines_and_truncate_drops_them() {
+        let path = temp_path("trace-file-append");
+        std::fs::write(&path, "{\"name\":\"earlier\"}\n").unwrap();
+        let write_one = |append: bool| {
+            let provider = SdkTracerProvider::builder()
+                .with_simple_exporter(FileExporter::open(&path, append).unwrap())
+                .build();
+            drop(provider.tracer("test").start("later"));
+            provider.shutdown().unwrap();
+        };
+
+        write_one(true);
+        let names: Vec<_> = read_spans(&path)
+            .into_iter()
+            .map(|s| s["name"].clone())
+            .collect();
+        assert_eq!(names, vec![json!("earlier"), json!("later")]);
+
+        write_one(false);
+        let names: Vec<_> = read_spans(&path)
+            .into_iter()
+            .map(|s| s["name"].clone())
+            .collect();
+        assert_eq!(names, vec![json!("later")]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+use crate::acp::{self, Direction};
+use crate::spans::{self, SpanManager};
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::path::Path;
+use std::time::SystemTime;
+use time::format_description::well_known::Rfc3339;
+use time::format_description::BorrowedFormatItem;
+use time::macros::format_description;
+use time::OffsetDateTime;
+
+/// `--dump-jsonl`: the raw ACP traffic, one `{"ts","dir","msg"}` object per line.
+/// `msg` is the intercepted line when it is JSON, else a JSON string. Either way
+/// `--redact-pattern` matches are replaced in its strings and `authenticate` params
+/// are cut down to the `methodId`.
+pub struct Transcript {
+    /// `None` once a write failed; dumping stops rather than logging every line.
+    writer: Option<LineWriter<File>>,
+    redact: Vec<Regex>,
+}
+
+impl Transcript {
+    /// Create (or truncate) the transcript file at `path`.
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create transcript {}", path.display()))?;
+        Ok(Self {
+            writer: Some(LineWriter::new(file)),
+            redact: Vec::new(),
+        })
+    }
+
+    /// Replace every match of `patterns` in recorded strings with `[REDACTED]`.
+    pub fn with_redact_patterns(mut self, patterns: Vec<Regex>) -> Self {
+        self.redact = patterns;
+        self
+    }
+
+    /// Append one intercepted line, seen at `at`.
+    pub fn record(&mut self, direction: Direction, line: &str, at: SystemTime) {
+        let Some(writer) = self.writer.as_mut() else {
+            return;
+        };
+        let msg = match serde_json::from_str::<Value>(line) {
+            Ok(msg) if self.redact.is_empty() && !is_authenticate(&msg) => line.to_string(),
+            Ok(mut msg) => {
+                scrub(&mut msg, &self.redact);
+                msg.to_string()
+            }
+            Err(_) => Value::String(spans::redact(&self.redact, line.to_string())).to_string(),
+        };
+        let entry = format!(
+            r#"{{"ts":"{}","dir":"{}","msg":{msg}}}"#,
+            rfc3339(at),
+            direction.as_str()
+        );
+        if let Err(e) = writeln!(writer, "{entry}") {
+            self.disable(e);
+        }
+    }
+
+    /// Flush buffered output; call before exit.
+    pub fn flush(&mut self) {
+        if let Some(Err(e)) = self.writer.as_mut().map(|w| w.flush()) {
+            self.disable(e);
+        }
+    }
+
+    fn disable(&mut self, error: std::io::Error) {
+        tracing::warn!(error = %error, "transcript write failed; no longer dumping");
+        self.writer = None;
+    }
+}
+
+fn is_authenticate(msg: &Value) -> bool {
+    msg.get("method").and_then(Value::as_str) == Some("authenticate")
+}
+
+/// Drop `authenticate` params other than `methodId`, which may be credentials, and
+/// redact every string left in `msg`.
+fn scrub(msg: &mut Value, patterns: &[Regex]) {
+    if is_authenticate(msg) {
+        let method_id = msg.get("params").and_then(acp::extract_auth_method_id);
+        let params = match method_id {
+            Some(id) => serde_json::json!({ "methodId": id }),
+            None => serde_json::json!({}),
+        };
+        msg["params"] = params;
+    }
+    redact_strings(msg, patterns);
+}
+
+fn redact_strings(value: &mut Value, patterns: &[Regex]) {
+    match value {
+        Value::String(s) => *s = spans::redact(patterns, std::mem::take(s)),
+        Value::Array(items) => items.iter_mut().for_each(|v| redact_strings(v, patterns)),
+        Value::Object(map) => map.values_mut().for_each(|v| redact_strings(v, patterns)),
+        _ => {}
+    }
+}
+
+/// Feed a `--dump-jsonl` transcript through `mgr` at its recorded times, so the spans
+/// it produces match the original session's timing. Entries that don't parse are
+/// skipped with a warning. Returns how many messages were replayed.
+pub fn replay(path: &Path, mgr: &mut SpanManager) -> Result<usize> {
+    let file = File::open(path)
+        .with_context(|| format!("failed to open transcript {}", path.display()))?;
+    let mut replayed = 0;
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("failed to read {}", path.display()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_entry(&line) {
+            Some((at, direction, msg)) => {
+                mgr.process_message_at(direction, &msg, at);
+                replayed += 1;
+            }
+            None => tracing::warn!(line = index + 1, "skipping malformed transcript entry"),
+        }
+    }
+    Ok(replayed)
+}
+
+/// `(ts, dir, msg)` of one transcript line; a string `msg` is replayed as-is.
+fn parse_entry(line: &str) -> Option<(SystemTime, Direction, String)> {
+    let entry: Value = serde_json::from_str(line).ok()?;
+    let at = parse_rfc3339(entry.get("ts")?.as_str()?)?;
+    let direction = match entry.get("dir")?.as_str()? {
+        "editor_to_agent" => Direction::EditorToAgent,
+        "agent_to_editor" => Direction::AgentToEditor,
+        _ => return None,
+    };
+    let msg = match entry.get("msg")? {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    Some((at, direction, msg))
+}
+
+/// Transcript and `--stderr-file` timestamps: UTC with millisecond precision.
+const TIMESTAMP: &[BorrowedFormatItem<'_>] =
+    format_description!("[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond digits:3]Z");
+
+/// `2024-05-01T12:34:56.789Z`.
+pub(crate) fn rfc3339(at: SystemTime) -> String {
+    OffsetDateTime::from(at)
+        .format(TIMESTAMP)
+        .expect("a UTC date formats")
+}
+
+/// Parse an RFC 3339 timestamp, as written by [`rfc3339`] or, with any offset, by
+/// other tools in hand-captured logs.
+fn parse_rfc3339(s: &str) -> Option<SystemTime> {
+    OffsetDateTime::parse(s, &Rfc3339)
+        .ok()
+        .map(SystemTime::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn timestamps_are_rfc3339_utc() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let leap_day = UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+        assert_eq!(rfc3339(leap_day), "2024-02-29T12:34:56.789Z");
+        let new_year = UNIX_EPOCH + Duration::from_secs(946_684_799);
+        assert_eq!(rfc3339(new_year), "1999-12-31T23:59:59.000Z");
+    }
+
+    #[test]
+    fn rfc3339_round_trips_and_accepts_offsets() {
+        for millis in [0, 1_709_210_096_789, 946_684_799_000] {
+            let at = UNIX_EPOCH + Duration::from_millis(millis);
+            assert_eq!(parse_rfc3339(&rfc3339(at)), Some(at));
+        }
+        assert_eq!(
+            parse_rfc3339("2024-02-29T14:34:56.5+02:00"),
+            Some(UNIX_EPOCH + Duration::from_millis(1_709_210_096_500))
+        );
+        assert_eq!(
+            parse_rfc3339("1970-01-01T00:00:01Z"),
+            Some(UNIX_EPOCH + Duration::from_secs(1))
+        );
+        for bad in [
+            "",
+            "2024-02-29",
+            "2024-02-29T12:34:56",
+            "2024-13-01T00:00:00Z",
+        ] {
+            assert_eq!(parse_rfc3339(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn short_session_dumps_both_directions() {
+        let path = std::env::temp_dir().join(format!(
+            "acp-traces-transcript-{}.jsonl",
+            std::process::id()
+        ));
+        let session = [
+            (
+                Direction::EditorToAgent,
+                r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+            ),
+            (
+                Direction::AgentToEditor,
+                r#"{"jsonrpc":"2.0","id":0,"result":{"protocolVersion":1}}"#,
+            ),
+            (Direction::AgentToEditor, "not json"),
+        ];
+        let mut transcript = Transcript::create(&path).unwrap();
+        for (direction, line) in session {
+            transcript.record(direction, line, SystemTime::now());
+        }
+        transcript.flush();
+
+        let entries: Vec<Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 3);
+        for (entry, (direction, line)) in entries.iter().zip(session) {
+            assert_eq!(entry["dir"], direction.as_str());
+            assert!(entry["ts"].as_str().unwrap().ends_with('Z'));
+            let expected =
+                serde_json::from_str(line).unwrap_or_else(|_| Value::String(line.to_string()));
+            assert_eq!(entry["msg"], expected);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn dump_is_redacted_and_drops_credentials() {
+        let path = std::env::temp_dir().join(format!(
+            "acp-traces-transcript-redact-{}.jsonl",
+            std::process::id()
+        ));
+        let mut transcript = Transcript::create(&path)
+            .unwrap()
+            .with_redact_patterns(vec![Regex::new(r"sk-[a-z0-9]+").unwrap()]);
+        for line in [
+            r#"{"jsonrpc":"2.0","id":1,"method":"authenticate","params":{"methodId":"api-key","apiKey":"hunter2"}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"session/prompt","params":{"prompt":[{"type":"text","text":"use sk-abc123"}]}}"#,
+            "token sk-def456",
+        ] {
+            transcript.record(Direction::EditorToAgent, line, SystemTime::now());
+        }
+        transcript.flush();
+
+        let dump = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!dump.contains("hunter2") && !dump.contains("sk-"), "{dump}");
+        let entries: Vec<Value> = dump
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            entries[0]["msg"]["params"],
+            serde_json::json!({"methodId": "api-key"})
+        );
+        assert_eq!(
+            entries[1]["msg"]["params"]["prompt"][0]["text"],
+            "use [REDACTED]"
+        );
+        assert_eq!(entries[2]["msg"], "token [REDACTED]");
+    }
+}
@@ -0,0 +1,107 @@
+#![cfg(unix)]
+
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+
+#[test]
+fn env_and_cwd_reach_the_agent() {
+    let dir = std::env::temp_dir().canonicalize().unwrap();
+    // Report what the agent sees, then wait for the editor to hang up.
+    let agent = r#"printf '%s|%s|%s\n' "$ACP_TRACES_KEPT" "${ACP_TRACES_DROPPED-unset}" "$(pwd)"; cat >/dev/null"#;
+    let mut proxy = Command::new(env!("CARGO_BIN_EXE_acp-traces"))
+        .env("ACP_TRACES_DROPPED", "1")
+        .args([
+            "--disable-traces",
+            "--disable-metrics",
+            "--env",
+            "ACP_TRACES_KEPT=a=b==",
+        ])
+        .args(["--env-remove", "ACP_TRACES_DROPPED", "--cwd"])
+        .arg(&dir)
+        .args(["--", "sh", "-c", agent])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let stdin = proxy.stdin.take().unwrap();
+    let mut line = String::new();
+    BufReader::new(proxy.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    drop(stdin);
+    proxy.wait().unwrap();
+
+    assert_eq!(line, format!("a=b==|unset|{}\n", dir.display()));
+}
+
+#[test]
+fn missing_cwd_fails_at_startup() {
+    let output = Command::new(env!("CARGO_BIN_EXE_acp-traces"))
+        .args([
+            "--disable-traces",
+            "--disable-metrics",
+            "--cwd",
+            "/nonexistent/acp-traces",
+            "--",
+        ])
+        .args(["sh", "-c", "exit 0"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("is not a directory"), "{stderr}");
+}
+
+#[test]
+fn resource_detector_none_stands_alone() {
+    let output = Command::new(env!("CARGO_BIN_EXE_acp-traces"))
+        .args([
+            "--disable-traces",
+            "--disable-metrics",
+            "--resource-detectors",
+            "host,none",
+        ])
+        .args(["--", "sh", "-c", "exit 0"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("can't be combined"), "{stderr}");
+}
+
+#[test]
+fn replay_rejects_prometheus_port() {
+    let output = Command::new(env!("CARGO_BIN_EXE_acp-traces"))
+        .args([
+            "--prometheus-port",
+            "9464",
+            "replay",
+            "/nonexistent/transcript.jsonl",
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("replay exits without serving"), "{stderr}");
+}
+
+#[test]
+fn request_timeouts_must_be_positive() {
+    for flag in ["--request-timeout", "--prompt-timeout"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_acp-traces"))
+            .args(["--disable-traces", "--disable-metrics", flag, "0"])
+            .args(["--", "sh", "-c", "exit 0"])
+            .stdin(Stdio::null())
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2), "{flag}");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains("expected a positive number of seconds"),
+            "{stderr}"
+        );
+    }
+}
@@ -0,0 +1,141 @@
+#![cfg(unix)]
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+
+#[test]
+fn agent_killed_by_a_signal_fails_the_proxy_like_a_shell() {
+    // Answer `initialize`, then crash.
+    let agent = r#"read line; printf '%s\n' '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":1}}'; kill -SEGV $$"#;
+    let mut proxy = Command::new(env!("CARGO_BIN_EXE_acp-traces"))
+        .args(["--otlp-protocol", "stdout", "--", "sh", "-c", agent])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Keep stdin open: the proxy must notice the agent's exit on its own.
+    let mut stdin = proxy.stdin.take().unwrap();
+    stdin
+        .write_all(
+            b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"initialize\",\"params\":{\"protocolVersion\":1}}\n",
+        )
+        .unwrap();
+    let mut stdout = BufReader::new(proxy.stdout.take().unwrap());
+    let mut response = String::new();
+    stdout.read_line(&mut response).unwrap();
+    let output = proxy.wait_with_output().unwrap();
+    drop(stdin);
+
+    assert_eq!(output.status.code(), Some(128 + 11));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("acp.agent.exit_signal = SIGSEGV"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("status=error(agent killed by SIGSEGV)"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn summary_file_records_the_exit_code() {
+    let path = std::env::temp_dir().join(format!("acp-traces-summary-{}.json", std::process::id()));
+    let output = Command::new(env!("CARGO_BIN_EXE_acp-traces"))
+        .args(["--disable-traces", "--disable-metrics", "--summary-file"])
+        .arg(&path)
+        .args(["--", "sh", "-c", "exit 3"])
+        .stdin(Stdio::piped())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("acp-traces session summary"), "{stderr}");
+
+    let summary: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(summary["prompts"], 0);
+    assert!(summary["turn_duration"].is_null());
+    // The agent gets to exit on its own after stdin EOF, so its code comes through.
+    assert_eq!(summary["agent_exit_code"], 3);
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn agent_stopped_after_stdin_eof_is_not_a_signal_failure() {
+    // Answer `initialize`, then ignore the closed stdin until stopped.
+    let agent = r#"read line; printf '%s\n' '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":1}}'; exec sleep 30"#;
+    let mut proxy = Command::new(env!("CARGO_BIN_EXE_acp-traces"))
+        .args([
+            "--otlp-protocol",
+            "stdout",
+            "--kill-timeout",
+            "1",
+            "--",
+            "sh",
+            "-c",
+            agent,
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = proxy.stdin.take().unwrap();
+    stdin
+        .write_all(
+            b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"initialize\",\"params\":{\"protocolVersion\":1}}\n",
+        )
+        .unwrap();
+    let mut stdout = BufReader::new(proxy.stdout.take().unwrap());
+    let mut response = String::new();
+    stdout.read_line(&mut response).unwrap();
+    drop(stdin);
+    let output = proxy.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("acp.agent.exit_signal"), "{stderr}");
+    assert!(!stderr.contains("agent killed by"), "{stderr}");
+}
+
+#[test]
+fn agent_output_written_just_before_exit_is_relayed() {
+    // Answer `initialize` after a burst of updates, then exit at once.
+    let agent = r#"read line
+i=0
+while [ $i -lt 200 ]; do
+  printf '%s\n' '{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"x"}}}}'
+  i=$((i+1))
+done
+printf '%s\n' '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":1}}'"#;
+    let mut proxy = Command::new(env!("CARGO_BIN_EXE_acp-traces"))
+        .args(["--otlp-protocol", "stdout", "--", "sh", "-c", agent])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // The editor sends its request and closes stdin straight away.
+    proxy
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(
+            b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"initialize\",\"params\":{\"protocolVersion\":1}}\n",
+        )
+        .unwrap();
+    let output = proxy.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 201);
+    assert!(lines[200].contains(r#""id":1"#), "{}", lines[200]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        !stderr.contains("process exited before response"),
+        "{stderr}"
+    );
+}
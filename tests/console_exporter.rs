@@ -0,0 +1,44 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Command, Stdio};
+
+#[test]
+fn stdout_protocol_prints_spans_to_stderr() {
+    // A one-shot agent: answer `initialize`, then wait for the editor to hang up.
+    let agent = r#"read line; printf '%s\n' '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":1}}'; cat >/dev/null"#;
+    let mut proxy = Command::new(env!("CARGO_BIN_EXE_acp-traces"))
+        .args(["--otlp-protocol", "stdout", "--", "sh", "-c", agent])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = proxy.stdin.take().unwrap();
+    stdin
+        .write_all(
+            b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"initialize\",\"params\":{\"protocolVersion\":1}}\n",
+        )
+        .unwrap();
+    let mut stdout = BufReader::new(proxy.stdout.take().unwrap());
+    let mut response = String::new();
+    stdout.read_line(&mut response).unwrap();
+    drop(stdin);
+    let mut rest = String::new();
+    stdout.read_to_string(&mut rest).unwrap();
+    let output = proxy.wait_with_output().unwrap();
+
+    // stdout stays pure ACP.
+    assert!(response.contains("\"protocolVersion\":1"));
+    assert!(rest.is_empty(), "{rest}");
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("span initialize"), "{stderr}");
+    assert!(stderr.contains("rpc.method = initialize"), "{stderr}");
+    assert!(stderr.contains("span acp_session"), "{stderr}");
+    // Both lines in full, newlines included, summed over the two directions.
+    assert!(
+        stderr.contains("metric acp.io.messages total=2"),
+        "{stderr}"
+    );
+    let bytes = format!("metric acp.io.bytes total={}\n", 78 + response.len());
+    assert!(stderr.contains(&bytes), "{stderr}");
+}
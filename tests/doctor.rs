@@ -0,0 +1,54 @@
+#![cfg(unix)]
+
+use std::process::{Command, Output, Stdio};
+
+fn doctor(extra: &[&str], agent: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_acp-traces"))
+        .args(["--disable-traces", "--disable-metrics", "doctor"])
+        .args(extra)
+        .args(["--", "sh", "-c", agent])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn handshake_and_session_are_reported() {
+    let agent = r#"
+        read -r line
+        echo 'booting'
+        echo '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":1,"agentCapabilities":{"loadSession":true},"agentInfo":{"name":"fake","version":"0.1"},"authMethods":[{"id":"oauth","name":"Log in"}]}}'
+        read -r line
+        echo '{"jsonrpc":"2.0","id":2,"result":{"sessionId":"s-1"}}'
+        cat >/dev/null
+    "#;
+    let output = doctor(&["--session"], agent);
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success(), "{report}");
+    for expected in [
+        "  initialize: ok in ",
+        "  agent: fake 0.1\n",
+        "  protocol version: 1\n",
+        "  capabilities: loadSession\n",
+        "  auth methods: oauth\n",
+        "  session/new: ok in ",
+        " (session s-1, cancelled)\n",
+        "weren't JSON-RPC, first: \"booting\"",
+    ] {
+        assert!(
+            report.contains(expected),
+            "{expected:?} missing from\n{report}"
+        );
+    }
+}
+
+#[test]
+fn silent_agent_times_out() {
+    let output = doctor(&["--timeout", "1"], "cat >/dev/null");
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(output.status.code(), Some(1), "{report}");
+    assert!(
+        report.contains("initialize: FAILED: no response to initialize within 1s"),
+        "{report}"
+    );
+}
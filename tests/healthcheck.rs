@@ -0,0 +1,30 @@
+use std::process::Command;
+
+#[test]
+fn healthcheck_fails_with_the_reason_when_nothing_listens() {
+    // A port that was free a moment ago: connections to it are refused.
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let output = Command::new(env!("CARGO_BIN_EXE_acp-traces"))
+        .env_remove("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .args(["--otlp-endpoint", &format!("http://127.0.0.1:{port}")])
+        .args([
+            "--otlp-protocol",
+            "http",
+            "--disable-metrics",
+            "--otlp-timeout",
+            "5",
+        ])
+        .arg("healthcheck")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(output.status.code(), Some(1), "{stdout}");
+    assert!(
+        stdout.contains("traces: FAILED (connection refused: nothing is listening"),
+        "{stdout}"
+    );
+}
@@ -0,0 +1,73 @@
+use std::process::{Command, Stdio};
+
+#[test]
+fn json_logs_keep_fields_queryable() {
+    let output = Command::new(env!("CARGO_BIN_EXE_acp-traces"))
+        .env_remove("RUST_LOG")
+        .args([
+            "--disable-traces",
+            "--disable-metrics",
+            "--log-format",
+            "json",
+            "--no-summary",
+            "-v",
+        ])
+        .args(["--", "sh", "-c", "exit 0"])
+        .stdin(Stdio::piped())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let spawn = stderr
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).expect(line))
+        .find(|log| log["message"] == "spawning agent")
+        .unwrap_or_else(|| panic!("{stderr}"));
+    assert_eq!(spawn["level"], "INFO");
+    assert_eq!(spawn["cmd"], "sh");
+    assert!(spawn["timestamp"].is_string());
+}
+
+#[test]
+fn verbosity_still_applies_to_json_logs() {
+    let output = Command::new(env!("CARGO_BIN_EXE_acp-traces"))
+        .env_remove("RUST_LOG")
+        .args([
+            "--disable-traces",
+            "--disable-metrics",
+            "--log-format",
+            "json",
+            "--no-summary",
+        ])
+        .args(["--", "sh", "-c", "exit 0"])
+        .stdin(Stdio::piped())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("spawning agent"), "{stderr}");
+}
+
+#[test]
+fn json_logs_carry_the_summary_as_an_event() {
+    let output = Command::new(env!("CARGO_BIN_EXE_acp-traces"))
+        .env_remove("RUST_LOG")
+        .args([
+            "--disable-traces",
+            "--disable-metrics",
+            "--log-format",
+            "json",
+            "-v",
+        ])
+        .args(["--", "sh", "-c", "exit 0"])
+        .stdin(Stdio::piped())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let report = stderr
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).expect(line))
+        .find(|log| log["message"] == "acp-traces session summary")
+        .unwrap_or_else(|| panic!("{stderr}"));
+    let summary: serde_json::Value =
+        serde_json::from_str(report["summary"].as_str().unwrap()).unwrap();
+    assert_eq!(summary["agent_exit_code"], 0);
+}
@@ -0,0 +1,165 @@
+use acp_traces::acp::Direction;
+use acp_traces::spans::SpanManager;
+use opentelemetry::global::BoxedTracer;
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_sdk::metrics::data::{Histogram, Sum};
+use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+
+/// Run one prompt turn through a `SpanManager` set up by `configure` and return the
+/// exported point count of each named f64 histogram (0 when it wasn't recorded).
+fn histogram_counts(
+    tracer: BoxedTracer,
+    configure: impl FnOnce(SpanManager) -> SpanManager,
+    names: &[&str],
+) -> Vec<u64> {
+    let exporter = InMemoryMetricExporter::default();
+    let provider = SdkMeterProvider::builder()
+        .with_reader(PeriodicReader::builder(exporter.clone()).build())
+        .build();
+    let mut mgr = configure(SpanManager::new(tracer, provider.meter("test"), false));
+
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"done"}}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    provider.force_flush().unwrap();
+
+    let metrics = exporter.get_finished_metrics().unwrap();
+    names
+        .iter()
+        .map(|name| {
+            let Some(metric) = metrics
+                .iter()
+                .flat_map(|rm| &rm.scope_metrics)
+                .flat_map(|sm| &sm.metrics)
+                .find(|m| m.name == *name)
+            else {
+                return 0;
+            };
+            let histogram = metric
+                .data
+                .as_any()
+                .downcast_ref::<Histogram<f64>>()
+                .expect("f64 histogram");
+            histogram.data_points.iter().map(|p| p.count).sum()
+        })
+        .collect()
+}
+
+#[test]
+fn prompt_duration_reaches_the_metric_reader() {
+    let counts = histogram_counts(
+        opentelemetry::global::tracer("test"),
+        |mgr| mgr,
+        &["gen_ai.client.operation.duration"],
+    );
+    assert_eq!(counts, vec![1]);
+}
+
+#[test]
+fn sampled_out_turns_still_record_metrics() {
+    let tracer_provider = SdkTracerProvider::builder()
+        .with_sampler(Sampler::AlwaysOff)
+        .build();
+    let tracer = BoxedTracer::new(Box::new(tracer_provider.tracer("test")));
+    let counts = histogram_counts(
+        tracer,
+        |mgr| mgr,
+        &[
+            "gen_ai.client.operation.duration",
+            "gen_ai.server.time_to_first_token",
+        ],
+    );
+    assert_eq!(counts, vec![1, 1]);
+}
+
+#[test]
+fn filtered_out_methods_record_no_histograms() {
+    let names = [
+        "gen_ai.client.operation.duration",
+        "gen_ai.server.time_to_first_token",
+    ];
+    let only = |methods: &[&str]| {
+        let patterns = methods.iter().map(|m| m.parse().unwrap()).collect();
+        histogram_counts(
+            opentelemetry::global::tracer("test"),
+            |mgr| mgr.with_only_methods(patterns),
+            &names,
+        )
+    };
+    assert_eq!(only(&["session/*"]), vec![1, 1]);
+    // The turn is measured, but without its updates there is no first token.
+    assert_eq!(only(&["session/prompt"]), vec![1, 0]);
+    assert_eq!(only(&["session/update"]), vec![0, 0]);
+}
+
+#[test]
+fn every_relayed_line_is_timed() {
+    let counts = histogram_counts(
+        opentelemetry::global::tracer("test"),
+        |mgr| mgr,
+        &["acp.proxy.processing_duration"],
+    );
+    assert_eq!(counts, vec![3]);
+}
+
+#[test]
+fn lines_and_parse_failures_are_counted_by_direction() {
+    let exporter = InMemoryMetricExporter::default();
+    let provider = SdkMeterProvider::builder()
+        .with_reader(PeriodicReader::builder(exporter.clone()).build())
+        .build();
+    let mut mgr = SpanManager::new(
+        opentelemetry::global::tracer("test"),
+        provider.meter("test"),
+        false,
+    );
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+    );
+    mgr.process_message(Direction::AgentToEditor, "Starting agent...");
+    mgr.process_message(Direction::AgentToEditor, "{not json");
+    provider.force_flush().unwrap();
+
+    let metrics = exporter.get_finished_metrics().unwrap();
+    let sum = |name: &str, direction: &str| -> u64 {
+        let metric = metrics
+            .iter()
+            .flat_map(|rm| &rm.scope_metrics)
+            .flat_map(|sm| &sm.metrics)
+            .find(|m| m.name == name)
+            .unwrap_or_else(|| panic!("{name} not exported"));
+        let sum = metric.data.as_any().downcast_ref::<Sum<u64>>().unwrap();
+        sum.data_points
+            .iter()
+            .filter(|p| {
+                p.attributes.contains(&opentelemetry::KeyValue::new(
+                    "acp.direction",
+                    direction.to_string(),
+                ))
+            })
+            .map(|p| p.value)
+            .sum()
+    };
+    assert_eq!(sum("acp.proxy.lines", "editor_to_agent"), 1);
+    assert_eq!(sum("acp.proxy.lines", "agent_to_editor"), 2);
+    assert_eq!(sum("acp.proxy.parse_failures", "agent_to_editor"), 2);
+    assert_eq!(sum("acp.proxy.parse_failures", "editor_to_agent"), 0);
+}
@@ -1,6 +1,12 @@
-use opentelemetry::trace::{Span, SpanKind, TraceContextExt, Tracer, TracerProvider};
+use acp_traces::acp::Direction;
+use acp_traces::spans::{ContentPolicy, SpanManager};
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry::trace::{Span, SpanKind, Status, TraceContextExt, Tracer, TracerProvider};
+use opentelemetry_sdk::metrics::data::{Histogram, Sum};
+use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
 use opentelemetry_sdk::trace::{SdkTracerProvider, SpanData};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[derive(Clone, Debug)]
 struct TestExporter {
@@ -38,6 +44,24 @@ fn setup() -> (SdkTracerProvider, TestExporter) {
     (provider, exporter)
 }
 
+fn manager(record_content: bool) -> (SpanManager, SdkTracerProvider, TestExporter) {
+    let (provider, exporter) = setup();
+    let tracer = opentelemetry::global::BoxedTracer::new(Box::new(provider.tracer("test")));
+    let meter = opentelemetry::global::meter("test");
+    (
+        SpanManager::new(tracer, meter, record_content),
+        provider,
+        exporter,
+    )
+}
+
+fn attr(span: &SpanData, key: &str) -> Option<opentelemetry::Value> {
+    span.attributes
+        .iter()
+        .find(|kv| kv.key.as_str() == key)
+        .map(|kv| kv.value.clone())
+}
+
 #[test]
 fn child_span_shares_trace_id_with_parent_via_remote_context() {
     let (provider, exporter) = setup();
@@ -109,3 +133,3329 @@ fn finish_reason_mappings() {
     );
     assert_eq!(map_stop_reason_to_finish_reason("cancelled"), "cancelled");
 }
+
+#[test]
+fn prompt_spans_carry_current_session_mode() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/set_mode","params":{"sessionId":"s1","modeId":"ask"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":2,"method":"session/prompt","params":{"sessionId":"s1","prompt":[{"type":"text","text":"explain"}]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":2,"result":{"stopReason":"end_turn"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"current_mode_update","currentModeId":"code"}}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":3,"method":"session/prompt","params":{"sessionId":"s1","prompt":[{"type":"text","text":"fix it"}]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":3,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let set_mode = spans.iter().find(|s| s.name == "session/set_mode").unwrap();
+    assert_eq!(attr(set_mode, "acp.session.mode"), Some("ask".into()));
+
+    let modes: Vec<_> = spans
+        .iter()
+        .filter(|s| s.name.starts_with("invoke_agent"))
+        .map(|s| attr(s, "acp.session.mode"))
+        .collect();
+    assert_eq!(modes, vec![Some("ask".into()), Some("code".into())]);
+}
+
+#[test]
+fn rejected_set_mode_keeps_the_previous_mode() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/set_mode","params":{"sessionId":"s1","modeId":"ask"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":2,"method":"session/set_mode","params":{"sessionId":"s1","modeId":"yolo"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":2,"error":{"code":-32602,"message":"unknown mode"}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":3,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":3,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(attr(prompt, "acp.session.mode"), Some("ask".into()));
+}
+
+#[test]
+fn prompt_spans_follow_model_changes() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/set_model","params":{"sessionId":"s1","modelId":"model-a"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":2,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":2,"result":{"stopReason":"end_turn"}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":3,"method":"session/set_model","params":{"sessionId":"s1","modelId":"model-b"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":3,"result":{}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":4,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":4,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let models: Vec<_> = exporter
+        .spans()
+        .iter()
+        .filter(|s| s.name.starts_with("invoke_agent"))
+        .map(|s| attr(s, "gen_ai.request.model"))
+        .collect();
+    assert_eq!(models, vec![Some("model-a".into()), Some("model-b".into())]);
+}
+
+#[test]
+fn rejected_set_model_keeps_the_previous_model() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/set_model","params":{"sessionId":"s1","modelId":"model-a"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":2,"method":"session/set_model","params":{"sessionId":"s1","modelId":"model-x"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":2,"error":{"code":-32602,"message":"unknown model"}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":3,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":3,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(attr(prompt, "gen_ai.request.model"), Some("model-a".into()));
+}
+
+#[test]
+fn wait_for_exit_records_exit_status_under_prompt() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[{"type":"text","text":"run the tests"}]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":"t1","method":"terminal/wait_for_exit","params":{"sessionId":"s1","terminalId":"term-1"}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":"t1","result":{"exitCode":1,"signal":null}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    let wait = spans
+        .iter()
+        .find(|s| s.name == "execute_tool terminal/wait_for_exit")
+        .unwrap();
+    assert_eq!(wait.parent_span_id, prompt.span_context.span_id());
+    assert_eq!(attr(wait, "acp.terminal.id"), Some("term-1".into()));
+    assert_eq!(attr(wait, "acp.terminal.exit_code"), Some(1i64.into()));
+    assert_eq!(attr(wait, "acp.terminal.signal"), None);
+    assert!(matches!(wait.status, Status::Error { .. }));
+}
+
+#[test]
+fn terminal_output_is_only_marked_truncated_when_it_was() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":"t1","method":"terminal/output","params":{"sessionId":"s1","terminalId":"term-1"}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":"t1","result":{"output":"ok","truncated":false}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":"t2","method":"terminal/output","params":{"sessionId":"s1","terminalId":"term-1"}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":"t2","result":{"output":"...","truncated":true}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let truncated: Vec<_> = exporter
+        .spans()
+        .iter()
+        .filter(|s| s.name == "execute_tool terminal/output")
+        .map(|s| attr(s, "acp.terminal.truncated"))
+        .collect();
+    assert_eq!(truncated, vec![None, Some(true.into())]);
+}
+
+#[test]
+fn plan_updates_become_prompt_span_events() {
+    let (mut mgr, provider, exporter) = manager(true);
+    let transcript = [
+        // No prompt open yet — counted, not attached.
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"plan","entries":[]}}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"plan","entries":[{"content":"Fix bug","priority":"high","status":"in_progress"},{"content":"Run tests","priority":"medium","status":"pending"}]}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"plan","entries":[{"content":"Fix bug","priority":"high","status":"completed"},{"content":"Run tests","priority":"medium","status":"completed"}]}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    let events: Vec<_> = prompt
+        .events
+        .events
+        .iter()
+        .filter(|e| e.name == "acp.plan_update")
+        .collect();
+    assert_eq!(events.len(), 2);
+    let completed = |e: &opentelemetry::trace::Event| {
+        e.attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == "acp.plan.completed")
+            .map(|kv| kv.value.clone())
+    };
+    assert_eq!(completed(events[0]), Some(0i64.into()));
+    assert_eq!(completed(events[1]), Some(2i64.into()));
+    assert!(events[0]
+        .attributes
+        .iter()
+        .any(|kv| kv.key.as_str() == "acp.plan.titles"));
+}
+
+#[test]
+fn thought_chunks_accumulate_separately_and_count_toward_ttft() {
+    let (mut mgr, provider, exporter) = manager(true);
+    let chunk = |kind: &str, text: &str| {
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"session/update","params":{{"sessionId":"s1","update":{{"sessionUpdate":"{kind}","content":{{"type":"text","text":"{text}"}}}}}}}}"#
+        )
+    };
+    // Replay recorded times so TTFT is exact: thought at +100ms, message at +300ms.
+    let t0 = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+    let at = |ms: u64| t0 + std::time::Duration::from_millis(ms);
+    mgr.process_message_at(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        at(0),
+    );
+    mgr.process_message_at(
+        Direction::AgentToEditor,
+        &chunk("agent_thought_chunk", "Let me "),
+        at(100),
+    );
+    mgr.process_message_at(
+        Direction::AgentToEditor,
+        &chunk("agent_message_chunk", "Hel"),
+        at(300),
+    );
+    mgr.process_message_at(
+        Direction::AgentToEditor,
+        &chunk("agent_thought_chunk", "think."),
+        at(400),
+    );
+    mgr.process_message_at(
+        Direction::AgentToEditor,
+        &chunk("agent_message_chunk", "lo"),
+        at(500),
+    );
+    mgr.process_message_at(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+        at(600),
+    );
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(attr(prompt, "acp.thought.chunks"), Some(2i64.into()));
+    assert_eq!(attr(prompt, "acp.thought.chars"), Some(13i64.into()));
+
+    let ttft = match attr(prompt, "acp.time_to_first_token_ms") {
+        Some(opentelemetry::Value::I64(ms)) => ms,
+        other => panic!("unexpected ttft {other:?}"),
+    };
+    // The first thought chunk, not the first message chunk, sets TTFT.
+    assert_eq!(ttft, 100, "TTFT should come from the first thought chunk");
+
+    let output = attr(prompt, "gen_ai.output.messages").unwrap().to_string();
+    let output: serde_json::Value = serde_json::from_str(&output).unwrap();
+    let parts = &output[0]["parts"];
+    assert_eq!(parts[0]["type"], "reasoning");
+    assert_eq!(parts[0]["content"], "Let me think.");
+    assert_eq!(parts[1]["type"], "text");
+    assert_eq!(parts[1]["content"], "Hello");
+    assert_eq!(output[0]["finish_reason"], "stop");
+}
+
+#[test]
+fn user_message_chunks_fill_input_for_context_only_prompts() {
+    let (mut mgr, provider, exporter) = manager(true);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[{"type":"resource","resource":{"uri":"file:///main.rs","text":"fn main() {}"}}]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"user_message_chunk","content":{"type":"text","text":"fix the "}}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"user_message_chunk","content":{"type":"text","text":"bug"}}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(attr(prompt, "acp.user_message.chunks"), Some(2i64.into()));
+    assert_eq!(attr(prompt, "acp.user_message.chars"), Some(11i64.into()));
+    let input = attr(prompt, "gen_ai.input.messages").unwrap().to_string();
+    let input: serde_json::Value = serde_json::from_str(&input).unwrap();
+    assert_eq!(input[0]["role"], "user");
+    assert_eq!(input[0]["parts"][0]["content"], "fix the bug");
+}
+
+#[test]
+fn available_commands_update_marks_root_span_once_per_change() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let update = |names: &str| {
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"session/update","params":{{"sessionId":"s1","update":{{"sessionUpdate":"available_commands_update","availableCommands":[{names}]}}}}}}"#
+        )
+    };
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+    );
+    let web = r#"{"name":"web","description":"Search"}"#;
+    let test = r#"{"name":"test","description":"Run tests"}"#;
+    mgr.process_message(Direction::AgentToEditor, &update(web));
+    mgr.process_message(Direction::AgentToEditor, &update(web));
+    mgr.process_message(Direction::AgentToEditor, &update(&format!("{web},{test}")));
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let root = spans.iter().find(|s| s.name == "acp_session").unwrap();
+    let changes = root
+        .events
+        .events
+        .iter()
+        .filter(|e| e.name == "acp.available_commands_update")
+        .count();
+    assert_eq!(changes, 2);
+    let set = root
+        .attributes
+        .iter()
+        .filter(|kv| kv.key.as_str() == "acp.session.available_commands")
+        .count();
+    assert_eq!(set, 1, "set once, when the root span ends");
+    assert_eq!(
+        attr(root, "acp.session.available_commands"),
+        Some("web,test".into())
+    );
+    assert_eq!(
+        attr(root, "acp.session.available_commands.count"),
+        Some(2i64.into())
+    );
+}
+
+#[test]
+fn mode_change_mid_prompt_lands_on_prompt_span() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/set_mode","params":{"sessionId":"s1","modeId":"ask"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":2,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"current_mode_update","currentModeId":"code"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":2,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    let event = prompt
+        .events
+        .events
+        .iter()
+        .find(|e| e.name == "acp.mode_changed")
+        .expect("mode change event on prompt span");
+    let get = |key: &str| {
+        event
+            .attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == key)
+            .map(|kv| kv.value.clone())
+    };
+    assert_eq!(get("acp.mode.from"), Some("ask".into()));
+    assert_eq!(get("acp.mode.to"), Some("code".into()));
+    assert_eq!(attr(prompt, "acp.session.mode"), Some("ask".into()));
+}
+
+#[test]
+fn resent_diffs_replace_rather_than_double_count() {
+    let (mut mgr, provider, exporter) = manager(true);
+    let diff = r#"{"type":"diff","path":"/src/a.rs","oldText":"a\nb","newText":"a\nc\nd"}"#;
+    let tool_call = format!(
+        r#"{{"jsonrpc":"2.0","method":"session/update","params":{{"sessionId":"s1","update":{{"sessionUpdate":"tool_call","toolCallId":"tc1","title":"Edit","kind":"edit","status":"in_progress","content":[{diff}]}}}}}}"#
+    );
+    let tool_update = format!(
+        r#"{{"jsonrpc":"2.0","method":"session/update","params":{{"sessionId":"s1","update":{{"sessionUpdate":"tool_call_update","toolCallId":"tc1","status":"completed","content":[{diff},{{"type":"diff","path":"/src/new.rs","oldText":null,"newText":"x"}}]}}}}}}"#
+    );
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+    );
+    mgr.process_message(Direction::AgentToEditor, &tool_call);
+    mgr.process_message(Direction::AgentToEditor, &tool_update);
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+    );
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let tool = spans
+        .iter()
+        .find(|s| s.name.starts_with("execute_tool"))
+        .unwrap();
+    assert_eq!(attr(tool, "acp.tool.diff.files"), Some(2i64.into()));
+    assert_eq!(attr(tool, "acp.tool.diff.lines_added"), Some(3i64.into()));
+    assert_eq!(attr(tool, "acp.tool.diff.lines_removed"), Some(1i64.into()));
+    let text = attr(tool, "acp.tool.diff").unwrap().to_string();
+    assert!(text.contains("+++ /src/new.rs"));
+}
+
+#[test]
+fn tool_status_transitions_become_events() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let update = |status: &str| {
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"session/update","params":{{"sessionId":"s1","update":{{"sessionUpdate":"tool_call_update","toolCallId":"tc1","status":"{status}"}}}}}}"#
+        )
+    };
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+    );
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call","toolCallId":"tc1","title":"Run","kind":"execute","status":"pending"}}}"#,
+    );
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    mgr.process_message(Direction::AgentToEditor, &update("in_progress"));
+    mgr.process_message(Direction::AgentToEditor, &update("in_progress"));
+    mgr.process_message(Direction::AgentToEditor, &update("completed"));
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let tool = spans
+        .iter()
+        .find(|s| s.name.starts_with("execute_tool"))
+        .unwrap();
+    let statuses: Vec<String> = tool
+        .events
+        .events
+        .iter()
+        .filter(|e| e.name == "acp.tool.status")
+        .map(|e| e.attributes[0].value.to_string())
+        .collect();
+    assert_eq!(statuses, ["in_progress", "completed"]);
+    let queued = match attr(tool, "acp.tool.queue_duration_ms") {
+        Some(opentelemetry::Value::I64(ms)) => ms,
+        other => panic!("unexpected queue duration {other:?}"),
+    };
+    assert!(queued >= 20);
+}
+
+#[test]
+fn fs_requests_nest_under_matching_tool_call() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let tool_call = |id: &str, path: &str| {
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"session/update","params":{{"sessionId":"s1","update":{{"sessionUpdate":"tool_call","toolCallId":"{id}","title":"Edit {path}","kind":"edit","status":"in_progress","locations":[{{"path":"{path}"}}]}}}}}}"#
+        )
+    };
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+    );
+    mgr.process_message(Direction::AgentToEditor, &tool_call("tc1", "/src/a.rs"));
+    mgr.process_message(Direction::AgentToEditor, &tool_call("tc2", "/src/b.rs"));
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","id":10,"method":"fs/write_text_file","params":{"sessionId":"s1","path":"/src/b.rs","content":"x"}}"#,
+    );
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":10,"result":null}"#,
+    );
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+    );
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let tool = spans
+        .iter()
+        .find(|s| s.name == "execute_tool Edit /src/b.rs")
+        .unwrap();
+    let fs = spans
+        .iter()
+        .find(|s| s.name == "execute_tool fs/write_text_file")
+        .unwrap();
+    assert_eq!(fs.parent_span_id, tool.span_context.span_id());
+    assert_eq!(fs.span_context.trace_id(), tool.span_context.trace_id());
+}
+
+#[test]
+fn tool_call_update_before_tool_call_starts_one_span() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call_update","toolCallId":"tc1","status":"in_progress"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call","toolCallId":"tc1","title":"Read file","kind":"read","status":"in_progress"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call_update","toolCallId":"tc1","status":"completed"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call_update","toolCallId":"tc2","title":"Fetch","status":"failed"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let tools: Vec<_> = spans
+        .iter()
+        .filter(|s| s.name.starts_with("execute_tool"))
+        .collect();
+    assert_eq!(tools.len(), 2);
+    let read = tools
+        .iter()
+        .find(|s| s.name == "execute_tool Read file")
+        .unwrap();
+    assert_eq!(attr(read, "acp.tool.kind"), Some("read".into()));
+    assert_eq!(attr(read, "acp.tool.lazy"), Some(true.into()));
+    assert_eq!(read.status, Status::Unset);
+    let fetch = tools
+        .iter()
+        .find(|s| s.name == "execute_tool Fetch")
+        .unwrap();
+    assert!(matches!(fetch.status, Status::Error { .. }));
+}
+
+#[test]
+fn bare_tool_call_after_an_update_keeps_its_kind_and_status() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call_update","toolCallId":"tc1","kind":"execute","status":"in_progress"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call","toolCallId":"tc1","title":"cargo build"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call_update","toolCallId":"tc1","status":"completed"}}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let tool = spans
+        .iter()
+        .find(|s| s.name == "execute_tool cargo build")
+        .unwrap();
+    assert_eq!(attr(tool, "acp.tool.kind"), Some("execute".into()));
+    let statuses: Vec<_> = tool
+        .events
+        .events
+        .iter()
+        .filter(|e| e.name == "acp.tool.status")
+        .flat_map(|e| e.attributes.iter().map(|kv| kv.value.to_string()))
+        .collect();
+    assert_eq!(statuses, ["completed"]);
+}
+
+#[test]
+fn extension_methods_are_traced_under_the_prompt() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":0,"result":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":7,"method":"_vendor/checkpoint","params":{"sessionId":"s1"}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":7,"result":{}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"_vendor/progress","params":{"sessionId":"s1"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    let ext = spans
+        .iter()
+        .find(|s| s.name == "extension _vendor/checkpoint")
+        .unwrap();
+    assert_eq!(ext.parent_span_id, prompt.span_context.span_id());
+    assert_eq!(attr(ext, "acp.extension"), Some(true.into()));
+    assert!(prompt
+        .events
+        .events
+        .iter()
+        .any(|e| e.name == "acp.extension_notification"));
+}
+
+#[test]
+fn authenticate_is_checked_against_offered_methods() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":0,"result":{"protocolVersion":1,"authMethods":[{"id":"oauth","name":"Log in"}]}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"authenticate","params":{"methodId":"api-key"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32602,"message":"unknown method"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let init = spans.iter().find(|s| s.name == "initialize").unwrap();
+    assert_eq!(attr(init, "acp.agent.auth_methods"), Some("oauth".into()));
+    assert_eq!(
+        attr(init, "acp.agent.auth_methods.count"),
+        Some(1i64.into())
+    );
+    let auth = spans.iter().find(|s| s.name == "authenticate").unwrap();
+    assert_eq!(attr(auth, "acp.auth.method_offered"), Some(false.into()));
+}
+
+#[test]
+fn prompt_resources_are_listed_and_capped() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let links: Vec<String> = (0..12)
+        .map(|i| format!(r#"{{"type":"resource_link","uri":"file:///f{i}.rs","name":"f{i}.rs"}}"#))
+        .collect();
+    let prompt = format!(
+        r#"{{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{{"sessionId":"s1","prompt":[{{"type":"text","text":"hi"}},{}]}}}}"#,
+        links.join(",")
+    );
+    mgr.process_message(Direction::EditorToAgent, &prompt);
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+    );
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(
+        attr(prompt, "acp.prompt.resources.count"),
+        Some(12i64.into())
+    );
+    let listed = attr(prompt, "acp.prompt.resources").unwrap().to_string();
+    assert!(listed.starts_with("file:///f0.rs,file:///f1.rs,"));
+    assert!(listed.ends_with("file:///f9.rs,...+2 more"));
+    assert_eq!(attr(prompt, "acp.prompt.blocks"), Some(13i64.into()));
+    assert_eq!(attr(prompt, "gen_ai.input.messages"), None);
+}
+
+#[test]
+fn image_prompts_record_blob_parts_without_payload() {
+    let (mut mgr, provider, exporter) = manager(true);
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[{"type":"text","text":"what is this?"},{"type":"image","mimeType":"image/png","data":"iVBORw0KGgo="}]}}"#,
+    );
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+    );
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(attr(prompt, "acp.prompt.images"), Some(1i64.into()));
+    assert_eq!(
+        attr(prompt, "acp.prompt.image.mime_types"),
+        Some("image/png".into())
+    );
+    let input: serde_json::Value =
+        serde_json::from_str(&attr(prompt, "gen_ai.input.messages").unwrap().to_string()).unwrap();
+    let parts = input[0]["parts"].as_array().unwrap();
+    assert_eq!(parts[0]["content"], "what is this?");
+    assert_eq!(parts[1]["mime_type"], "image/png");
+    assert_eq!(parts[1]["byte_size"], 8);
+    assert!(!input.to_string().contains("iVBORw0KGgo"));
+}
+
+#[test]
+fn prompt_usage_sets_token_attributes_only_when_reported() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn","_meta":{"usage":{"inputTokens":100,"outputTokens":20}}}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":2,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":2,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompts: Vec<_> = spans
+        .iter()
+        .filter(|s| s.name.starts_with("invoke_agent"))
+        .collect();
+    assert_eq!(prompts.len(), 2);
+    assert_eq!(
+        attr(prompts[0], "gen_ai.usage.input_tokens"),
+        Some(100i64.into())
+    );
+    assert_eq!(
+        attr(prompts[0], "gen_ai.usage.output_tokens"),
+        Some(20i64.into())
+    );
+    assert_eq!(attr(prompts[1], "gen_ai.usage.input_tokens"), None);
+}
+
+#[test]
+fn session_new_result_seeds_first_prompt() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/new","params":{"cwd":"/repo","mcpServers":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"sessionId":"s1","modes":{"currentModeId":"ask","availableModes":[{"id":"ask","name":"Ask"},{"id":"code","name":"Code"}]},"models":{"currentModelId":"sonnet","availableModels":[{"modelId":"sonnet","name":"Sonnet"},{"modelId":"opus","name":"Opus"}]}}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":2,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":2,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let new = spans.iter().find(|s| s.name == "session/new").unwrap();
+    assert_eq!(attr(new, "acp.session.initial_mode"), Some("ask".into()));
+    assert_eq!(
+        attr(new, "acp.session.available_models.count"),
+        Some(2i64.into())
+    );
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(attr(prompt, "acp.session.mode"), Some("ask".into()));
+    assert_eq!(attr(prompt, "gen_ai.request.model"), Some("sonnet".into()));
+}
+
+#[test]
+fn prompts_carry_turn_index() {
+    let (mut mgr, provider, exporter) = manager(false);
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+    );
+    for id in 1..=3 {
+        mgr.process_message(
+            Direction::EditorToAgent,
+            &format!(
+                r#"{{"jsonrpc":"2.0","id":{id},"method":"session/prompt","params":{{"sessionId":"s1","prompt":[]}}}}"#
+            ),
+        );
+        mgr.process_message(
+            Direction::AgentToEditor,
+            &format!(r#"{{"jsonrpc":"2.0","id":{id},"result":{{"stopReason":"end_turn"}}}}"#),
+        );
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let indices: Vec<_> = spans
+        .iter()
+        .filter(|s| s.name.starts_with("invoke_agent"))
+        .map(|s| attr(s, "acp.turn.index"))
+        .collect();
+    assert_eq!(
+        indices,
+        [Some(0i64.into()), Some(1i64.into()), Some(2i64.into())]
+    );
+    let root = spans.iter().find(|s| s.name == "acp_session").unwrap();
+    assert_eq!(attr(root, "acp.session.turns"), Some(3i64.into()));
+}
+
+#[test]
+fn consecutive_prompts_link_to_previous_turn() {
+    let (mut mgr, provider, exporter) = manager(false);
+    for id in 1..=2 {
+        mgr.process_message(
+            Direction::EditorToAgent,
+            &format!(
+                r#"{{"jsonrpc":"2.0","id":{id},"method":"session/prompt","params":{{"sessionId":"s1","prompt":[]}}}}"#
+            ),
+        );
+        mgr.process_message(
+            Direction::AgentToEditor,
+            &format!(r#"{{"jsonrpc":"2.0","id":{id},"result":{{"stopReason":"end_turn"}}}}"#),
+        );
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompts: Vec<_> = spans
+        .iter()
+        .filter(|s| s.name.starts_with("invoke_agent"))
+        .collect();
+    assert!(prompts[0].links.links.is_empty());
+    let link = &prompts[1].links.links[0];
+    assert_eq!(
+        link.span_context.trace_id(),
+        prompts[0].span_context.trace_id()
+    );
+    assert_eq!(
+        link.span_context.span_id(),
+        prompts[0].span_context.span_id()
+    );
+    assert_eq!(link.attributes[0].value, "previous_turn".into());
+}
+
+#[test]
+fn span_kind_follows_request_direction() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":0,"method":"fs/read_text_file","params":{"sessionId":"s1","path":"/a.rs"}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":0,"result":{"content":""}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(prompt.span_kind, SpanKind::Client);
+    assert_eq!(
+        attr(prompt, "acp.direction"),
+        Some("editor_to_agent".into())
+    );
+    let fs = spans
+        .iter()
+        .find(|s| s.name == "execute_tool fs/read_text_file")
+        .unwrap();
+    assert_eq!(fs.span_kind, SpanKind::Server);
+    assert_eq!(attr(fs, "acp.direction"), Some("agent_to_editor".into()));
+}
+
+#[test]
+fn colliding_ids_from_each_side_match_their_own_responses() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let t0 = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+    let at = |ms: u64| t0 + std::time::Duration::from_millis(ms);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":3,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+            at(0),
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":3,"method":"fs/read_text_file","params":{"sessionId":"s1","path":"/a.rs"}}"#,
+            at(5),
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":3,"result":{"content":"hello"}}"#,
+            at(10),
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":3,"result":{"stopReason":"end_turn"}}"#,
+            at(30),
+        ),
+    ];
+    for (direction, line, time) in transcript {
+        mgr.process_message_at(direction, line, time);
+    }
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let fs = spans
+        .iter()
+        .find(|s| s.name == "execute_tool fs/read_text_file")
+        .expect("fs span ended by the editor's response");
+    assert_eq!(attr(fs, "acp.fs.path"), Some("/a.rs".into()));
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .expect("prompt span ended by the agent's response");
+    assert_eq!(
+        attr(prompt, "gen_ai.response.finish_reasons"),
+        Some("[\"end_turn\"]".into())
+    );
+    assert_eq!(prompt.status, Status::Unset);
+    assert_eq!((fs.start_time, fs.end_time), (at(5), at(10)));
+    assert_eq!((prompt.start_time, prompt.end_time), (at(0), at(30)));
+    mgr.shutdown();
+}
+
+#[test]
+fn orphan_responses_are_counted_on_root_span() {
+    let (provider, exporter) = setup();
+    let tracer = opentelemetry::global::BoxedTracer::new(Box::new(provider.tracer("test")));
+    let metric_exporter = InMemoryMetricExporter::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(PeriodicReader::builder(metric_exporter.clone()).build())
+        .build();
+    let mut mgr = SpanManager::new(tracer, meter_provider.meter("test"), false);
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+    );
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","id":42,"result":{}}"#,
+    );
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":43,"error":{"code":-32603,"message":"boom"}}"#,
+    );
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let root = spans.iter().find(|s| s.name == "acp_session").unwrap();
+    assert_eq!(attr(root, "acp.orphan_responses"), Some(2i64.into()));
+
+    meter_provider.force_flush().unwrap();
+    let metrics = metric_exporter.get_finished_metrics().unwrap();
+    let counter = metrics
+        .iter()
+        .flat_map(|rm| &rm.scope_metrics)
+        .flat_map(|sm| &sm.metrics)
+        .find(|m| m.name == "acp.orphan_responses")
+        .expect("acp.orphan_responses exported");
+    let sum = counter
+        .data
+        .as_any()
+        .downcast_ref::<Sum<u64>>()
+        .expect("u64 counter");
+    assert_eq!(sum.data_points.iter().map(|p| p.value).sum::<u64>(), 2);
+}
+
+#[test]
+fn fs_reads_record_path_and_range() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":0,"method":"fs/read_text_file","params":{"sessionId":"s1","path":"/src/full.rs"}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":0,"result":{"content":""}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"method":"fs/read_text_file","params":{"sessionId":"s1","path":"/src/ranged.rs","line":10,"limit":50}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"content":""}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let reads: Vec<_> = spans
+        .iter()
+        .filter(|s| s.name == "execute_tool fs/read_text_file")
+        .collect();
+    assert_eq!(attr(reads[0], "acp.fs.path"), Some("/src/full.rs".into()));
+    assert_eq!(attr(reads[0], "acp.fs.line"), None);
+    assert_eq!(attr(reads[0], "acp.fs.limit"), None);
+    assert_eq!(attr(reads[1], "acp.fs.path"), Some("/src/ranged.rs".into()));
+    assert_eq!(attr(reads[1], "acp.fs.line"), Some(10i64.into()));
+    assert_eq!(attr(reads[1], "acp.fs.limit"), Some(50i64.into()));
+}
+
+#[test]
+fn fs_spans_record_content_sizes_without_content() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":0,"method":"fs/write_text_file","params":{"sessionId":"s1","path":"/a.txt","content":"日本語\nok"}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":0,"result":null}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"method":"fs/read_text_file","params":{"sessionId":"s1","path":"/a.txt"}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"content":"日本語\nok"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    for name in [
+        "execute_tool fs/write_text_file",
+        "execute_tool fs/read_text_file",
+    ] {
+        let span = spans.iter().find(|s| s.name == name).unwrap();
+        assert_eq!(attr(span, "acp.fs.content_bytes"), Some(12i64.into()));
+        assert_eq!(attr(span, "acp.fs.content_chars"), Some(6i64.into()));
+        assert_eq!(attr(span, "acp.fs.content_lines"), Some(2i64.into()));
+        assert_eq!(attr(span, "gen_ai.tool.call.arguments"), None);
+    }
+}
+
+#[test]
+fn permission_requests_nest_under_their_tool_call() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call","toolCallId":"tc1","title":"Run tests","kind":"execute","status":"pending"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":5,"method":"session/request_permission","params":{"sessionId":"s1","toolCall":{"toolCallId":"tc1"},"options":[{"optionId":"ok","name":"Allow","kind":"allow_once"}]}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":5,"result":{"outcome":{"outcome":"selected","optionId":"ok"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":6,"method":"session/request_permission","params":{"sessionId":"s1","toolCall":{"toolCallId":"gone"},"options":[]}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":6,"result":{"outcome":{"outcome":"cancelled"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let tool = spans
+        .iter()
+        .find(|s| s.name == "execute_tool Run tests")
+        .unwrap();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    let permissions: Vec<_> = spans
+        .iter()
+        .filter(|s| s.name == "session/request_permission")
+        .collect();
+    assert_eq!(permissions[0].parent_span_id, tool.span_context.span_id());
+    assert_eq!(
+        attr(permissions[0], "acp.permission.tool_call_id"),
+        Some("tc1".into())
+    );
+    assert_eq!(permissions[1].parent_span_id, prompt.span_context.span_id());
+    assert_eq!(
+        attr(permissions[1], "acp.permission.tool_call_id"),
+        Some("gone".into())
+    );
+}
+
+#[test]
+fn rejected_permission_marks_tool_span_denied() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let tool_call = |id: &str| {
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"session/update","params":{{"sessionId":"s1","update":{{"sessionUpdate":"tool_call","toolCallId":"{id}","title":"Tool {id}","kind":"edit","status":"pending"}}}}}}"#
+        )
+    };
+    let request = |rpc: u32, id: &str| {
+        format!(
+            r#"{{"jsonrpc":"2.0","id":{rpc},"method":"session/request_permission","params":{{"sessionId":"s1","toolCall":{{"toolCallId":"{id}"}},"options":[{{"optionId":"yes","name":"Allow","kind":"allow_once"}},{{"optionId":"no","name":"Reject","kind":"reject_once"}}]}}}}"#
+        )
+    };
+    let failed = |id: &str| {
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"session/update","params":{{"sessionId":"s1","update":{{"sessionUpdate":"tool_call_update","toolCallId":"{id}","status":"failed"}}}}}}"#
+        )
+    };
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+    );
+    for id in ["a", "b", "c"] {
+        mgr.process_message(Direction::AgentToEditor, &tool_call(id));
+    }
+    mgr.process_message(Direction::AgentToEditor, &request(10, "a"));
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":10,"result":{"outcome":{"outcome":"selected","optionId":"no"}}}"#,
+    );
+    mgr.process_message(Direction::AgentToEditor, &request(11, "b"));
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":11,"result":{"outcome":{"outcome":"selected","optionId":"yes"}}}"#,
+    );
+    for id in ["a", "b", "c"] {
+        mgr.process_message(Direction::AgentToEditor, &failed(id));
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let tool = |id: &str| {
+        spans
+            .iter()
+            .find(|s| s.name == format!("execute_tool Tool {id}"))
+            .unwrap()
+    };
+    assert_eq!(
+        attr(tool("a"), "acp.tool.permission"),
+        Some("denied".into())
+    );
+    assert!(tool("a")
+        .events
+        .events
+        .iter()
+        .any(|e| e.name == "acp.permission_denied"));
+    assert_eq!(
+        attr(tool("b"), "acp.tool.permission"),
+        Some("granted".into())
+    );
+    assert_eq!(attr(tool("c"), "acp.tool.permission"), None);
+}
+
+#[test]
+fn tool_span_follows_refined_title() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call","toolCallId":"tc1","title":"Running command","kind":"execute","status":"pending"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call_update","toolCallId":"tc1","title":"cargo test","status":"in_progress"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call_update","toolCallId":"tc1","title":"cargo test --workspace","status":"completed"}}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let tool = spans
+        .iter()
+        .find(|s| s.name.starts_with("execute_tool"))
+        .unwrap();
+    assert_eq!(tool.name, "execute_tool cargo test --workspace");
+    assert_eq!(
+        attr(tool, "gen_ai.tool.name"),
+        Some("cargo test --workspace".into())
+    );
+    assert_eq!(
+        attr(tool, "acp.tool.initial_title"),
+        Some("Running command".into())
+    );
+}
+
+#[test]
+fn output_size_is_counted_without_content() {
+    let (provider, exporter) = setup();
+    let tracer = opentelemetry::global::BoxedTracer::new(Box::new(provider.tracer("test")));
+    let metric_exporter = InMemoryMetricExporter::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(PeriodicReader::builder(metric_exporter.clone()).build())
+        .build();
+    let mut mgr = SpanManager::new(tracer, meter_provider.meter("test"), false);
+    let chunk = |text: &str| {
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"session/update","params":{{"sessionId":"s1","update":{{"sessionUpdate":"agent_message_chunk","content":{{"type":"text","text":"{text}"}}}}}}}}"#
+        )
+    };
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+    );
+    mgr.process_message(Direction::AgentToEditor, &chunk("Hello, "));
+    mgr.process_message(Direction::AgentToEditor, &chunk("wörld"));
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+    );
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(attr(prompt, "acp.output.chunks"), Some(2i64.into()));
+    assert_eq!(attr(prompt, "acp.output.chars"), Some(12i64.into()));
+    assert_eq!(attr(prompt, "gen_ai.output.messages"), None);
+
+    meter_provider.force_flush().unwrap();
+    let metrics = metric_exporter.get_finished_metrics().unwrap();
+    let metric = |name: &str| {
+        metrics
+            .iter()
+            .flat_map(|rm| &rm.scope_metrics)
+            .flat_map(|sm| &sm.metrics)
+            .find(|m| m.name == name)
+            .unwrap_or_else(|| panic!("{name} exported"))
+    };
+    for (name, total) in [("acp.output.chunks", 2), ("acp.output.chars", 12)] {
+        let histogram = metric(name)
+            .data
+            .as_any()
+            .downcast_ref::<Histogram<u64>>()
+            .expect("u64 histogram");
+        assert_eq!(histogram.data_points.len(), 1, "{name}");
+        assert_eq!(histogram.data_points[0].sum, total, "{name}");
+    }
+    let duration = metric("gen_ai.client.operation.duration")
+        .data
+        .as_any()
+        .downcast_ref::<Histogram<f64>>()
+        .expect("f64 histogram");
+    assert!(duration.data_points[0]
+        .attributes
+        .iter()
+        .all(|kv| !kv.key.as_str().starts_with("acp.output.")));
+}
+
+#[test]
+fn truncated_turns_are_flagged() {
+    let (mut mgr, provider, exporter) = manager(false);
+    for (id, reason) in [(1, "max_tokens"), (2, "end_turn")] {
+        mgr.process_message(
+            Direction::EditorToAgent,
+            &format!(
+                r#"{{"jsonrpc":"2.0","id":{id},"method":"session/prompt","params":{{"sessionId":"s1","prompt":[]}}}}"#
+            ),
+        );
+        mgr.process_message(
+            Direction::AgentToEditor,
+            &format!(r#"{{"jsonrpc":"2.0","id":{id},"result":{{"stopReason":"{reason}"}}}}"#),
+        );
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompts: Vec<_> = spans
+        .iter()
+        .filter(|s| s.name.starts_with("invoke_agent"))
+        .collect();
+    assert_eq!(attr(prompts[0], "acp.turn.truncated"), Some(true.into()));
+    assert!(prompts[0]
+        .events
+        .events
+        .iter()
+        .any(|e| e.name == "acp.turn_truncated"));
+    assert_eq!(attr(prompts[1], "acp.turn.truncated"), None);
+    assert!(prompts[1].events.events.is_empty());
+}
+
+#[test]
+fn unfinished_tools_end_with_the_prompt_response() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call","toolCallId":"tc1","title":"Search","kind":"search","status":"in_progress"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let tool = spans
+        .iter()
+        .find(|s| s.name == "execute_tool Search")
+        .expect("tool span ended before shutdown");
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(tool.status, Status::Ok);
+    assert_eq!(attr(tool, "acp.tool.outcome"), Some("unreported".into()));
+    assert!(tool.end_time <= prompt.end_time);
+    mgr.shutdown();
+}
+
+#[test]
+fn unfinished_tools_fail_with_a_failed_prompt() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call","toolCallId":"tc1","title":"Search","kind":"search","status":"in_progress"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32603,"message":"model overloaded"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let tool = spans
+        .iter()
+        .find(|s| s.name == "execute_tool Search")
+        .expect("tool span ended before shutdown");
+    assert!(matches!(tool.status, Status::Error { .. }));
+    assert_eq!(attr(tool, "acp.tool.outcome"), Some("unreported".into()));
+    mgr.shutdown();
+}
+
+#[test]
+fn overlapping_prompt_supersedes_the_previous_one() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call","toolCallId":"t1","title":"Search","kind":"search","status":"in_progress"}}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":2,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"max_tokens"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":2,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompts: Vec<_> = spans
+        .iter()
+        .filter(|s| s.name.starts_with("invoke_agent"))
+        .collect();
+    assert_eq!(prompts.len(), 2);
+    assert_eq!(
+        attr(prompts[0], "acp.turn.outcome"),
+        Some("superseded".into())
+    );
+    assert!(matches!(prompts[0].status, Status::Error { .. }));
+    assert_eq!(
+        attr(prompts[1], "gen_ai.response.finish_reasons"),
+        Some("[\"end_turn\"]".into())
+    );
+    assert_eq!(attr(prompts[1], "acp.turn.truncated"), None);
+    // The open tool ended with the turn it belonged to, not the next one.
+    let tool = spans
+        .iter()
+        .find(|s| s.name == "execute_tool Search")
+        .unwrap();
+    assert_eq!(tool.parent_span_id, prompts[0].span_context.span_id());
+    assert_eq!(tool.status, Status::error("prompt superseded"));
+    assert_eq!(attr(tool, "acp.tool.outcome"), None);
+    mgr.shutdown();
+}
+
+#[test]
+fn late_response_to_a_superseded_prompt_is_not_an_orphan() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":2,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"cancelled"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":2,"result":{"stopReason":"end_turn"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":9,"result":{}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let root = spans.iter().find(|s| s.name == "acp_session").unwrap();
+    assert_eq!(attr(root, "acp.orphan_responses"), Some(1i64.into()));
+}
+
+#[test]
+fn updates_for_unknown_sessions_still_produce_tool_spans() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"late","update":{"sessionUpdate":"tool_call","toolCallId":"tc1","title":"Grep","kind":"search","status":"in_progress"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"late","update":{"sessionUpdate":"tool_call_update","toolCallId":"tc1","status":"completed"}}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let root = spans.iter().find(|s| s.name == "acp_session").unwrap();
+    let tool = spans
+        .iter()
+        .find(|s| s.name == "execute_tool Grep")
+        .unwrap();
+    assert_eq!(tool.parent_span_id, root.span_context.span_id());
+    assert_eq!(tool.status, Status::Unset);
+}
+
+#[test]
+fn repeated_initialize_starts_a_new_root() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let init = |id: u32, agent: &str| {
+        [
+            (
+                Direction::EditorToAgent,
+                format!(
+                    r#"{{"jsonrpc":"2.0","id":{id},"method":"initialize","params":{{"protocolVersion":1}}}}"#
+                ),
+            ),
+            (
+                Direction::AgentToEditor,
+                format!(
+                    r#"{{"jsonrpc":"2.0","id":{id},"result":{{"protocolVersion":1,"agentInfo":{{"name":"{agent}","version":"1.0"}}}}}}"#
+                ),
+            ),
+        ]
+    };
+    for (direction, line) in init(0, "first") {
+        mgr.process_message(direction, &line);
+    }
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+    );
+    for (direction, line) in init(2, "second") {
+        mgr.process_message(direction, &line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let roots: Vec<_> = spans.iter().filter(|s| s.name == "acp_session").collect();
+    assert_eq!(roots.len(), 2);
+    assert_ne!(
+        roots[0].span_context.trace_id(),
+        roots[1].span_context.trace_id()
+    );
+    assert_eq!(attr(roots[0], "gen_ai.agent.name"), Some("first".into()));
+    assert_eq!(attr(roots[1], "gen_ai.agent.name"), Some("second".into()));
+    assert_eq!(attr(roots[1], "acp.handshakes"), Some(2i64.into()));
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(prompt.status, Status::error("agent restarted"));
+}
+
+#[test]
+fn cancel_latency_and_ignored_cancels() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let prompt = |id: u32| {
+        format!(
+            r#"{{"jsonrpc":"2.0","id":{id},"method":"session/prompt","params":{{"sessionId":"s1","prompt":[]}}}}"#
+        )
+    };
+    let cancel = r#"{"jsonrpc":"2.0","method":"session/cancel","params":{"sessionId":"s1"}}"#;
+    let t0 = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+    let at = |ms: u64| t0 + std::time::Duration::from_millis(ms);
+    mgr.process_message_at(Direction::EditorToAgent, &prompt(1), at(0));
+    mgr.process_message_at(Direction::EditorToAgent, cancel, at(10));
+    mgr.process_message_at(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"cancelled"}}"#,
+        at(30),
+    );
+    mgr.process_message_at(Direction::EditorToAgent, &prompt(2), at(40));
+    mgr.process_message_at(Direction::EditorToAgent, cancel, at(50));
+    mgr.process_message_at(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","id":2,"result":{"stopReason":"end_turn"}}"#,
+        at(60),
+    );
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompts: Vec<_> = spans
+        .iter()
+        .filter(|s| s.name.starts_with("invoke_agent"))
+        .collect();
+    assert_eq!(
+        attr(prompts[0], "acp.cancel.latency_ms"),
+        Some(20i64.into())
+    );
+    assert_eq!(attr(prompts[0], "acp.cancel.ignored"), None);
+    assert_eq!(attr(prompts[1], "acp.cancel.ignored"), Some(true.into()));
+    assert_eq!(attr(prompts[1], "acp.cancel.latency_ms"), None);
+}
+
+fn run_tool_with_updates(record_content: bool, updates: &[&str]) -> SpanData {
+    let (mut mgr, provider, exporter) = manager(record_content);
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+    );
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call","toolCallId":"tc1","title":"Run","kind":"execute","status":"in_progress"}}}"#,
+    );
+    for update in updates {
+        let msg = format!(
+            r#"{{"jsonrpc":"2.0","method":"session/update","params":{{"sessionId":"s1","update":{{"sessionUpdate":"tool_call_update","toolCallId":"tc1",{update}}}}}}}"#
+        );
+        mgr.process_message(Direction::AgentToEditor, &msg);
+    }
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+    );
+    mgr.shutdown();
+    let _ = provider.force_flush();
+    exporter
+        .spans()
+        .into_iter()
+        .find(|s| s.name.starts_with("execute_tool"))
+        .unwrap()
+}
+
+#[test]
+fn streamed_tool_content_becomes_the_result() {
+    let updates = [
+        r#""content":[{"type":"content","content":{"type":"text","text":"line 1\n"}}]"#,
+        r#""content":[{"type":"content","content":{"type":"text","text":"line 2\n"}}]"#,
+        r#""status":"completed","content":[{"type":"content","content":{"type":"text","text":"done"}}]"#,
+    ];
+    let tool = run_tool_with_updates(true, &updates);
+    assert_eq!(
+        attr(&tool, "gen_ai.tool.call.result"),
+        Some("line 1\nline 2\ndone".into())
+    );
+    assert_eq!(attr(&tool, "acp.tool.output.chars"), Some(18i64.into()));
+
+    let tool = run_tool_with_updates(false, &updates);
+    assert_eq!(attr(&tool, "gen_ai.tool.call.result"), None);
+    assert_eq!(attr(&tool, "acp.tool.output.chars"), Some(18i64.into()));
+}
+
+#[test]
+fn raw_output_wins_over_streamed_content() {
+    let tool = run_tool_with_updates(
+        true,
+        &[
+            r#""content":[{"type":"content","content":{"type":"text","text":"partial"}}]"#,
+            r#""status":"completed","rawOutput":{"exit":0}"#,
+        ],
+    );
+    assert_eq!(
+        attr(&tool, "gen_ai.tool.call.result"),
+        Some(r#"{"exit":0}"#.into())
+    );
+    assert_eq!(attr(&tool, "acp.tool.output.chars"), Some(7i64.into()));
+}
+
+#[test]
+fn streamed_raw_input_is_merged_key_by_key() {
+    let tool = run_tool_with_updates(
+        true,
+        &[
+            r#""rawInput":{"command":"ls"}"#,
+            r#""rawInput":{"cwd":"/tmp","command":"ls -la"}"#,
+            r#""status":"completed""#,
+        ],
+    );
+    assert_eq!(
+        attr(&tool, "gen_ai.tool.call.arguments"),
+        Some(r#"{"command":"ls -la","cwd":"/tmp"}"#.into())
+    );
+
+    let tool = run_tool_with_updates(
+        false,
+        &[r#""rawInput":{"command":"ls"}"#, r#""status":"completed""#],
+    );
+    assert_eq!(attr(&tool, "gen_ai.tool.call.arguments"), None);
+}
+
+#[test]
+fn mcp_tools_are_split_and_counted_per_turn() {
+    let (mut mgr, provider, exporter) = manager(false);
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+    );
+    for (id, title) in [
+        ("tc1", "mcp__github__create_issue"),
+        ("tc2", "Read file"),
+        ("tc3", "search_docs (context7 MCP Server)"),
+    ] {
+        let msg = format!(
+            r#"{{"jsonrpc":"2.0","method":"session/update","params":{{"sessionId":"s1","update":{{"sessionUpdate":"tool_call","toolCallId":"{id}","title":"{title}","kind":"other","status":"completed"}}}}}}"#
+        );
+        mgr.process_message(Direction::AgentToEditor, &msg);
+    }
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+    );
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let tool = |id: &str| {
+        spans
+            .iter()
+            .find(|s| attr(s, "gen_ai.tool.call.id") == Some(id.to_string().into()))
+            .unwrap()
+    };
+    let github = tool("tc1");
+    assert_eq!(attr(github, "acp.mcp.server"), Some("github".into()));
+    assert_eq!(
+        attr(github, "gen_ai.tool.name"),
+        Some("create_issue".into())
+    );
+    assert_eq!(
+        attr(github, "acp.tool.title"),
+        Some("mcp__github__create_issue".into())
+    );
+    let read = tool("tc2");
+    assert_eq!(attr(read, "acp.mcp.server"), None);
+    assert_eq!(attr(read, "gen_ai.tool.name"), Some("Read file".into()));
+    assert_eq!(attr(tool("tc3"), "acp.mcp.server"), Some("context7".into()));
+
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(attr(prompt, "acp.turn.tool_calls.mcp"), Some(2i64.into()));
+    assert_eq!(
+        attr(prompt, "acp.turn.tool_calls.builtin"),
+        Some(1i64.into())
+    );
+}
+
+#[test]
+fn response_model_prefers_reported_over_configured() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/set_model","params":{"sessionId":"s1","modelId":"auto"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":2,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":2,"result":{"stopReason":"end_turn","_meta":{"model":"opus"}}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":3,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"hi"},"_meta":{"modelId":"haiku"}}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":3,"result":{"stopReason":"end_turn"}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":4,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":4,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let models: Vec<_> = exporter
+        .spans()
+        .iter()
+        .filter(|s| s.name.starts_with("invoke_agent"))
+        .map(|s| attr(s, "gen_ai.response.model"))
+        .collect();
+    assert_eq!(
+        models,
+        vec![
+            Some("opus".into()),
+            Some("haiku".into()),
+            Some("auto".into())
+        ]
+    );
+}
+
+#[test]
+fn meta_attrs_land_on_the_affected_span() {
+    let (mgr, provider, exporter) = manager(false);
+    let mut mgr = mgr.with_meta_attrs(vec![
+        "requestId=vendor.request_id".parse().unwrap(),
+        "cost.usd=vendor.cost_usd".parse().unwrap(),
+        "cache.hit=vendor.cache_hit".parse().unwrap(),
+        "tool.server=vendor.server".parse().unwrap(),
+    ]);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[],"_meta":{"requestId":"r-1"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call","toolCallId":"tc1","title":"Search","kind":"search","status":"in_progress","_meta":{"tool":{"server":"docs"}}}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call_update","toolCallId":"tc1","status":"completed","_meta":{"cache":{"hit":true}}}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn","_meta":{"cost":{"usd":0.5}}}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(attr(prompt, "vendor.request_id"), Some("r-1".into()));
+    assert_eq!(attr(prompt, "vendor.cost_usd"), Some(0.5.into()));
+    assert_eq!(attr(prompt, "vendor.cache_hit"), None);
+    let tool = spans
+        .iter()
+        .find(|s| s.name.starts_with("execute_tool"))
+        .unwrap();
+    assert_eq!(attr(tool, "vendor.server"), Some("docs".into()));
+    assert_eq!(attr(tool, "vendor.cache_hit"), Some(true.into()));
+}
+
+#[test]
+fn ignored_notifications_leave_other_spans_alone() {
+    let (mgr, provider, exporter) = manager(false);
+    let mut mgr = mgr
+        .with_ignored_methods(vec!["session/update".parse().unwrap()])
+        .with_meta_attrs(vec!["mode=vendor.mode".parse().unwrap()]);
+    let update = r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"hi"},"_meta":{"mode":"fast"}}}}"#;
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":0,"result":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (Direction::AgentToEditor, update),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+        ),
+        // Would break `update_after_turn` if it were traced.
+        (Direction::AgentToEditor, update),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(attr(prompt, "vendor.mode"), None);
+    assert_eq!(attr(prompt, "acp.updates.size_bytes"), None);
+    assert!(!spans
+        .iter()
+        .flat_map(|s| s.events.iter())
+        .any(|e| e.name == "acp.protocol_violation"));
+}
+
+#[test]
+fn meta_attr_requires_path_and_attribute() {
+    use acp_traces::spans::MetaAttr;
+    let parsed: MetaAttr = "a.b=x.y".parse().unwrap();
+    assert_eq!(parsed.path, "a.b");
+    assert_eq!(parsed.attribute, "x.y");
+    assert!("a.b".parse::<MetaAttr>().is_err());
+    assert!("=x".parse::<MetaAttr>().is_err());
+    assert!("a=".parse::<MetaAttr>().is_err());
+}
+
+#[test]
+fn stuck_requests_time_out_and_late_responses_are_orphans() {
+    use std::time::{Duration, Instant};
+    let (mgr, provider, exporter) = manager(false);
+    let mut mgr = mgr
+        .with_request_timeout(Duration::from_secs(30))
+        .with_prompt_timeout(Duration::from_secs(60));
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":0,"result":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call","toolCallId":"tc1","title":"Search","kind":"search","status":"in_progress"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":7,"method":"fs/read_text_file","params":{"sessionId":"s1","path":"/a.rs"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":8,"method":"session/request_permission","params":{"sessionId":"s1","toolCall":{"toolCallId":"tc1"},"options":[]}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    let start = Instant::now();
+    mgr.expire_requests(start);
+    mgr.expire_requests(start + Duration::from_secs(31));
+    let _ = provider.force_flush();
+    let names = |spans: Vec<opentelemetry_sdk::trace::SpanData>| {
+        spans
+            .into_iter()
+            .map(|s| s.name.to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names(exporter.spans()),
+        ["initialize", "execute_tool fs/read_text_file"]
+    );
+
+    mgr.expire_requests(start + Duration::from_secs(61));
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+    );
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":8,"result":{"outcome":{"outcome":"cancelled"}}}"#,
+    );
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    for name in ["invoke_agent", "execute_tool fs/read_text_file"] {
+        let span = spans.iter().find(|s| s.name.starts_with(name)).unwrap();
+        assert_eq!(span.status, Status::error("request timed out"), "{name}");
+        assert_eq!(attr(span, "error.type"), Some("timeout".into()));
+        assert_eq!(attr(span, "acp.request.timed_out"), Some(true.into()));
+    }
+    let tool = spans
+        .iter()
+        .find(|s| s.name == "execute_tool Search")
+        .unwrap();
+    assert_eq!(tool.status, Status::error("prompt timed out"));
+    let permission = spans
+        .iter()
+        .find(|s| s.name == "session/request_permission")
+        .unwrap();
+    assert_eq!(attr(permission, "acp.request.timed_out"), None);
+    let root = spans.iter().find(|s| s.name == "acp_session").unwrap();
+    assert_eq!(attr(root, "acp.orphan_responses"), Some(1i64.into()));
+}
+
+#[test]
+fn reused_request_ids_end_the_earlier_request() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":7,"method":"fs/read_text_file","params":{"sessionId":"s1","path":"/a.rs"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":7,"method":"fs/read_text_file","params":{"sessionId":"s1","path":"/b.rs"}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":7,"result":{"content":"b"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let reads: Vec<_> = spans
+        .iter()
+        .filter(|s| s.name == "execute_tool fs/read_text_file")
+        .collect();
+    assert_eq!(reads.len(), 2);
+    let first = reads
+        .iter()
+        .find(|s| attr(s, "acp.fs.path") == Some("/a.rs".into()))
+        .unwrap();
+    assert_eq!(attr(first, "acp.request.superseded"), Some(true.into()));
+    assert_eq!(
+        first.status,
+        Status::error("request id reused before its response")
+    );
+    let second = reads
+        .iter()
+        .find(|s| attr(s, "acp.fs.path") == Some("/b.rs".into()))
+        .unwrap();
+    assert_eq!(attr(second, "acp.request.superseded"), None);
+    assert!(!matches!(second.status, Status::Error { .. }));
+}
+
+#[test]
+fn payload_sizes_are_recorded() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let prompt = r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#;
+    let update = r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"hi"}}}}"#;
+    let read = r#"{"jsonrpc":"2.0","id":7,"method":"fs/read_text_file","params":{"sessionId":"s1","path":"/a.rs"}}"#;
+    let read_result = r#"{"jsonrpc":"2.0","id":7,"result":{"content":"fn main() {}"}}"#;
+    let response = r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#;
+    mgr.process_message(Direction::EditorToAgent, prompt);
+    mgr.process_message(Direction::AgentToEditor, update);
+    mgr.process_message(Direction::AgentToEditor, update);
+    mgr.process_message(Direction::AgentToEditor, read);
+    mgr.process_message(Direction::EditorToAgent, read_result);
+    mgr.process_message(Direction::AgentToEditor, response);
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let size = |n: usize| Some(opentelemetry::Value::I64(n as i64));
+    let fs = spans
+        .iter()
+        .find(|s| s.name == "execute_tool fs/read_text_file")
+        .unwrap();
+    assert_eq!(attr(fs, "acp.request.size_bytes"), size(read.len()));
+    assert_eq!(attr(fs, "acp.response.size_bytes"), size(read_result.len()));
+    let turn = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(attr(turn, "acp.request.size_bytes"), size(prompt.len()));
+    assert_eq!(attr(turn, "acp.response.size_bytes"), size(response.len()));
+    assert_eq!(attr(turn, "acp.updates.size_bytes"), size(2 * update.len()));
+}
+
+#[test]
+fn protocol_violations_become_events() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        // No initialize response yet.
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call_update","toolCallId":"tc1","status":"completed"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call_update","toolCallId":"tc1","status":"completed"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"},"error":{"code":-32603,"message":"x"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"late"}}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"current_mode_update","currentModeId":"ask"}}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let rules = |span: &SpanData| -> Vec<String> {
+        span.events
+            .events
+            .iter()
+            .filter(|e| e.name == "acp.protocol_violation")
+            .filter_map(|e| e.attributes.first().map(|kv| kv.value.to_string()))
+            .collect()
+    };
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(
+        rules(prompt),
+        vec![
+            "prompt_before_initialize",
+            "update_for_finished_tool",
+            "result_and_error"
+        ]
+    );
+    let root = spans.iter().find(|s| s.name == "acp_session").unwrap();
+    assert_eq!(rules(root), vec!["update_after_turn"]);
+}
+
+#[test]
+fn reinitialize_waits_for_the_new_handshake_before_prompts_are_valid() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":0,"result":{"protocolVersion":1}}"#,
+        ),
+        // The agent restarted; its new handshake hasn't completed yet.
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":2,"method":"session/prompt","params":{"sessionId":"early","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":3,"method":"session/prompt","params":{"sessionId":"late","prompt":[]}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let violations = |session: &str| -> usize {
+        let spans = exporter.spans();
+        let prompt = spans
+            .iter()
+            .find(|s| {
+                s.name.starts_with("invoke_agent")
+                    && attr(s, "gen_ai.conversation.id") == Some(session.to_string().into())
+            })
+            .unwrap();
+        prompt
+            .events
+            .events
+            .iter()
+            .filter(|e| e.name == "acp.protocol_violation")
+            .count()
+    };
+    assert_eq!(violations("early"), 1);
+    assert_eq!(violations("late"), 0);
+}
+
+#[test]
+fn long_content_is_cut_with_a_marker() {
+    let (mgr, provider, exporter) = manager(true);
+    let mut mgr = mgr.with_max_attr_length(80);
+    let long = "é".repeat(100);
+    let prompt = format!(
+        r#"{{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{{"sessionId":"s1","prompt":[{{"type":"text","text":"{long}"}}]}}}}"#
+    );
+    let transcript = [
+        (Direction::EditorToAgent, prompt.as_str()),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"ok"}}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    let input = attr(prompt, "gen_ai.input.messages").unwrap().to_string();
+    assert!(input.len() <= 80, "{} bytes", input.len());
+    assert!(input.contains("…[truncated "), "{input}");
+    assert!(input.ends_with(" bytes]"), "{input}");
+    assert_eq!(attr(prompt, "acp.content.truncated"), Some(true.into()));
+}
+
+#[test]
+fn content_within_the_limit_is_not_flagged() {
+    let (mgr, provider, exporter) = manager(true);
+    let mut mgr = mgr.with_max_attr_length(4096);
+    for (direction, line) in [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[{"type":"text","text":"hi"}]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ] {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert!(attr(prompt, "gen_ai.input.messages")
+        .unwrap()
+        .to_string()
+        .contains("hi"));
+    assert_eq!(attr(prompt, "acp.content.truncated"), None);
+}
+
+/// One turn whose prompt, tool call, and reply carry an API key and an email, run
+/// through a manager set up by `configure`. Returns the prompt and tool spans.
+fn content_turn(configure: impl FnOnce(SpanManager) -> SpanManager) -> (SpanData, SpanData) {
+    let (mgr, provider, exporter) = manager(false);
+    let mut mgr = configure(mgr);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[{"type":"text","text":"use sk-abcdef123456 and mail dev@example.com"}]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call","toolCallId":"tc1","title":"Run","kind":"execute","status":"in_progress","rawInput":{"token":"sk-abcdef123456"}}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call_update","toolCallId":"tc1","status":"completed","rawOutput":{"owner":"dev@example.com"}}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"sent to dev@example.com"}}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let find = |prefix: &str| {
+        spans
+            .iter()
+            .find(|s| s.name.starts_with(prefix))
+            .unwrap()
+            .clone()
+    };
+    (find("invoke_agent"), find("execute_tool"))
+}
+
+/// [`content_turn`] recording everything, with `patterns` as `--redact-pattern`s.
+fn redacted_turn(patterns: &[&str]) -> (SpanData, SpanData) {
+    let patterns: Vec<_> = patterns
+        .iter()
+        .map(|p| regex::Regex::new(p).unwrap())
+        .collect();
+    content_turn(|mgr| {
+        mgr.with_content_policy(ContentPolicy::all())
+            .with_redact_patterns(patterns)
+    })
+}
+
+const CONTENT_KEYS: [(bool, &str); 4] = [
+    (true, "gen_ai.input.messages"),
+    (true, "gen_ai.output.messages"),
+    (false, "gen_ai.tool.call.arguments"),
+    (false, "gen_ai.tool.call.result"),
+];
+
+#[test]
+fn every_redact_pattern_applies_to_all_content() {
+    let (prompt, tool) = redacted_turn(&[r"sk-[A-Za-z0-9]{8,}", r"[\w.]+@[\w.]+\.\w+"]);
+    for (on_prompt, key) in CONTENT_KEYS {
+        let span = if on_prompt { &prompt } else { &tool };
+        let value = attr(span, key).unwrap().to_string();
+        assert!(value.contains("[REDACTED]"), "{key}: {value}");
+        assert!(!value.contains("sk-abcdef"), "{key}: {value}");
+        assert!(!value.contains("example.com"), "{key}: {value}");
+    }
+    let input = attr(&prompt, "gen_ai.input.messages").unwrap().to_string();
+    assert!(
+        input.contains("use [REDACTED] and mail [REDACTED]"),
+        "{input}"
+    );
+}
+
+#[test]
+fn overlapping_matches_are_redacted_once() {
+    // "sk-abcdef123456" and "abcdef123456 and mail dev" overlap: the union is hidden
+    // behind a single marker, with nothing of either match left over.
+    let (prompt, _) = redacted_turn(&[r"sk-[a-z0-9]+", r"abcdef\w* and mail dev"]);
+    let input = attr(&prompt, "gen_ai.input.messages").unwrap().to_string();
+    assert!(input.contains("use [REDACTED]@example.com"), "{input}");
+    assert_eq!(input.matches("[REDACTED]").count(), 1, "{input}");
+}
+
+#[test]
+fn content_is_untouched_without_redact_patterns() {
+    let (prompt, tool) = redacted_turn(&[]);
+    for (on_prompt, key) in CONTENT_KEYS {
+        let span = if on_prompt { &prompt } else { &tool };
+        let value = attr(span, key).unwrap().to_string();
+        assert!(!value.contains("[REDACTED]"), "{key}: {value}");
+    }
+    assert_eq!(
+        attr(&tool, "gen_ai.tool.call.arguments"),
+        Some(r#"{"token":"sk-abcdef123456"}"#.into())
+    );
+    assert_eq!(
+        attr(&tool, "gen_ai.tool.call.result"),
+        Some(r#"{"owner":"dev@example.com"}"#.into())
+    );
+}
+
+/// The `N` in a value ending in `…[truncated N bytes]`, and the text before the marker.
+fn truncation_marker(value: &str) -> (&str, usize) {
+    let (kept, marker) = value.split_once("…[truncated ").expect("marker");
+    let count = marker.strip_suffix(" bytes]").expect("marker end");
+    (kept, count.parse().unwrap())
+}
+
+#[test]
+fn streamed_chunks_past_the_limit_are_counted_but_not_stored() {
+    let (mgr, provider, exporter) = manager(true);
+    let mut mgr = mgr.with_max_attr_length(200);
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+    );
+    let chunk = "x".repeat(100);
+    for _ in 0..10 {
+        mgr.process_message(
+            Direction::AgentToEditor,
+            &format!(
+                r#"{{"jsonrpc":"2.0","method":"session/update","params":{{"sessionId":"s1","update":{{"sessionUpdate":"agent_message_chunk","content":{{"type":"text","text":"{chunk}"}}}}}}}}"#
+            ),
+        );
+    }
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+    );
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(attr(prompt, "acp.output.chars"), Some(1000i64.into()));
+    let output = attr(prompt, "gen_ai.output.messages").unwrap().to_string();
+    assert!(output.len() <= 200, "{} bytes", output.len());
+    assert_eq!(attr(prompt, "acp.content.truncated"), Some(true.into()));
+    // The marker accounts for the text never buffered, not just the final cut.
+    let full = format!(
+        r#"[{{"role":"assistant","parts":[{{"type":"text","content":"{}"}}],"finish_reason":"stop"}}]"#,
+        "x".repeat(1000)
+    );
+    let (kept, cut) = truncation_marker(&output);
+    assert_eq!(kept.len() + cut, full.len());
+}
+
+#[test]
+fn streamed_tool_output_past_the_limit_is_marked() {
+    let (mgr, provider, exporter) = manager(true);
+    let mut mgr = mgr.with_max_attr_length(64);
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+    );
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call","toolCallId":"tc1","title":"cat Cargo.lock","kind":"read","status":"in_progress"}}}"#,
+    );
+    let line = "y".repeat(50);
+    for _ in 0..4 {
+        mgr.process_message(
+            Direction::AgentToEditor,
+            &format!(
+                r#"{{"jsonrpc":"2.0","method":"session/update","params":{{"sessionId":"s1","update":{{"sessionUpdate":"tool_call_update","toolCallId":"tc1","content":[{{"type":"content","content":{{"type":"text","text":"{line}"}}}}]}}}}}}"#
+            ),
+        );
+    }
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call_update","toolCallId":"tc1","status":"completed"}}}"#,
+    );
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let tool = spans
+        .iter()
+        .find(|s| s.name.starts_with("execute_tool"))
+        .unwrap();
+    assert_eq!(attr(tool, "acp.tool.output.chars"), Some(200i64.into()));
+    let result = attr(tool, "gen_ai.tool.call.result").unwrap().to_string();
+    assert!(result.len() <= 64, "{} bytes", result.len());
+    let (kept, cut) = truncation_marker(&result);
+    assert_eq!(kept.len() + cut, 200);
+    assert_eq!(attr(tool, "acp.content.truncated"), Some(true.into()));
+}
+
+/// Stream `chunks` as agent message chunks and as tool output in one turn, redacting
+/// 40-character `sk-` keys, and return the exported prompt and tool spans.
+fn redacted_stream(max_attr_length: Option<usize>, chunks: &[String]) -> (SpanData, SpanData) {
+    let (mgr, provider, exporter) = manager(true);
+    let mut mgr = mgr.with_redact_patterns(vec![regex::Regex::new(r"sk-[a-z0-9]{40}").unwrap()]);
+    if let Some(max) = max_attr_length {
+        mgr = mgr.with_max_attr_length(max);
+    }
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+    );
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call","toolCallId":"tc1","title":"env","kind":"execute","status":"in_progress"}}}"#,
+    );
+    for text in chunks {
+        mgr.process_message(
+            Direction::AgentToEditor,
+            &format!(
+                r#"{{"jsonrpc":"2.0","method":"session/update","params":{{"sessionId":"s1","update":{{"sessionUpdate":"agent_message_chunk","content":{{"type":"text","text":"{text}"}}}}}}}}"#
+            ),
+        );
+        mgr.process_message(
+            Direction::AgentToEditor,
+            &format!(
+                r#"{{"jsonrpc":"2.0","method":"session/update","params":{{"sessionId":"s1","update":{{"sessionUpdate":"tool_call_update","toolCallId":"tc1","content":[{{"type":"content","content":{{"type":"text","text":"{text}"}}}}]}}}}}}"#
+            ),
+        );
+    }
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call_update","toolCallId":"tc1","status":"completed"}}}"#,
+    );
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+    );
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let find = |prefix: &str| {
+        spans
+            .iter()
+            .find(|s| s.name.starts_with(prefix))
+            .unwrap()
+            .clone()
+    };
+    (find("invoke_agent"), find("execute_tool"))
+}
+
+#[test]
+fn secrets_straddling_the_length_limit_are_redacted() {
+    let secret = format!("sk-{}", "a1".repeat(20));
+    let (prompt, tool) = redacted_stream(Some(200), &["x".repeat(160), secret]);
+    for (span, key) in [
+        (&prompt, "gen_ai.output.messages"),
+        (&tool, "gen_ai.tool.call.result"),
+    ] {
+        let value = attr(span, key).unwrap().to_string();
+        assert!(value.len() <= 200, "{key}: {} bytes", value.len());
+        assert!(!value.contains("sk-"), "{key}: {value}");
+        assert!(!value.contains("a1a1"), "{key}: {value}");
+    }
+}
+
+#[test]
+fn secrets_straddling_the_tool_output_cap_are_redacted() {
+    let secret = format!("sk-{}", "a1".repeat(20));
+    let (_, tool) = redacted_stream(None, &["x".repeat(16 * 1024 - 10), secret]);
+    let result = attr(&tool, "gen_ai.tool.call.result").unwrap().to_string();
+    assert!(!result.contains("sk-"), "{}", &result[result.len() - 60..]);
+    assert!(
+        result.ends_with("[REDACTED]"),
+        "{}",
+        &result[result.len() - 60..]
+    );
+}
+
+#[test]
+fn each_record_flag_only_emits_its_own_attributes() {
+    for bits in 0..16u8 {
+        let policy = ContentPolicy {
+            input: bits & 1 != 0,
+            output: bits & 2 != 0,
+            tool_args: bits & 4 != 0,
+            tool_results: bits & 8 != 0,
+        };
+        let (prompt, tool) = content_turn(|mgr| mgr.with_content_policy(policy));
+        let emitted = |span: &SpanData, key| attr(span, key).is_some();
+        assert_eq!(
+            (
+                emitted(&prompt, "gen_ai.input.messages"),
+                emitted(&prompt, "gen_ai.output.messages"),
+                emitted(&tool, "gen_ai.tool.call.arguments"),
+                emitted(&tool, "gen_ai.tool.call.result"),
+            ),
+            (
+                policy.input,
+                policy.output,
+                policy.tool_args,
+                policy.tool_results
+            ),
+            "{policy:?}"
+        );
+    }
+}
+
+#[test]
+fn method_patterns_match_exact_names_and_prefixes() {
+    use acp_traces::spans::MethodPattern;
+    let exact: MethodPattern = "terminal/output".parse().unwrap();
+    assert!(exact.matches("terminal/output"));
+    assert!(!exact.matches("terminal/output2"));
+    assert!(!exact.matches("terminal/create"));
+    let prefix: MethodPattern = "terminal/*".parse().unwrap();
+    assert!(prefix.matches("terminal/output"));
+    assert!(prefix.matches("terminal/wait_for_exit"));
+    assert!(!prefix.matches("fs/read_text_file"));
+    assert!("*".parse::<MethodPattern>().unwrap().matches("anything"));
+    assert!("".parse::<MethodPattern>().is_err());
+    assert!("terminal/*/x".parse::<MethodPattern>().is_err());
+}
+
+#[test]
+fn ignored_methods_produce_no_spans_or_orphans() {
+    let (mgr, provider, exporter) = manager(false);
+    let mut mgr = mgr.with_ignored_methods(vec![
+        "terminal/*".parse().unwrap(),
+        "_vendor/ping".parse().unwrap(),
+    ]);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":0,"result":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":5,"method":"terminal/output","params":{"sessionId":"s1","terminalId":"t1"}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":5,"result":{"output":"...","truncated":false}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":6,"method":"fs/read_text_file","params":{"sessionId":"s1","path":"/a.rs"}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":6,"result":{"content":"fn main() {}"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"_vendor/ping","params":{"sessionId":"s1"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"_vendor/status","params":{"sessionId":"s1"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    assert!(!spans.iter().any(|s| s.name.contains("terminal/")));
+    assert!(spans
+        .iter()
+        .any(|s| s.name == "execute_tool fs/read_text_file"));
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    let notifications: Vec<_> = prompt
+        .events
+        .iter()
+        .filter(|e| e.name == "acp.extension_notification")
+        .filter_map(|e| {
+            e.attributes
+                .iter()
+                .find(|kv| kv.key.as_str() == "acp.extension.method")
+                .map(|kv| kv.value.to_string())
+        })
+        .collect();
+    assert_eq!(notifications, vec!["_vendor/status"]);
+    let root = spans.iter().find(|s| s.name == "acp_session").unwrap();
+    assert_eq!(attr(root, "acp.orphan_responses"), None);
+}
+
+#[test]
+fn only_methods_keep_listed_spans_under_the_root() {
+    let (mgr, provider, exporter) = manager(false);
+    let mut mgr = mgr.with_only_methods(vec![
+        "session/prompt".parse().unwrap(),
+        "session/update".parse().unwrap(),
+    ]);
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":0,"result":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/new","params":{"cwd":"/tmp","mcpServers":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"sessionId":"s1"}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":2,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":7,"method":"fs/read_text_file","params":{"sessionId":"s1","path":"/a.rs"}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":7,"result":{"content":"fn main() {}"}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"tool_call","toolCallId":"tc1","title":"Read","kind":"read","status":"completed"}}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":2,"result":{"stopReason":"end_turn"}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let mut names: Vec<_> = spans.iter().map(|s| s.name.to_string()).collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec!["acp_session", "execute_tool Read", "invoke_agent"],
+        "{names:?}"
+    );
+    let root = spans.iter().find(|s| s.name == "acp_session").unwrap();
+    let prompt = spans.iter().find(|s| s.name == "invoke_agent").unwrap();
+    assert_eq!(prompt.parent_span_id, root.span_context.span_id());
+    // The hidden initialize still counts, so the prompt isn't flagged as premature.
+    assert!(!prompt
+        .events
+        .iter()
+        .any(|e| e.name == "acp.protocol_violation"));
+    assert_eq!(attr(root, "acp.orphan_responses"), None);
+}
+
+#[test]
+fn replayed_transcript_keeps_recorded_timing() {
+    let (mut mgr, provider, exporter) = manager(false);
+    let path = std::env::temp_dir().join(format!("acp-traces-replay-{}.jsonl", std::process::id()));
+    let dump = [
+        r#"{"ts":"2024-02-29T12:00:00.000Z","dir":"editor_to_agent","msg":{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}}"#,
+        r#"{"ts":"2024-02-29T12:00:00.250Z","dir":"agent_to_editor","msg":{"jsonrpc":"2.0","id":0,"result":{"protocolVersion":1}}}"#,
+        r#"{"ts":"2024-02-29T12:00:01.000Z","dir":"editor_to_agent","msg":{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}}"#,
+        "not a transcript entry",
+        r#"{"ts":"2024-02-29T12:00:04.500Z","dir":"agent_to_editor","msg":"{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"stopReason\":\"end_turn\"}}"}"#,
+    ];
+    std::fs::write(&path, dump.join("\n")).unwrap();
+    let replayed = acp_traces::transcript::replay(&path, &mut mgr).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    assert_eq!(replayed, 4);
+    let at = |millis: u64| UNIX_EPOCH + Duration::from_millis(1_709_208_000_000 + millis);
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(prompt.start_time, at(1_000));
+    assert_eq!(prompt.end_time, at(4_500));
+    let root = spans.iter().find(|s| s.name == "acp_session").unwrap();
+    assert_eq!(root.start_time, at(0));
+    assert_eq!(root.end_time, at(4_500));
+    assert!(spans
+        .iter()
+        .all(|s| s.end_time < SystemTime::now() - Duration::from_secs(60)));
+}
+
+#[test]
+fn agent_name_fallback_applies_only_without_agent_info() {
+    let initialized = |agent_info: &str| {
+        let (mgr, provider, exporter) = manager(false);
+        let mut mgr = mgr.with_agent_info("fallback".into(), Some("0.9".into()));
+        let transcript = [
+            (
+                Direction::EditorToAgent,
+                r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#
+                    .to_string(),
+            ),
+            (
+                Direction::AgentToEditor,
+                format!(r#"{{"jsonrpc":"2.0","id":0,"result":{{"protocolVersion":1{agent_info}}}}}"#),
+            ),
+            (
+                Direction::EditorToAgent,
+                r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#
+                    .to_string(),
+            ),
+        ];
+        for (direction, line) in transcript {
+            mgr.process_message(direction, &line);
+        }
+        mgr.shutdown();
+        let _ = provider.force_flush();
+        exporter.spans()
+    };
+
+    let spans = initialized("");
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(prompt.name, "invoke_agent fallback");
+    assert_eq!(
+        attr(prompt, "gen_ai.provider.name"),
+        Some("acp.fallback".into())
+    );
+    assert_eq!(attr(prompt, "acp.agent.version"), Some("0.9".into()));
+    for name in ["acp_session", "initialize"] {
+        let span = spans.iter().find(|s| s.name == name).unwrap();
+        assert_eq!(
+            attr(span, "gen_ai.agent.name"),
+            Some("fallback".into()),
+            "{name}"
+        );
+    }
+
+    let spans = initialized(r#","agentInfo":{"name":"real"}"#);
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    assert_eq!(prompt.name, "invoke_agent real");
+    assert_eq!(attr(prompt, "acp.agent.version"), None);
+    let root = spans.iter().find(|s| s.name == "acp_session").unwrap();
+    assert_eq!(attr(root, "gen_ai.agent.name"), Some("real".into()));
+}
+
+#[test]
+fn active_span_context_follows_the_open_prompt() {
+    let (mut mgr, provider, exporter) = manager(false);
+    assert!(mgr.active_span_context().is_none());
+    let transcript = [
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::AgentToEditor,
+            r#"{"jsonrpc":"2.0","id":0,"result":{"protocolVersion":1}}"#,
+        ),
+        (
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, line);
+    }
+    let during_prompt = mgr.active_span_context().unwrap();
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+    );
+    let after_prompt = mgr.active_span_context().unwrap();
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let prompt = spans
+        .iter()
+        .find(|s| s.name.starts_with("invoke_agent"))
+        .unwrap();
+    let root = spans.iter().find(|s| s.name == "acp_session").unwrap();
+    assert_eq!(during_prompt.span_id(), prompt.span_context.span_id());
+    assert_eq!(after_prompt.span_id(), root.span_context.span_id());
+}
+
+#[test]
+fn prompt_responses_report_a_completed_turn_once() {
+    let (mut mgr, _provider, _exporter) = manager(false);
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":1,"method":"session/prompt","params":{"sessionId":"s1","prompt":[]}}"#,
+    );
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","method":"session/update","params":{"sessionId":"s1","update":{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"hi"}}}}"#,
+    );
+    assert!(!mgr.take_turn_completed());
+    mgr.process_message(
+        Direction::AgentToEditor,
+        r#"{"jsonrpc":"2.0","id":1,"result":{"stopReason":"end_turn"}}"#,
+    );
+    assert!(mgr.take_turn_completed());
+    assert!(!mgr.take_turn_completed());
+}
+
+#[test]
+fn exit_signal_marks_the_root_span_as_failed() {
+    let (mut mgr, provider, exporter) = manager(false);
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+    );
+    mgr.record_exit_signal("SIGSEGV");
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let root = spans.iter().find(|s| s.name == "acp_session").unwrap();
+    assert_eq!(attr(root, "acp.agent.exit_signal"), Some("SIGSEGV".into()));
+    assert_eq!(root.status, Status::error("agent killed by SIGSEGV"));
+}
+
+#[test]
+fn root_span_records_the_agent_cwd() {
+    let (mgr, provider, exporter) = manager(false);
+    let mut mgr = mgr.with_agent_cwd("/work/repo".into());
+    mgr.process_message(
+        Direction::EditorToAgent,
+        r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":1}}"#,
+    );
+    mgr.shutdown();
+    let _ = provider.force_flush();
+
+    let spans = exporter.spans();
+    let root = spans.iter().find(|s| s.name == "acp_session").unwrap();
+    assert_eq!(attr(root, "acp.agent.cwd"), Some("/work/repo".into()));
+}
+
+#[test]
+fn summary_totals_prompts_tools_and_output() {
+    let (mut mgr, _provider, _exporter) = manager(false);
+    let update = |update: &str| {
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"session/update","params":{{"sessionId":"s1","update":{update}}}}}"#
+        )
+    };
+    let prompt = |id: u32| {
+        format!(
+            r#"{{"jsonrpc":"2.0","id":{id},"method":"session/prompt","params":{{"sessionId":"s1","prompt":[]}}}}"#
+        )
+    };
+    let response =
+        |id: u32| format!(r#"{{"jsonrpc":"2.0","id":{id},"result":{{"stopReason":"end_turn"}}}}"#);
+    let transcript = [
+        (Direction::EditorToAgent, prompt(1)),
+        (
+            Direction::AgentToEditor,
+            update(
+                r#"{"sessionUpdate":"agent_message_chunk","content":{"type":"text","text":"héllo"}}"#,
+            ),
+        ),
+        (
+            Direction::AgentToEditor,
+            update(
+                r#"{"sessionUpdate":"tool_call","toolCallId":"t1","title":"Read a.rs","kind":"read","status":"pending"}"#,
+            ),
+        ),
+        (
+            Direction::AgentToEditor,
+            update(
+                r#"{"sessionUpdate":"tool_call_update","toolCallId":"t1","status":"completed"}"#,
+            ),
+        ),
+        (
+            Direction::AgentToEditor,
+            update(
+                r#"{"sessionUpdate":"tool_call","toolCallId":"t2","title":"cargo test","kind":"execute","status":"pending"}"#,
+            ),
+        ),
+        (
+            Direction::AgentToEditor,
+            update(r#"{"sessionUpdate":"tool_call_update","toolCallId":"t2","status":"failed"}"#),
+        ),
+        (
+            Direction::AgentToEditor,
+            update(
+                r#"{"sessionUpdate":"tool_call_update","toolCallId":"t3","status":"in_progress"}"#,
+            ),
+        ),
+        (
+            Direction::AgentToEditor,
+            update(
+                r#"{"sessionUpdate":"tool_call_update","toolCallId":"t3","title":"grep TODO","status":"completed"}"#,
+            ),
+        ),
+        (Direction::AgentToEditor, response(1)),
+        // Unanswered: counted as a prompt, but not in the turn timings.
+        (Direction::EditorToAgent, prompt(2)),
+        (
+            Direction::AgentToEditor,
+            update(
+                r#"{"sessionUpdate":"tool_call","toolCallId":"t4","title":"Write b.rs","kind":"edit","status":"pending"}"#,
+            ),
+        ),
+    ];
+    for (direction, line) in transcript {
+        mgr.process_message(direction, &line);
+    }
+    mgr.shutdown();
+
+    let summary = mgr.summary();
+    assert_eq!(summary.prompts, 2);
+    assert_eq!(summary.turn_duration.unwrap().count, 1);
+    assert_eq!(summary.time_to_first_token.unwrap().count, 1);
+    assert_eq!(summary.output_chars, 5);
+    let counts = |name: &str| {
+        let counts = &summary.tool_calls[name];
+        (counts.calls, counts.failed)
+    };
+    assert_eq!(counts("Read a.rs"), (1, 0));
+    assert_eq!(counts("cargo test"), (1, 1));
+    // Started from an update, then named by a later one.
+    assert_eq!(counts("grep TODO"), (1, 0));
+    // Still open at shutdown, so it ends with an error like its span.
+    assert_eq!(counts("Write b.rs"), (1, 1));
+    assert_eq!(summary.agent_exit_code, None);
+}
+
+#[test]
+fn protocol_version_negotiation_is_recorded_and_mismatches_flagged() {
+    let handshake = |negotiated: i64| {
+        let (mut mgr, provider, exporter) = manager(false);
+        mgr.process_message(
+            Direction::EditorToAgent,
+            r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":2}}"#,
+        );
+        mgr.process_message(
+            Direction::AgentToEditor,
+            &format!(r#"{{"jsonrpc":"2.0","id":0,"result":{{"protocolVersion":{negotiated}}}}}"#),
+        );
+        mgr.shutdown();
+        let _ = provider.force_flush();
+        exporter.spans()
+    };
+
+    let spans = handshake(1);
+    for name in ["initialize", "acp_session"] {
+        let span = spans.iter().find(|s| s.name == name).unwrap();
+        assert_eq!(
+            attr(span, "acp.protocol.version.requested"),
+            Some(2i64.into()),
+            "{name}"
+        );
+        assert_eq!(
+            attr(span, "acp.protocol.version"),
+            Some(1i64.into()),
+            "{name}"
+        );
+    }
+    let init = spans.iter().find(|s| s.name == "initialize").unwrap();
+    let mismatch = init
+        .events
+        .iter()
+        .find(|e| e.name == "acp.protocol.version_mismatch")
+        .unwrap();
+    assert!(mismatch.attributes.contains(&opentelemetry::KeyValue::new(
+        "acp.protocol.version.requested",
+        2i64
+    )));
+
+    let spans = handshake(2);
+    let init = spans.iter().find(|s| s.name == "initialize").unwrap();
+    assert!(init.events.is_empty());
+}